@@ -7,7 +7,7 @@ use mongodb::{Client, Database, bson::{self, Document, to_document, doc}, Collec
 use serde::{Deserialize, Serialize};
 mod types;
 mod routes;
-use routes::{post_routes, user_routes};
+use routes::{post_routes, user_routes, revision_routes, comment_routes, moderation_routes, notification_routes, realtime_routes, email_routes, bookmark_routes, history_routes, series_routes, contributor_routes, review_routes, admin_routes, analytics_routes, post_analytics_routes};
 mod utils;
 use types::{Common,Permission,Post,Tag,User};
 use futures_util::future::FutureExt;
@@ -19,6 +19,7 @@ use futures_util::future::{Future, Ready};
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    env_logger::init();
 
     // Get secret key from environment variable
     let mongodb_uri = env::var("MONGODB_URI")
@@ -41,6 +42,27 @@ async fn main() -> std::io::Result<()> {
         let doc = to_document(&common).unwrap();
         let _ = coll.insert_one(doc, None).await;
     }
+
+    utils::run_migrations(&db).await.expect("database migration failed");
+    utils::load_capabilities();
+
+    // `promote-admin <email>` makes a user an admin and exits, for the first admin or when every admin is locked out
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("promote-admin") {
+        let email = args.get(2).expect("usage: promote-admin <email>");
+        match utils::promote_admin(&db, email, "cli").await.expect("failed to promote admin") {
+            Ok(_) => println!("{} is now an admin", email),
+            Err(reason) => println!("could not promote {}: {}", email, reason),
+        }
+        return Ok(());
+    }
+    utils::bootstrap_admin(&db).await.expect("failed to bootstrap admin");
+    let broker = Data::new(utils::Broker::new());
+    utils::spawn_publish_scheduler(db.clone(), broker.clone());
+    utils::spawn_mail_worker(db.clone(), utils::Mailer::from_env());
+    utils::spawn_digest_scheduler(db.clone());
+    let content_filters = Data::new(utils::ContentFilterPipeline::from_env());
+    let rate_limiter = Data::new(utils::RateLimiter::from_env(&db));
     fn jwt_middleware(headers:HeaderMap){
         println!("hello from jwt_middleware");
 
@@ -78,8 +100,25 @@ async fn main() -> std::io::Result<()> {
             })
             .wrap(cors)   
             .app_data(Data::new(db.clone()))
+            .app_data(content_filters.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(broker.clone())
             .configure(post_routes)
             .configure(user_routes)
+            .configure(revision_routes)
+            .configure(comment_routes)
+            .configure(moderation_routes)
+            .configure(notification_routes)
+            .configure(realtime_routes)
+            .configure(email_routes)
+            .configure(bookmark_routes)
+            .configure(history_routes)
+            .configure(series_routes)
+            .configure(contributor_routes)
+            .configure(review_routes)
+            .configure(admin_routes)
+            .configure(analytics_routes)
+            .configure(post_analytics_routes)
     })
 
     .bind("127.0.0.1:443")?
@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId, Document}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{Capability, Permission, RoleChange, User};
+use crate::utils::{ban_user, change_permission, effective_capabilities, unban_user, AuthUser};
+
+// The target of an admin action, never the admin themself
+async fn find_target(db: &Database, auth: &AuthUser, user_id: &str) -> Result<User, HttpResponse> {
+    let id = match ObjectId::from_str(user_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid user ID"}))),
+    };
+    if id == auth.id {
+        return Err(HttpResponse::BadRequest().json(json!({"error":"You can't change your own role"})));
+    }
+    match db.collection::<User>("users").find_one(doc! {"_id": id}, None).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error":"User not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)}))),
+    }
+}
+
+fn changed(change: mongodb::error::Result<Option<RoleChange>>) -> HttpResponse {
+    match change {
+        Ok(Some(change)) => HttpResponse::Ok().json(change),
+        Ok(None) => HttpResponse::Conflict().json(json!({"error":"User's permission changed meanwhile, try again"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update user: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleRequest {
+    permission: Permission,
+    reason: Option<String>,
+}
+
+// Promotes or demotes a user, bans go through /admin/users/{id}/ban
+async fn change_role(user_id: web::Path<String>, auth: AuthUser, request: web::Json<RoleRequest>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::UserRoles).await {
+        return response;
+    }
+    let user = match find_target(&db, &auth, &user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if request.permission == Permission::Banned {
+        return HttpResponse::BadRequest().json(json!({"error":"Use /admin/users/{id}/ban to ban a user"}));
+    }
+    if user.permission == Permission::Banned {
+        return HttpResponse::BadRequest().json(json!({"error":"Unban the user first"}));
+    }
+    if user.permission == request.permission {
+        return HttpResponse::BadRequest().json(json!({"error":"User already has this role"}));
+    }
+    changed(change_permission(&db, &user, request.permission.clone(), &auth.id_str(), request.reason.clone(), None).await)
+}
+
+#[derive(Deserialize)]
+struct BanRequest {
+    reason: String,
+    days: Option<u32>, // a permanent ban when missing
+}
+
+async fn ban(user_id: web::Path<String>, auth: AuthUser, request: web::Json<BanRequest>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::UserBan).await {
+        return response;
+    }
+    let user = match find_target(&db, &auth, &user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error":"A ban needs a reason"}));
+    }
+    if request.days == Some(0) {
+        return HttpResponse::BadRequest().json(json!({"error":"A temporary ban lasts at least a day"}));
+    }
+    if user.permission == Permission::Admin {
+        return HttpResponse::BadRequest().json(json!({"error":"Demote the admin before banning them"}));
+    }
+    if user.permission == Permission::Banned {
+        return HttpResponse::Conflict().json(json!({"error":"User is already banned"}));
+    }
+    let expires_at = request.days.map(|days| Utc::now() + Duration::days(days as i64));
+    changed(ban_user(&db, &user, &auth.id_str(), reason.to_string(), expires_at).await)
+}
+
+#[derive(Deserialize)]
+struct UnbanRequest {
+    reason: Option<String>,
+}
+
+async fn unban(user_id: web::Path<String>, auth: AuthUser, request: web::Json<UnbanRequest>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::UserBan).await {
+        return response;
+    }
+    let user = match find_target(&db, &auth, &user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if user.permission != Permission::Banned {
+        return HttpResponse::BadRequest().json(json!({"error":"User is not banned"}));
+    }
+    changed(unban_user(&db, &user, &auth.id_str(), request.reason.clone()).await)
+}
+
+#[derive(Deserialize)]
+struct CapabilitiesRequest {
+    granted: Vec<Capability>,
+    revoked: Vec<Capability>,
+}
+
+// Replaces the user's own grants and revokes, their role's capabilities stay as configured
+async fn set_capabilities(user_id: web::Path<String>, auth: AuthUser, request: web::Json<CapabilitiesRequest>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::UserRoles).await {
+        return response;
+    }
+    let user = match find_target(&db, &auth, &user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if request.granted.iter().any(|capability| request.revoked.contains(capability)) {
+        return HttpResponse::BadRequest().json(json!({"error":"A capability can't be granted and revoked at once"}));
+    }
+    let update = doc! {"$set": {
+        "granted_capabilities": bson::to_bson(&request.granted).unwrap(),
+        "revoked_capabilities": bson::to_bson(&request.revoked).unwrap(),
+        "updated_at": Utc::now().timestamp(),
+    }};
+    match db.collection::<User>("users").update_one(doc! {"_id": user.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"capabilities": effective_capabilities(&User { granted_capabilities: request.granted.clone(), revoked_capabilities: request.revoked.clone(), ..user })})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update user: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleChangesQuery {
+    user_id: Option<String>,
+    page: Option<u64>,
+}
+
+// History of role changes and bans, newest first
+async fn fetch_role_changes(auth: AuthUser, query: web::Query<RoleChangesQuery>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::UserRoles).await {
+        return response;
+    }
+    let mut filter = Document::new();
+    if let Some(user_id) = &query.user_id {
+        filter.insert("user_id", user_id);
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder().sort(doc! {"created_at": -1}).skip((page - 1) * 50).limit(50).build();
+
+    match db.collection::<RoleChange>("role_changes").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<RoleChange>>().await {
+            Ok(changes) => HttpResponse::Ok().json(changes),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch role changes: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch role changes: {}", e)})),
+    }
+}
+
+pub fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/admin/users/{id}/role")
+            .route(web::post().to(change_role))
+    )
+    .service(
+        web::resource("/admin/users/{id}/ban")
+            .route(web::post().to(ban))
+    )
+    .service(
+        web::resource("/admin/users/{id}/unban")
+            .route(web::post().to(unban))
+    )
+    .service(
+        web::resource("/admin/users/{id}/capabilities")
+            .route(web::post().to(set_capabilities))
+    )
+    .service(
+        web::resource("/admin/role-changes")
+            .route(web::get().to(fetch_role_changes))
+    );
+}
@@ -0,0 +1,294 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{Duration, TimeZone, Utc};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, Bson, Document}};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::Capability;
+use crate::utils::AuthUser;
+
+#[derive(Deserialize)]
+pub(crate) struct RangeQuery {
+    from: Option<i64>, // unix seconds, 30 days ago by default
+    to: Option<i64>, // unix seconds, now by default
+    limit: Option<i64>, // of the top lists, 10 by default
+    format: Option<String>, // "csv" for a download instead of json
+}
+
+pub(crate) struct Range {
+    pub from: i64,
+    pub to: i64,
+    pub limit: i64,
+}
+
+impl RangeQuery {
+    pub(crate) fn range(&self) -> Result<Range, HttpResponse> {
+        let to = self.to.unwrap_or_else(|| Utc::now().timestamp());
+        let from = self.from.unwrap_or(to - Duration::days(30).num_seconds());
+        if from >= to {
+            return Err(HttpResponse::BadRequest().json(json!({"error":"from must be before to"})));
+        }
+        if to - from > Duration::days(366).num_seconds() {
+            return Err(HttpResponse::BadRequest().json(json!({"error":"The range can be at most a year"})));
+        }
+        Ok(Range { from, to, limit: self.limit.unwrap_or(10).clamp(1, 100) })
+    }
+}
+
+impl Range {
+    fn filter(&self) -> Document {
+        doc! {"$gte": self.from, "$lt": self.to}
+    }
+
+    // Every UTC day the range touches, like "2023-05-17"
+    pub(crate) fn days(&self) -> Vec<String> {
+        let (Some(from), Some(to)) = (Utc.timestamp_opt(self.from, 0).single(), Utc.timestamp_opt(self.to - 1, 0).single()) else {
+            return vec![];
+        };
+        from.date_naive().iter_days().take_while(|day| *day <= to.date_naive()).map(|day| day.format("%Y-%m-%d").to_string()).collect()
+    }
+}
+
+// Dates are stored as unix seconds, buckets are UTC days like "2023-05-17"
+fn day_of(field: &str) -> Document {
+    doc! {"$dateToString": {"format": "%Y-%m-%d", "date": {"$toDate": {"$multiply": [format!("${}", field), 1000]}}}}
+}
+
+fn per_day(matching: Document, field: &str) -> Vec<Document> {
+    vec![
+        doc! {"$match": matching},
+        doc! {"$group": {"_id": day_of(field), "count": {"$sum": 1}}},
+        doc! {"$sort": {"_id": 1}},
+        doc! {"$project": {"_id": 0, "date": "$_id", "count": 1}},
+    ]
+}
+
+// The user behind a string `field`, for names in the top lists
+fn with_user_name(field: &str) -> Vec<Document> {
+    vec![
+        doc! {"$addFields": {"user_oid": {"$convert": {"input": format!("${}", field), "to": "objectId", "onError": null, "onNull": null}}}},
+        doc! {"$lookup": {"from": "users", "localField": "user_oid", "foreignField": "_id", "as": "user"}},
+        doc! {"$addFields": {"name": {"$ifNull": [{"$first": "$user.name"}, null]}}},
+        doc! {"$project": {"user_oid": 0, "user": 0}},
+    ]
+}
+
+// The pipeline, collection and csv columns of every metric
+fn metric(name: &str, range: &Range) -> Option<(&'static str, Vec<Document>, &'static [&'static str])> {
+    let published = doc! {"status": "Public", "published_at": range.filter()};
+    match name {
+        "signups" => Some(("users", per_day(doc! {"created_at": range.filter()}, "created_at"), &["date", "count"])),
+        "posts" => Some(("posts", per_day(doc! {"created_at": range.filter(), "status": {"$ne": "Deleted"}}, "created_at"), &["date", "count"])),
+        "comments" => Some(("comments", per_day(doc! {"created_at": range.filter(), "deleted": false}, "created_at"), &["date", "count"])),
+        "registrations" => Some((
+            "users",
+            vec![
+                doc! {"$match": {"created_at": range.filter()}},
+                doc! {"$group": {"_id": "$registred_via", "count": {"$sum": 1}}},
+                doc! {"$sort": {"count": -1}},
+                doc! {"$project": {"_id": 0, "registered_via": "$_id", "count": 1}},
+            ],
+            &["registered_via", "count"],
+        )),
+        "top-authors" => {
+            let mut pipeline = vec![
+                doc! {"$match": published},
+                doc! {"$group": {
+                    "_id": "$author",
+                    "posts": {"$sum": 1},
+                    "views": {"$sum": "$views"},
+                    "likes": {"$sum": {"$size": {"$ifNull": ["$likes", []]}}},
+                }},
+                doc! {"$sort": {"views": -1, "posts": -1}},
+                doc! {"$limit": range.limit},
+                doc! {"$project": {"_id": 0, "author": "$_id", "posts": 1, "views": 1, "likes": 1}},
+            ];
+            pipeline.extend(with_user_name("author"));
+            Some(("posts", pipeline, &["author", "name", "posts", "views", "likes"]))
+        }
+        "top-tags" => Some((
+            "posts",
+            vec![
+                doc! {"$match": published},
+                doc! {"$unwind": "$tags"},
+                doc! {"$group": {"_id": "$tags", "posts": {"$sum": 1}, "views": {"$sum": "$views"}}},
+                doc! {"$sort": {"posts": -1, "views": -1}},
+                doc! {"$limit": range.limit},
+                doc! {"$project": {"_id": 0, "tag": "$_id", "posts": 1, "views": 1}},
+            ],
+            &["tag", "posts", "views"],
+        )),
+        "top-posts" => {
+            let mut pipeline = vec![
+                doc! {"$match": published},
+                doc! {"$sort": {"views": -1}},
+                doc! {"$limit": range.limit},
+                doc! {"$project": {
+                    "_id": 0,
+                    "post_id": {"$toString": "$_id"},
+                    "title": 1,
+                    "author": 1,
+                    "views": 1,
+                    "likes": {"$size": {"$ifNull": ["$likes", []]}},
+                    "comment_count": {"$ifNull": ["$comment_count", 0]},
+                    "published_at": 1,
+                }},
+            ];
+            pipeline.extend(with_user_name("author"));
+            Some(("posts", pipeline, &["post_id", "title", "author", "name", "views", "likes", "comment_count", "published_at"]))
+        }
+        // Someone who wrote, commented or read on a day, counted once per day.
+        // Readers who paused their history are not counted.
+        "active-users" => Some((
+            "comments",
+            vec![
+                doc! {"$match": {"created_at": range.filter(), "author_id": {"$ne": "anon"}}},
+                doc! {"$project": {"_id": 0, "user": "$author_id", "date": day_of("created_at")}},
+                doc! {"$unionWith": {"coll": "posts", "pipeline": [
+                    {"$match": {"created_at": range.filter()}},
+                    {"$project": {"_id": 0, "user": "$author", "date": day_of("created_at")}},
+                ]}},
+                doc! {"$unionWith": {"coll": "reading_days", "pipeline": [
+                    {"$match": {"date": {"$in": range.days()}}},
+                    {"$project": {"_id": 0, "user": "$user_id", "date": 1}},
+                ]}},
+                doc! {"$group": {"_id": {"date": "$date", "user": "$user"}}},
+                doc! {"$group": {"_id": "$_id.date", "count": {"$sum": 1}}},
+                doc! {"$sort": {"_id": 1}},
+                doc! {"$project": {"_id": 0, "date": "$_id", "count": 1}},
+            ],
+            &["date", "count"],
+        )),
+        _ => None,
+    }
+}
+
+const METRICS: [&str; 8] = ["signups", "posts", "comments", "registrations", "top-authors", "top-tags", "top-posts", "active-users"];
+
+async fn run_metric(db: &Database, collection: &str, pipeline: Vec<Document>) -> Result<Vec<Document>, HttpResponse> {
+    match db.collection::<Document>(collection).aggregate(pipeline, None).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Document>>().await {
+            Ok(rows) => Ok(rows),
+            Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to compute metric: {}", e)}))),
+        },
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to compute metric: {}", e)}))),
+    }
+}
+
+fn csv_field(value: Option<&Bson>) -> String {
+    let text = match value {
+        None | Some(Bson::Null) => String::new(),
+        Some(Bson::String(text)) => text.clone(),
+        Some(Bson::Int32(number)) => number.to_string(),
+        Some(Bson::Int64(number)) => number.to_string(),
+        Some(Bson::Double(number)) => number.to_string(),
+        Some(other) => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn to_csv(columns: &[&str], rows: &[Document]) -> String {
+    let mut csv = columns.join(",");
+    csv.push('\n');
+    for row in rows {
+        let fields: Vec<String> = columns.iter().map(|column| csv_field(row.get(column))).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+// One metric over the range, as json or csv
+async fn fetch_metric(name: web::Path<String>, auth: AuthUser, query: web::Query<RangeQuery>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::AnalyticsView).await {
+        return response;
+    }
+    let range = match query.range() {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+    let (collection, pipeline, columns) = match metric(&name, &range) {
+        Some(metric) => metric,
+        None => return HttpResponse::NotFound().json(json!({"error":"Unknown metric", "metrics": METRICS})),
+    };
+    let rows = match run_metric(&db, collection, pipeline).await {
+        Ok(rows) => rows,
+        Err(response) => return response,
+    };
+
+    if query.format.as_deref() == Some("csv") {
+        return HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}-{}-{}.csv\"", name, range.from, range.to)))
+            .body(to_csv(columns, &rows));
+    }
+    HttpResponse::Ok().json(json!({"metric": name.as_str(), "from": range.from, "to": range.to, "rows": rows}))
+}
+
+// Site totals, also written to the `common` document so it stays current
+async fn fetch_overview(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::AnalyticsView).await {
+        return response;
+    }
+    let totals = vec![doc! {"$facet": {
+        "posts": [{"$match": {"status": "Public"}}, {"$group": {"_id": null, "count": {"$sum": 1}, "views": {"$sum": "$views"}}}],
+        "tags": [{"$match": {"status": "Public"}}, {"$unwind": "$tags"}, {"$group": {"_id": "$tags"}}, {"$count": "count"}],
+    }}];
+    let posts = match run_metric(&db, "posts", totals).await {
+        Ok(mut rows) => rows.pop().unwrap_or_default(),
+        Err(response) => return response,
+    };
+    let first = |facet: &str, field: &str| -> i64 {
+        posts
+            .get_array(facet)
+            .ok()
+            .and_then(|rows| rows.first())
+            .and_then(|row| row.as_document())
+            .and_then(|row| row.get(field))
+            .and_then(|value| match value {
+                Bson::Int32(number) => Some(*number as i64),
+                Bson::Int64(number) => Some(*number),
+                Bson::Double(number) => Some(*number as i64),
+                _ => None,
+            })
+            .unwrap_or(0)
+    };
+    let (post_count, total_view, tag_count) = (first("posts", "count"), first("posts", "views"), first("tags", "count"));
+    let user_count = match db.collection::<Document>("users").count_documents(doc! {}, None).await {
+        Ok(count) => count as i64,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to count users: {}", e)})),
+    };
+    let comment_count = match db.collection::<Document>("comments").count_documents(doc! {"deleted": false}, None).await {
+        Ok(count) => count as i64,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to count comments: {}", e)})),
+    };
+
+    let update = doc! {"$set": {"user_count": user_count, "post_count": post_count, "tag_count": tag_count, "total_view": total_view}};
+    if let Err(e) = db.collection::<Document>("common").update_one(doc! {}, update, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update totals: {}", e)}));
+    }
+    HttpResponse::Ok().json(json!({
+        "user_count": user_count,
+        "post_count": post_count,
+        "comment_count": comment_count,
+        "tag_count": tag_count,
+        "total_view": total_view,
+        "metrics": METRICS,
+    }))
+}
+
+pub fn analytics_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/admin/analytics")
+            .route(web::get().to(fetch_overview))
+    )
+    .service(
+        web::resource("/admin/analytics/{metric}")
+            .route(web::get().to(fetch_metric))
+    );
+}
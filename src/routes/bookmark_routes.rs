@@ -0,0 +1,350 @@
+use std::{collections::HashMap, str::FromStr};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, oid::ObjectId}, options::{FindOneOptions, FindOptions}};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{AuthorProfile, Bookmark, BookmarkSummary, BookmarkView, Post, PostStatus, PostSummary, FAVORITES};
+use crate::utils::{is_duplicate, AuthUser};
+
+fn check_name(name: &str) -> Result<String, HttpResponse> {
+    let name = name.trim();
+    match name.chars().count() {
+        1..=60 => Ok(name.to_string()),
+        _ => Err(HttpResponse::BadRequest().json(json!({"error":"Collection name must be 1-60 characters"}))),
+    }
+}
+
+// The collection, if it belongs to the signed in user
+async fn owned_bookmark(db: &Database, bookmark_id: &str, auth: &AuthUser) -> Result<Bookmark, HttpResponse> {
+    let bookmark_id = match ObjectId::from_str(bookmark_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid collection ID"}))),
+    };
+    match db.collection::<Bookmark>("bookmarks").find_one(doc! {"_id": bookmark_id, "user_id": auth.id_str()}, None).await {
+        Ok(Some(bookmark)) => Ok(bookmark),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error":"Collection not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch collection: {}", e)}))),
+    }
+}
+
+// Summaries of the posts in the collection's order, `viewer` also sees their own posts that aren't public
+async fn post_summaries(db: &Database, post_ids: &[String], viewer: Option<&str>) -> Result<Vec<PostSummary>, HttpResponse> {
+    let ids: Vec<ObjectId> = post_ids.iter().filter_map(|id| ObjectId::from_str(id).ok()).collect();
+    let mut filter = doc! {"_id": {"$in": ids}};
+    match viewer {
+        Some(viewer) => filter.insert("$or", vec![doc! {"status": "Public"}, doc! {"author": viewer, "status": {"$ne": "Deleted"}}]),
+        None => filter.insert("status", "Public"),
+    };
+    let options = FindOptions::builder().projection(PostSummary::projection()).build();
+    let mut posts: HashMap<String, PostSummary> = match db.collection::<PostSummary>("posts").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<PostSummary>>().await {
+            Ok(posts) => posts.into_iter().map(|post| (post.id.to_hex(), post)).collect(),
+            Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)}))),
+        },
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)}))),
+    };
+    Ok(post_ids.iter().filter_map(|id| posts.remove(id)).collect())
+}
+
+// The signed in user's collections, in their order
+async fn fetch_bookmarks(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let options = FindOptions::builder().sort(doc! {"position": 1, "created_at": 1}).build();
+    match db.collection::<Bookmark>("bookmarks").find(doc! {"user_id": auth.id_str()}, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Bookmark>>().await {
+            Ok(bookmarks) => HttpResponse::Ok().json(bookmarks.iter().map(BookmarkSummary::from).collect::<Vec<_>>()),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch collections: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch collections: {}", e)})),
+    }
+}
+
+async fn fetch_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    match post_summaries(&db, &bookmark.posts, Some(&auth.id_str())).await {
+        Ok(posts) => HttpResponse::Ok().json(BookmarkView { summary: BookmarkSummary::from(&bookmark), owner: None, posts }),
+        Err(response) => response,
+    }
+}
+
+// A shared collection, for anyone with the link
+async fn fetch_shared_bookmark(token: web::Path<String>, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match db.collection::<Bookmark>("bookmarks").find_one(doc! {"share_token": token.as_str()}, None).await {
+        Ok(Some(bookmark)) => bookmark,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error":"Collection not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch collection: {}", e)})),
+    };
+    let options = FindOneOptions::builder().projection(AuthorProfile::projection()).build();
+    let owner = match ObjectId::from_str(&bookmark.user_id) {
+        Ok(id) => match db.collection::<AuthorProfile>("users").find_one(doc! {"_id": id}, options).await {
+            Ok(owner) => owner,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)})),
+        },
+        Err(_) => None,
+    };
+    match post_summaries(&db, &bookmark.posts, None).await {
+        Ok(posts) => {
+            let mut summary = BookmarkSummary::from(&bookmark);
+            summary.share_token = None;
+            HttpResponse::Ok().json(BookmarkView { summary, owner, posts })
+        }
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct NameRequest {
+    name: String,
+}
+
+async fn create_bookmark(auth: AuthUser, request: web::Json<NameRequest>, db: web::Data<Database>) -> impl Responder {
+    let name = match check_name(&request.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+    let collection = db.collection::<Bookmark>("bookmarks");
+    let position = match collection.count_documents(doc! {"user_id": auth.id_str()}, None).await {
+        Ok(count) => count as u32,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to count collections: {}", e)})),
+    };
+
+    let bookmark = Bookmark::new(auth.id_str(), name, position);
+    match collection.insert_one(&bookmark, None).await {
+        Ok(_) => HttpResponse::Ok().json(BookmarkSummary::from(&bookmark)),
+        Err(e) if is_duplicate(&e) => HttpResponse::Conflict().json(json!({"error":"You already have a collection with this name"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to create collection: {}", e)})),
+    }
+}
+
+async fn rename_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, request: web::Json<NameRequest>, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    let name = match check_name(&request.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+    let update = doc! {"$set": {"name": &name, "updated_at": Utc::now().timestamp()}};
+    match db.collection::<Bookmark>("bookmarks").update_one(doc! {"_id": bookmark.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Collection renamed", "name": name})),
+        Err(e) if is_duplicate(&e) => HttpResponse::Conflict().json(json!({"error":"You already have a collection with this name"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to rename collection: {}", e)})),
+    }
+}
+
+async fn delete_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    match db.collection::<Bookmark>("bookmarks").delete_one(doc! {"_id": bookmark.id}, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Collection deleted"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to delete collection: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct PostRequest {
+    post_id: String,
+}
+
+// Adds the post to the end of the collection
+// The id of a post the user can bookmark: a public one, or one of their own that isn't deleted
+pub(crate) async fn bookmarkable_post(db: &Database, post_id: &str, auth: &AuthUser) -> Result<String, HttpResponse> {
+    let post_id = match ObjectId::from_str(post_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"}))),
+    };
+    match db.collection::<Post>("posts").find_one(doc! {"_id": post_id}, None).await {
+        Ok(Some(post)) if post.status == PostStatus::Public || (post.author == auth.id_str() && post.status != PostStatus::Deleted) => Ok(post_id.to_hex()),
+        Ok(_) => Err(HttpResponse::NotFound().json(json!({"error":"Post not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)}))),
+    }
+}
+
+async fn add_to_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, request: web::Json<PostRequest>, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    let post_id = match bookmarkable_post(&db, &request.post_id, &auth).await {
+        Ok(post_id) => post_id,
+        Err(response) => return response,
+    };
+    let update = doc! {"$push": {"posts": &post_id}, "$set": {"updated_at": Utc::now().timestamp()}};
+    match db.collection::<Bookmark>("bookmarks").update_one(doc! {"_id": bookmark.id, "posts": {"$ne": &post_id}}, update, None).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::Ok().json(json!({"success":"Post is already in the collection"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Post added to the collection"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update collection: {}", e)})),
+    }
+}
+
+async fn remove_from_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, request: web::Json<PostRequest>, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    let update = doc! {"$pull": {"posts": &request.post_id}, "$set": {"updated_at": Utc::now().timestamp()}};
+    match db.collection::<Bookmark>("bookmarks").update_one(doc! {"_id": bookmark.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Post removed from the collection"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update collection: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReorderPostsRequest {
+    posts: Vec<String>, // every post of the collection, in the new order
+}
+
+async fn reorder_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, request: web::Json<ReorderPostsRequest>, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    let mut current = bookmark.posts.clone();
+    let mut requested = request.posts.clone();
+    current.sort();
+    requested.sort();
+    if current != requested {
+        return HttpResponse::BadRequest().json(json!({"error":"The new order must contain every post of the collection once"}));
+    }
+
+    // Only applies to the list it was made for, a post added meanwhile isn't lost
+    let filter = doc! {"_id": bookmark.id, "posts": &bookmark.posts};
+    let update = doc! {"$set": {"posts": &request.posts, "updated_at": Utc::now().timestamp()}};
+    match db.collection::<Bookmark>("bookmarks").update_one(filter, update, None).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::Conflict().json(json!({"error":"The collection has changed, fetch it again"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Collection reordered"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update collection: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReorderCollectionsRequest {
+    collections: Vec<String>, // every collection of the user, in the new order
+}
+
+async fn reorder_bookmarks(auth: AuthUser, request: web::Json<ReorderCollectionsRequest>, db: web::Data<Database>) -> impl Responder {
+    let collection = db.collection::<Bookmark>("bookmarks");
+    let mut current: Vec<String> = match collection.find(doc! {"user_id": auth.id_str()}, None).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Bookmark>>().await {
+            Ok(bookmarks) => bookmarks.iter().map(|bookmark| bookmark.id.to_hex()).collect(),
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch collections: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch collections: {}", e)})),
+    };
+    let mut requested = request.collections.clone();
+    current.sort();
+    requested.sort();
+    if current != requested {
+        return HttpResponse::BadRequest().json(json!({"error":"The new order must contain every collection once"}));
+    }
+
+    for (position, bookmark_id) in request.collections.iter().enumerate() {
+        let bookmark_id = ObjectId::from_str(bookmark_id).unwrap();
+        if let Err(e) = collection.update_one(doc! {"_id": bookmark_id}, doc! {"$set": {"position": position as u32}}, None).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update collection: {}", e)}));
+        }
+    }
+    HttpResponse::Ok().json(json!({"success":"Collections reordered"}))
+}
+
+#[derive(Deserialize)]
+struct ShareRequest {
+    shared: bool,
+}
+
+// Turns the share link on, keeping the existing one, or off
+async fn share_bookmark(bookmark_id: web::Path<String>, auth: AuthUser, request: web::Json<ShareRequest>, db: web::Data<Database>) -> impl Responder {
+    let bookmark = match owned_bookmark(&db, &bookmark_id, &auth).await {
+        Ok(bookmark) => bookmark,
+        Err(response) => return response,
+    };
+    let share_token = match (request.shared, bookmark.share_token) {
+        (true, Some(token)) => return HttpResponse::Ok().json(json!({"success":"Collection is shared", "share_token": token})),
+        (false, _) => None,
+        (true, None) => {
+            let mut random = [0u8; 16];
+            SystemRandom::new().fill(&mut random).expect("failed to generate random bytes");
+            Some(random.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+        }
+    };
+
+    let update = doc! {"$set": {"share_token": &share_token, "updated_at": Utc::now().timestamp()}};
+    match db.collection::<Bookmark>("bookmarks").update_one(doc! {"_id": bookmark.id}, update, None).await {
+        Ok(_) if share_token.is_some() => HttpResponse::Ok().json(json!({"success":"Collection is shared", "share_token": share_token})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Collection is private"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update collection: {}", e)})),
+    }
+}
+
+// The collection `/user/addbookmark` toggles posts in, created on first use
+pub(crate) async fn favorites(db: &Database, user_id: &str) -> mongodb::error::Result<Bookmark> {
+    let collection = db.collection::<Bookmark>("bookmarks");
+    if let Some(bookmark) = collection.find_one(doc! {"user_id": user_id, "name": FAVORITES}, None).await? {
+        return Ok(bookmark);
+    }
+    let position = collection.count_documents(doc! {"user_id": user_id}, None).await? as u32;
+    let bookmark = Bookmark::new(user_id.to_string(), FAVORITES.to_string(), position);
+    match collection.insert_one(&bookmark, None).await {
+        Ok(_) => Ok(bookmark),
+        // Created by a request running at the same time
+        Err(e) if is_duplicate(&e) => Ok(collection.find_one(doc! {"user_id": user_id, "name": FAVORITES}, None).await?.unwrap_or(bookmark)),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn bookmark_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/bookmarks")
+            .route(web::get().to(fetch_bookmarks))
+    )
+    .service(
+        web::resource("/bookmarks/create")
+            .route(web::post().to(create_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/reorder")
+            .route(web::post().to(reorder_bookmarks))
+    )
+    .service(
+        web::resource("/bookmarks/shared/{token}")
+            .route(web::get().to(fetch_shared_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}")
+            .route(web::get().to(fetch_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}/rename")
+            .route(web::post().to(rename_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}/delete")
+            .route(web::post().to(delete_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}/add")
+            .route(web::post().to(add_to_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}/remove")
+            .route(web::post().to(remove_from_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}/reorder")
+            .route(web::post().to(reorder_bookmark))
+    )
+    .service(
+        web::resource("/bookmarks/{id}/share")
+            .route(web::post().to(share_bookmark))
+    );
+}
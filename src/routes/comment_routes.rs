@@ -0,0 +1,1013 @@
+use std::{env, str::FromStr, collections::HashMap, future::{ready, Ready}};
+
+use actix_web::{dev::Payload, error::InternalError, web, FromRequest, HttpRequest, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId, Document}, options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument}};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use chrono::Utc;
+
+use crate::types::{AuthorProfile, Comment, CommentStatus, CommentView, Capability, ModerationAction, NotificationKind, Post, PostStatus, ReportTarget, DELETED_COMMENT};
+use super::moderation_routes::log_moderation;
+use crate::utils::{client_ip_hash, has_capability, issue_challenge, notify, notify_new_comment, record_post_event, train_spam_model, verify_challenge, AuthUser, Broker, ContentFilterPipeline, OptionalAuthUser, PostEvent, RateLimit, Submission, Verdict};
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum CommentSort {
+    #[default]
+    Time, // oldest first, the way a conversation is read
+    Likes,
+}
+
+impl CommentSort {
+    fn sort(&self) -> Document {
+        match self {
+            CommentSort::Time => doc! {"created_at": 1, "_id": 1},
+            CommentSort::Likes => doc! {"like_count": -1, "created_at": 1, "_id": 1},
+        }
+    }
+
+    // Opaque position of a comment in this order, "<likes>_<created_at>_<id>"
+    fn cursor(&self, comment: &Comment) -> String {
+        format!("{}_{}_{}", comment.like_count, comment.created_at.timestamp(), comment.id.to_hex())
+    }
+
+    // Filter for the comments that come after `cursor`
+    fn after(&self, cursor: &str) -> Option<Document> {
+        let mut parts = cursor.split('_');
+        let like_count: u32 = parts.next()?.parse().ok()?;
+        let created_at: i64 = parts.next()?.parse().ok()?;
+        let id = ObjectId::from_str(parts.next()?).ok()?;
+
+        let later = vec![
+            doc! {"created_at": {"$gt": created_at}},
+            doc! {"created_at": created_at, "_id": {"$gt": id}},
+        ];
+        Some(match self {
+            CommentSort::Time => doc! {"$or": later},
+            CommentSort::Likes => doc! {"$or": [
+                {"like_count": {"$lt": like_count}},
+                {"like_count": like_count, "$or": later},
+            ]},
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    page: Option<u64>,
+    page_size: Option<u64>,
+    sort: Option<CommentSort>,
+}
+
+impl PageQuery {
+    fn options(&self) -> FindOptions {
+        let page_size = self.page_size.unwrap_or(20).clamp(1, 100);
+        let page = self.page.unwrap_or(1).max(1);
+        FindOptions::builder()
+            .sort(self.sort.unwrap_or_default().sort())
+            .skip((page - 1) * page_size)
+            .limit(page_size as i64)
+            .build()
+    }
+}
+
+// Replies can't be nested deeper than COMMENT_MAX_DEPTH (default 8)
+fn max_comment_depth() -> u32 {
+    env::var("COMMENT_MAX_DEPTH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(8)
+}
+
+async fn find_comments(db: &Database, filter: Document, options: FindOptions) -> Result<Vec<Comment>, HttpResponse> {
+    let cursor = match db.collection::<Comment>("comments").find(filter, options).await {
+        Ok(cursor) => cursor,
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comments: {}", e)}))),
+    };
+    cursor
+        .try_collect()
+        .await
+        .map_err(|e| HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comments: {}", e)})))
+}
+
+// Pending, hidden and quarantined comments are left out of every public listing
+fn visible() -> Document {
+    doc! {"status": {"$nin": ["Pending", "Hidden", "Quarantined"]}}
+}
+
+fn check_length(content: &str) -> Result<(), HttpResponse> {
+    let length = content.chars().count();
+    if !(2..=400).contains(&length) {
+        return Err(HttpResponse::BadRequest().json(json!({"error": "Comment content must be between 2 and 400 characters"})));
+    }
+    Ok(())
+}
+
+// Rejected content is refused, quarantined content waits for a moderator
+async fn apply_filters(db: &Database, filters: &ContentFilterPipeline, comment: &mut Comment) -> Result<(), HttpResponse> {
+    let author_id = Some(comment.author_id.clone()).filter(|id| ObjectId::from_str(id).is_ok());
+    let submission = Submission::comment(&comment.content, comment.id, author_id, comment.ip_hash.clone());
+    let outcome = match filters.check(db, &submission).await {
+        Ok(outcome) => outcome,
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to check comment: {}", e)}))),
+    };
+    match outcome.verdict {
+        Verdict::Allow => {}
+        Verdict::Quarantine(_) => {
+            comment.status = CommentStatus::Quarantined;
+            comment.flags = outcome.reasons;
+        }
+        Verdict::Reject(reason) => return Err(HttpResponse::BadRequest().json(json!({"error": reason}))),
+    }
+    Ok(())
+}
+
+async fn find_post(db: &Database, post_id: ObjectId) -> Result<Post, HttpResponse> {
+    match db.collection::<Post>("posts").find_one(doc! {"_id": post_id}, None).await {
+        Ok(Some(post)) => Ok(post),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error":"Post not found"}))),
+        Err(error) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", error)}))),
+    }
+}
+
+// Comments are only read on public posts
+async fn check_post_public(db: &Database, post_id: ObjectId) -> Result<(), HttpResponse> {
+    match db.collection::<Document>("posts").count_documents(doc! {"_id": post_id, "status": "Public"}, None).await {
+        Ok(0) => Err(HttpResponse::NotFound().json(json!({"error":"Post not found"}))),
+        Ok(_) => Ok(()),
+        Err(error) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", error)}))),
+    }
+}
+
+async fn find_comment(db: &Database, comment_id: &str) -> Result<Comment, HttpResponse> {
+    let comment_id = match ObjectId::from_str(comment_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error": "Invalid comment ID"}))),
+    };
+    match db.collection::<Comment>("comments").find_one(doc! {"_id": comment_id}, None).await {
+        Ok(Some(comment)) => Ok(comment),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error": "Comment not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comment: {}", e)}))),
+    }
+}
+
+// Public profiles of the comments' authors, by user id
+async fn author_profiles<'a>(db: &Database, comments: impl Iterator<Item = &'a Comment>) -> Result<HashMap<String, AuthorProfile>, HttpResponse> {
+    let ids: Vec<ObjectId> = comments.filter_map(|comment| ObjectId::from_str(&comment.author_id).ok()).collect();
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let options = FindOptions::builder().projection(AuthorProfile::projection()).build();
+    let cursor = match db.collection::<AuthorProfile>("users").find(doc! {"_id": {"$in": ids}}, options).await {
+        Ok(cursor) => cursor,
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch authors: {}", e)}))),
+    };
+    match cursor.try_collect::<Vec<AuthorProfile>>().await {
+        Ok(profiles) => Ok(profiles.into_iter().map(|profile| (profile.id.to_hex(), profile)).collect()),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch authors: {}", e)}))),
+    }
+}
+
+async fn comment_views(db: &Database, comments: Vec<Comment>) -> Result<Vec<CommentView>, HttpResponse> {
+    let profiles = author_profiles(db, comments.iter()).await?;
+    Ok(comments
+        .into_iter()
+        .map(|comment| {
+            let author = profiles.get(&comment.author_id).cloned();
+            CommentView::new(comment, author)
+        })
+        .collect())
+}
+
+// Keeps `Post.comment_count` equal to the number of approved comments, and the post's daily stats with it
+async fn count_on_post(db: &Database, post_id: ObjectId, by: i32) -> mongodb::error::Result<()> {
+    db.collection::<Post>("posts")
+        .update_one(doc! {"_id": post_id}, doc! {"$inc": {"comment_count": by}}, None)
+        .await?;
+    record_post_event(db, post_id, PostEvent::Comment, by as i64).await
+}
+
+// `Comment.reply_count` counts approved replies only, like the post's count
+async fn count_reply(db: &Database, reply: &Comment, by: i32) -> mongodb::error::Result<()> {
+    if let Some(parent_id) = reply.parent_id {
+        db.collection::<Comment>("comments")
+            .update_one(doc! {"_id": parent_id}, doc! {"$inc": {"reply_count": by}}, None)
+            .await?;
+    }
+    count_on_post(db, reply.post_id, by).await
+}
+
+// Replies of any status keep their parent in the thread, a removed parent would leave them without one
+async fn has_replies(db: &Database, comment_id: ObjectId) -> mongodb::error::Result<bool> {
+    let count = db.collection::<Comment>("comments").count_documents(doc! {"parent_id": comment_id}, None).await?;
+    Ok(count > 0)
+}
+
+// Comments on posts that require approval wait for the post author, unless the author wrote them
+fn initial_status(post: &Post, author_id: &str) -> CommentStatus {
+    if post.require_comment_approval && post.author != author_id {
+        CommentStatus::Pending
+    } else {
+        CommentStatus::Approved
+    }
+}
+
+#[derive(Deserialize, Clone)]
+struct AddCommentRequest{
+    content: String,
+    display_name: Option<String>, // anonymous comments only
+    challenge: Option<String>, // from /comment/challenge, anonymous comments only
+    nonce: Option<String>,
+}
+
+// Anonymous comments are allowed where both ALLOW_ANONYMOUS_COMMENTS (default true) and the post allow them
+fn anonymous_comments_allowed(post: &Post) -> bool {
+    let site = env::var("ALLOW_ANONYMOUS_COMMENTS")
+        .map(|value| value != "false" && value != "0")
+        .unwrap_or(true);
+    site && post.allow_anonymous_comments
+}
+
+struct CommentAuthor {
+    id: Option<String>, // None for anonymous comments
+    profile: Option<AuthorProfile>,
+    display_name: Option<String>,
+}
+
+// The signed-in user, or an anonymous visitor who solved a proof-of-work challenge
+async fn comment_author(db: &Database, auth: OptionalAuthUser, post: &Post, request: &AddCommentRequest) -> Result<CommentAuthor, HttpResponse> {
+    if let OptionalAuthUser(Some(auth)) = auth {
+        let user = auth.fetch(db).await?;
+        if !has_capability(&user, Capability::CommentCreate) {
+            return Err(HttpResponse::Forbidden().json(json!({"error": "You are not allowed to comment", "capability": Capability::CommentCreate})));
+        }
+        return Ok(CommentAuthor { id: Some(auth.id_str()), profile: Some(user.profile()), display_name: None });
+    }
+
+    if !anonymous_comments_allowed(post) {
+        return Err(HttpResponse::Unauthorized().json(json!({"error": "Sign in to comment on this post"})));
+    }
+    let display_name = request.display_name.as_deref().map(str::trim).filter(|name| !name.is_empty());
+    if display_name.map(|name| name.chars().count() > 40).unwrap_or(false) {
+        return Err(HttpResponse::BadRequest().json(json!({"error": "Display name can be at most 40 characters"})));
+    }
+    let (challenge, nonce) = match (&request.challenge, &request.nonce) {
+        (Some(challenge), Some(nonce)) => (challenge, nonce),
+        _ => return Err(HttpResponse::BadRequest().json(json!({"error": "Anonymous comments need a solved challenge from /comment/challenge"}))),
+    };
+    if let Err(error) = verify_challenge(db, challenge, nonce).await {
+        return Err(HttpResponse::BadRequest().json(json!({"error": error})));
+    }
+
+    Ok(CommentAuthor { id: None, profile: None, display_name: display_name.map(str::to_string) })
+}
+
+// A proof-of-work challenge to solve before commenting anonymously
+async fn fetch_challenge() -> impl Responder {
+    HttpResponse::Ok().json(issue_challenge())
+}
+async fn add_comment(
+    req: HttpRequest,
+    post_id: web::Path<String>,
+    auth: OptionalAuthUser,
+    comment_data: web::Json<AddCommentRequest>,
+    filters: web::Data<ContentFilterPipeline>,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+)-> impl Responder{
+
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    if let Err(response) = check_length(&comment_data.content) {
+        return response;
+    }
+
+    let post = match find_post(&db, post_id).await {
+        Ok(post) if post.status == PostStatus::Public => post,
+        Ok(_) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(response) => return response,
+    };
+
+    let author = match comment_author(&db, auth, &post, &comment_data).await {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    let mut comment = Comment::new(post_id, None, author.id.clone(), comment_data.content.clone());
+    comment.display_name = author.display_name;
+    comment.status = initial_status(&post, &comment.author_id);
+    if author.id.is_none() {
+        comment.ip_hash = client_ip_hash(&req);
+    }
+    if let Err(response) = apply_filters(&db, &filters, &mut comment).await {
+        return response;
+    }
+    if let Err(error) = db.collection::<Comment>("comments").insert_one(&comment, None).await {
+        return HttpResponse::InternalServerError().json(format!("Failed to add comment: {}", error));
+    }
+
+    let result = match comment.status {
+        CommentStatus::Approved => count_on_post(&db, post_id, 1).await,
+        _ => Ok(()),
+    };
+    let result = match result {
+        Ok(_) => notify_new_comment(&db, &broker, &post, &comment).await,
+        Err(error) => Err(error),
+    };
+
+    let visible = comment.status == CommentStatus::Approved;
+    let view = CommentView::new(comment, author.profile);
+    if visible {
+        broker.publish(&format!("post:{}", post_id.to_hex()), "comment", &view);
+    }
+    match result{
+        Ok(_)=>{HttpResponse::Ok().json(json!({"success":"Comment added!","comment":view}))},
+        Err(error) => {
+            // Return an error message as a JSON response
+            HttpResponse::InternalServerError().json(format!("Failed to add comment: {}", error))
+        }
+    }
+
+}
+
+#[derive(Deserialize)]
+struct ReplyQuery {
+    comment_id: Option<String>,
+    path: Option<String>, // "<id>/<id>/<id>" from the top-level comment down to the parent
+}
+
+// The post a reply goes to, from the path, and its parent comment, from the query
+struct ReplyTarget {
+    post_id: ObjectId,
+    parent_id: ObjectId,
+    path: Option<Vec<ObjectId>>,
+}
+
+impl ReplyTarget {
+    fn parse(post_id: &str, query: &ReplyQuery) -> Result<ReplyTarget, HttpResponse> {
+        let post_id = ObjectId::from_str(post_id).map_err(|_| HttpResponse::BadRequest().json(json!({"error": "Invalid Post ID"})))?;
+        let path = match &query.path {
+            Some(path) => match path.split('/').map(ObjectId::from_str).collect::<Result<Vec<_>, _>>() {
+                Ok(path) if !path.is_empty() => Some(path),
+                _ => return Err(HttpResponse::BadRequest().json(json!({"error": "Invalid comment path"}))),
+            },
+            None => None,
+        };
+        let parent_id = match (&path, query.comment_id.as_deref().map(ObjectId::from_str)) {
+            (Some(path), _) => *path.last().unwrap(),
+            (None, Some(Ok(id))) => id,
+            _ => return Err(HttpResponse::BadRequest().json(json!({"error": "Invalid comment ID"}))),
+        };
+        Ok(ReplyTarget { post_id, parent_id, path })
+    }
+}
+
+impl FromRequest for ReplyTarget {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let target = match web::Query::<ReplyQuery>::from_query(req.query_string()) {
+            Ok(query) => ReplyTarget::parse(req.match_info().get("id").unwrap_or_default(), &query),
+            Err(_) => Err(HttpResponse::BadRequest().json(json!({"error": "Invalid comment ID"}))),
+        };
+        ready(target.map_err(|response| InternalError::from_response("reply target", response).into()))
+    }
+}
+
+async fn add_reply(
+    req: HttpRequest,
+    target: ReplyTarget,
+    auth: OptionalAuthUser,
+    comment_data: web::Json<AddCommentRequest>,
+    filters: web::Data<ContentFilterPipeline>,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let comments = db.collection::<Comment>("comments");
+    let ReplyTarget { post_id, parent_id, path } = target;
+
+    if let Err(response) = check_length(&comment_data.content) {
+        return response;
+    }
+
+    let post = match find_post(&db, post_id).await {
+        Ok(post) if post.status == PostStatus::Public => post,
+        Ok(_) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(response) => return response,
+    };
+    // Only comments readers can see can be replied to
+    let mut parent_filter = doc! {"_id": parent_id, "post_id": post_id, "deleted": {"$ne": true}};
+    parent_filter.extend(visible());
+    let parent = match comments.find_one(parent_filter, None).await {
+        Ok(Some(parent)) => parent,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Comment not found"})),
+        Err(error) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comment: {}", error)})),
+    };
+    if path.map(|path| path != parent.path()).unwrap_or(false) {
+        return HttpResponse::BadRequest().json(json!({"error": "Comment path does not match the thread"}));
+    }
+    let max_depth = max_comment_depth();
+    if parent.depth + 1 > max_depth {
+        return HttpResponse::BadRequest().json(json!({"error": format!("Replies can be nested at most {} levels deep", max_depth)}));
+    }
+    let author = match comment_author(&db, auth, &post, &comment_data).await {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    let mut reply = Comment::new(post_id, Some(&parent), author.id.clone(), comment_data.content.clone());
+    reply.display_name = author.display_name;
+    reply.status = initial_status(&post, &reply.author_id);
+    if author.id.is_none() {
+        reply.ip_hash = client_ip_hash(&req);
+    }
+    if let Err(response) = apply_filters(&db, &filters, &mut reply).await {
+        return response;
+    }
+
+    if let Err(error) = comments.insert_one(&reply, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to add reply: {}", error)}));
+    }
+
+    let result = match reply.status {
+        CommentStatus::Approved => count_reply(&db, &reply, 1).await,
+        _ => Ok(()),
+    };
+    let result = match result {
+        Ok(_) => notify_new_comment(&db, &broker, &post, &reply).await,
+        Err(error) => Err(error),
+    };
+
+    let visible = reply.status == CommentStatus::Approved;
+    let view = CommentView::new(reply, author.profile);
+    if visible {
+        broker.publish(&format!("post:{}", post_id.to_hex()), "comment", &view);
+    }
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({"success": "Reply added!", "comment": view})),
+        Err(error) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", error)})),
+    }
+}
+
+async fn add_like(
+    query: web::Query<HashMap<String, String>>,
+    post_id: web::Path<String>,
+    auth: AuthUser,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let comments = db.collection::<Comment>("comments");
+
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid Post ID"})),
+    };
+    let comment_id = match query.get("comment_id").map(|id| ObjectId::from_str(id)) {
+        Some(Ok(id)) => id,
+        _ => return HttpResponse::BadRequest().json(json!({"error": "Invalid comment ID"})),
+    };
+    if let Err(response) = check_post_public(&db, post_id).await {
+        return response;
+    }
+    let user_id = auth.id_str();
+
+    // Like if the user hasn't liked yet, otherwise take the like back. Each step is a single atomic update.
+    // Only comments readers can see can be liked.
+    let mut like_filter = doc! {"_id": comment_id, "post_id": post_id, "likes": {"$ne": &user_id}, "deleted": {"$ne": true}};
+    like_filter.extend(visible());
+    let like = comments.find_one_and_update(
+        like_filter,
+        doc! {"$push": {"likes": &user_id}, "$inc": {"like_count": 1}},
+        None,
+    ).await;
+    // Both return the comment as it was before the update
+    let (is_deleted, like_count) = match like {
+        Ok(Some(comment)) => {
+            if let Err(error) = notify(&db, &broker, &comment.author_id, NotificationKind::Like, &user_id, Some(post_id), Some(comment_id)).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to notify: {}", error)}));
+            }
+            (false, comment.like_count + 1)
+        }
+        Ok(None) => {
+            let unlike = comments.find_one_and_update(
+                doc! {"_id": comment_id, "post_id": post_id, "likes": &user_id},
+                doc! {"$pull": {"likes": &user_id}, "$inc": {"like_count": -1}},
+                None,
+            ).await;
+            match unlike {
+                Ok(Some(comment)) => (true, comment.like_count.saturating_sub(1)),
+                Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Comment not found"})),
+                Err(error) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update comment: {}", error)})),
+            }
+        }
+        Err(error) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update comment: {}", error)})),
+    };
+    broker.publish(&format!("post:{}", post_id.to_hex()), "like", json!({"comment_id": comment_id, "like_count": like_count}));
+
+    HttpResponse::Ok().json(json!({"success": if is_deleted { "Like removed!" } else { "Like added!" }, "isDeleted": is_deleted}))
+}
+
+// Comments on the post itself, replies are fetched per thread
+async fn fetch_comments(post_id: web::Path<String>, query: web::Query<PageQuery>, db: web::Data<Database>) -> impl Responder {
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid Post ID"})),
+    };
+    if let Err(response) = check_post_public(&db, post_id).await {
+        return response;
+    }
+
+    let mut filter = doc! {"post_id": post_id, "parent_id": null};
+    filter.extend(visible());
+    let comments = match find_comments(&db, filter, query.options()).await {
+        Ok(comments) => comments,
+        Err(response) => return response,
+    };
+    match comment_views(&db, comments).await {
+        Ok(views) => HttpResponse::Ok().json(views),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct ThreadQuery {
+    sort: Option<CommentSort>,
+    depth: Option<u32>, // levels of replies to return, default 3
+    limit: Option<usize>, // replies per comment, default 10
+    cursor: Option<String>, // continues the direct replies after a previous page
+}
+
+#[derive(Serialize)]
+struct ThreadNode {
+    #[serde(flatten)]
+    comment: CommentView,
+    replies: Vec<ThreadNode>,
+    // More direct replies can be loaded with /comment/thread/{id}?cursor=<more_cursor>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    more_cursor: Option<String>,
+    // The replies are deeper than the requested depth, load them with /comment/thread/{id}
+    collapsed: bool,
+}
+
+impl ThreadNode {
+    fn build(
+        comment: Comment,
+        children: &mut HashMap<ObjectId, (Vec<Comment>, Option<String>)>,
+        profiles: &HashMap<String, AuthorProfile>,
+    ) -> ThreadNode {
+        let author = profiles.get(&comment.author_id).cloned();
+        match children.remove(&comment.id) {
+            Some((replies, more_cursor)) => ThreadNode {
+                replies: replies.into_iter().map(|reply| ThreadNode::build(reply, children, profiles)).collect(),
+                more_cursor,
+                collapsed: false,
+                comment: CommentView::new(comment, author),
+            },
+            None => ThreadNode {
+                collapsed: comment.reply_count > 0,
+                replies: vec![],
+                more_cursor: None,
+                comment: CommentView::new(comment, author),
+            },
+        }
+    }
+}
+
+// Splits the `limit + 1` comments fetched for a page into the page and the cursor of the next one
+fn page_with_cursor(mut comments: Vec<Comment>, limit: usize, sort: CommentSort) -> (Vec<Comment>, Option<String>) {
+    if comments.len() > limit {
+        comments.truncate(limit);
+        let cursor = comments.last().map(|last| sort.cursor(last));
+        (comments, cursor)
+    } else {
+        (comments, None)
+    }
+}
+
+// A comment with its subtree of replies, level by level
+async fn fetch_thread(comment_id: web::Path<String>, query: web::Query<ThreadQuery>, db: web::Data<Database>) -> impl Responder {
+    let comments = db.collection::<Comment>("comments");
+    let comment_id = match ObjectId::from_str(&comment_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid comment ID"})),
+    };
+    let sort = query.sort.unwrap_or_default();
+    let depth = query.depth.unwrap_or(3).clamp(1, max_comment_depth());
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+
+    // The same rules as the post's listing, for the comment and every reply below it
+    let mut root = doc! {"_id": comment_id};
+    root.extend(visible());
+    let comment = match comments.find_one(root, None).await {
+        Ok(Some(comment)) => comment,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Comment not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comment: {}", e)})),
+    };
+    if let Err(response) = check_post_public(&db, comment.post_id).await {
+        return response;
+    }
+
+    // First level, this is the one the cursor pages through
+    let mut filter = doc! {"post_id": comment.post_id, "parent_id": comment_id};
+    filter.extend(visible());
+    if let Some(cursor) = &query.cursor {
+        match sort.after(cursor) {
+            Some(after) => filter.extend(after),
+            None => return HttpResponse::BadRequest().json(json!({"error": "Invalid cursor"})),
+        }
+    }
+    let options = FindOptions::builder().sort(sort.sort()).limit(limit as i64 + 1).build();
+    let replies = match find_comments(&db, filter, options).await {
+        Ok(replies) => replies,
+        Err(response) => return response,
+    };
+
+    let mut children = HashMap::new();
+    let (replies, more_cursor) = page_with_cursor(replies, limit, sort);
+    let mut frontier: Vec<ObjectId> = replies.iter().filter(|reply| reply.reply_count > 0).map(|reply| reply.id).collect();
+    children.insert(comment_id, (replies, more_cursor));
+
+    // Deeper levels, the first `limit` replies of every comment of the previous level
+    for _ in 1..depth {
+        if frontier.is_empty() {
+            break;
+        }
+        let mut matched = doc! {"post_id": comment.post_id, "parent_id": {"$in": &frontier}};
+        matched.extend(visible());
+        let pipeline = vec![
+            doc! {"$match": matched},
+            doc! {"$sort": sort.sort()},
+            doc! {"$group": {"_id": "$parent_id", "replies": {"$push": "$$ROOT"}}},
+            doc! {"$project": {"replies": {"$slice": ["$replies", limit as i64 + 1]}}},
+        ];
+        let groups: Vec<Document> = match comments.aggregate(pipeline, None).await {
+            Ok(cursor) => match cursor.try_collect().await {
+                Ok(groups) => groups,
+                Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch replies: {}", e)})),
+            },
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch replies: {}", e)})),
+        };
+
+        frontier = vec![];
+        for group in groups {
+            let parent_id = match group.get_object_id("_id") {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let replies: Vec<Comment> = match group.get("replies") {
+                Some(replies) => bson::from_bson(replies.clone()).unwrap_or_default(),
+                None => vec![],
+            };
+            let (replies, more_cursor) = page_with_cursor(replies, limit, sort);
+            frontier.extend(replies.iter().filter(|reply| reply.reply_count > 0).map(|reply| reply.id));
+            children.insert(parent_id, (replies, more_cursor));
+        }
+    }
+
+    let everyone = children.values().flat_map(|(replies, _)| replies.iter()).chain([&comment]);
+    let profiles = match author_profiles(&db, everyone).await {
+        Ok(profiles) => profiles,
+        Err(response) => return response,
+    };
+    HttpResponse::Ok().json(ThreadNode::build(comment, &mut children, &profiles))
+}
+
+#[derive(Deserialize)]
+struct EditCommentRequest {
+    content: String,
+}
+
+// Only the author can edit, the previous content is kept in `history`.
+// The new content goes through the filters again, an edit can't sneak in what a new comment couldn't.
+async fn edit_comment(
+    comment_id: web::Path<String>,
+    auth: AuthUser,
+    request: web::Json<EditCommentRequest>,
+    filters: web::Data<ContentFilterPipeline>,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let comment = match find_comment(&db, &comment_id).await {
+        Ok(comment) => comment,
+        Err(response) => return response,
+    };
+    if comment.author_id != auth.id_str() {
+        return HttpResponse::Forbidden().json(json!({"error": "Only the author can edit a comment"}));
+    }
+    if comment.deleted {
+        return HttpResponse::BadRequest().json(json!({"error": "Deleted comments can't be edited"}));
+    }
+    if let Err(response) = check_length(&request.content) {
+        return response;
+    }
+
+    let mut edited = comment.clone();
+    edited.content = request.content.clone();
+    if let Err(response) = apply_filters(&db, &filters, &mut edited).await {
+        return response;
+    }
+
+    // A pipeline update reads the current content and replaces it in one step
+    let now = Utc::now().timestamp();
+    let update = vec![doc! {"$set": {
+        "history": {"$concatArrays": [{"$ifNull": ["$history", []]}, [{"content": "$content", "replaced_at": now}]]},
+        "content": &request.content,
+        "edited_at": now,
+        "status": bson::to_bson(&edited.status).unwrap(),
+        "flags": &edited.flags,
+    }}];
+    let filter = doc! {
+        "_id": comment.id,
+        "author_id": &comment.author_id,
+        "deleted": {"$ne": true},
+        "status": bson::to_bson(&comment.status).unwrap(),
+    };
+    let result = db.collection::<Comment>("comments").update_one(filter, update, None).await;
+
+    match result {
+        Ok(result) if result.modified_count == 0 => HttpResponse::Conflict().json(json!({"error": "Comment changed, try again"})),
+        Ok(_) => {
+            let channel = format!("post:{}", comment.post_id.to_hex());
+            if comment.status == CommentStatus::Approved && edited.status == CommentStatus::Quarantined {
+                if let Err(e) = count_reply(&db, &comment, -1).await {
+                    return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)}));
+                }
+                broker.publish(&channel, "comment_deleted", json!({"comment_id": comment.id, "tombstone": false}));
+            } else if edited.status == CommentStatus::Approved {
+                broker.publish(&channel, "comment_edited", json!({"comment_id": comment.id, "content": &request.content, "edited_at": now}));
+            }
+            HttpResponse::Ok().json(json!({"success": "Comment edited", "edited_at": now, "status": edited.status}))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to edit comment: {}", e)})),
+    }
+}
+
+// The comment's author, the post's author and admins can delete.
+// A comment with replies becomes a "[deleted]" tombstone so the thread stays readable.
+async fn delete_comment(comment_id: web::Path<String>, auth: AuthUser, broker: web::Data<Broker>, db: web::Data<Database>) -> impl Responder {
+    let comments = db.collection::<Comment>("comments");
+    let comment = match find_comment(&db, &comment_id).await {
+        Ok(comment) => comment,
+        Err(response) => return response,
+    };
+    let post = match find_post(&db, comment.post_id).await {
+        Ok(post) => post,
+        Err(response) => return response,
+    };
+
+    let user_id = auth.id_str();
+    if comment.author_id != user_id && post.author != user_id {
+        match auth.has_capability(&db, Capability::CommentModerate).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::Forbidden().json(json!({"error": "Not allowed to delete this comment"})),
+            Err(response) => return response,
+        }
+    }
+    if comment.deleted {
+        return HttpResponse::Ok().json(json!({"success": "Comment deleted"}));
+    }
+
+    let keep_tombstone = match has_replies(&db, comment.id).await {
+        Ok(has_replies) => has_replies,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comments: {}", e)})),
+    };
+    if keep_tombstone {
+        let update = doc! {"$set": {
+            "deleted": true,
+            "content": DELETED_COMMENT,
+            "author_id": DELETED_COMMENT,
+            "display_name": null,
+            "likes": [],
+            "like_count": 0,
+            "history": [],
+        }};
+        if let Err(e) = comments.update_one(doc! {"_id": comment.id}, update, None).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to delete comment: {}", e)}));
+        }
+        // A tombstone is not counted as a comment, its parent still counts it as a reply while it has replies
+        if comment.status == CommentStatus::Approved {
+            if let Err(e) = count_on_post(&db, comment.post_id, -1).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)}));
+            }
+        }
+        broker.publish(&format!("post:{}", comment.post_id.to_hex()), "comment_deleted", json!({"comment_id": comment.id, "tombstone": true}));
+        return HttpResponse::Ok().json(json!({"success": "Comment deleted", "tombstone": true}));
+    }
+
+    // Without replies the comment goes away, and so do tombstones above it that were only kept for it
+    broker.publish(&format!("post:{}", comment.post_id.to_hex()), "comment_deleted", json!({"comment_id": comment.id, "tombstone": false}));
+    let mut removed = comment;
+    loop {
+        if let Err(e) = comments.delete_one(doc! {"_id": removed.id}, None).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to delete comment: {}", e)}));
+        }
+        if removed.status == CommentStatus::Approved && !removed.deleted {
+            if let Err(e) = count_on_post(&db, removed.post_id, -1).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)}));
+            }
+        }
+
+        let parent_id = match removed.parent_id {
+            Some(parent_id) => parent_id,
+            None => break,
+        };
+        let by = if removed.status == CommentStatus::Approved { -1 } else { 0 };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        let parent = match comments.find_one_and_update(doc! {"_id": parent_id}, doc! {"$inc": {"reply_count": by}}, options).await {
+            Ok(Some(parent)) if parent.deleted => parent,
+            Ok(_) => break,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update comment: {}", e)})),
+        };
+        match has_replies(&db, parent.id).await {
+            Ok(false) => removed = parent,
+            Ok(true) => break,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch comments: {}", e)})),
+        }
+    }
+
+    HttpResponse::Ok().json(json!({"success": "Comment deleted", "tombstone": false}))
+}
+
+// Moves a comment to `status` and keeps the post's comment count right.
+// False when the comment's status changed in the meantime.
+pub(crate) async fn set_comment_status(db: &Database, broker: &Broker, comment: &Comment, status: &CommentStatus) -> Result<bool, HttpResponse> {
+    // Matching on the old status makes sure the count changes only once
+    let old_status = bson::to_bson(&comment.status).unwrap();
+    let new_status = bson::to_bson(status).unwrap();
+    let result = db.collection::<Comment>("comments")
+        .update_one(doc! {"_id": comment.id, "status": old_status}, doc! {"$set": {"status": new_status}}, None)
+        .await;
+
+    let changed = match result {
+        Ok(result) => result.modified_count > 0,
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to moderate comment: {}", e)}))),
+    };
+    let was_counted = comment.status == CommentStatus::Approved;
+    let is_counted = *status == CommentStatus::Approved;
+    if changed && was_counted != is_counted && !comment.deleted {
+        if let Err(e) = count_reply(db, comment, if is_counted { 1 } else { -1 }).await {
+            return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})));
+        }
+    }
+    // Readers of the post see it go, comments that become visible are announced by the caller with their author
+    if changed && was_counted && !is_counted {
+        broker.publish(&format!("post:{}", comment.post_id.to_hex()), "comment_deleted", json!({"comment_id": comment.id, "tombstone": false}));
+    }
+    Ok(changed)
+}
+
+#[derive(Deserialize)]
+struct ModerateRequest {
+    status: CommentStatus,
+    spam: Option<bool>, // what the spam filter learns from the decision, see `moderate_comment`
+}
+
+// The post's author or an admin approves or hides comments.
+// Hiding a comment teaches the spam filter it was spam and approving a held one that it wasn't,
+// unless `spam` says otherwise.
+async fn moderate_comment(
+    comment_id: web::Path<String>,
+    auth: AuthUser,
+    request: web::Json<ModerateRequest>,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let comment = match find_comment(&db, &comment_id).await {
+        Ok(comment) => comment,
+        Err(response) => return response,
+    };
+    let post = match find_post(&db, comment.post_id).await {
+        Ok(post) => post,
+        Err(response) => return response,
+    };
+    if post.author != auth.id_str() {
+        match auth.has_capability(&db, Capability::CommentModerate).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::Forbidden().json(json!({"error": "Only the post author can moderate comments"})),
+            Err(response) => return response,
+        }
+    }
+
+    let changed = match set_comment_status(&db, &broker, &comment, &request.status).await {
+        Ok(changed) => changed,
+        Err(response) => return response,
+    };
+
+    let was_held = matches!(comment.status, CommentStatus::Pending | CommentStatus::Quarantined);
+    let is_spam = match (request.spam, &request.status) {
+        (Some(spam), _) => Some(spam),
+        (None, CommentStatus::Hidden) => Some(true),
+        (None, CommentStatus::Approved) if was_held => Some(false),
+        _ => None,
+    };
+    if let (true, Some(is_spam), false) = (changed, is_spam, comment.deleted) {
+        train_spam_model(&db, &comment.content, is_spam);
+    }
+
+    let action = match request.status {
+        CommentStatus::Approved => Some(ModerationAction::Approve),
+        CommentStatus::Hidden => Some(ModerationAction::Hide),
+        _ => None,
+    };
+    if let (true, Some(action)) = (changed, action) {
+        if let Err(e) = log_moderation(&db, auth.id_str(), action, ReportTarget::Comment, comment.id, None, None).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to log moderation: {}", e)}));
+        }
+    }
+
+    // An approved comment is news for whoever it would have told if it had been visible right away.
+    // The post's author already heard about a pending comment, and doesn't need to hear about what they approved.
+    let is_news = comment.parent_id.is_some() || (comment.status == CommentStatus::Quarantined && post.author != auth.id_str());
+    if changed && was_held && request.status == CommentStatus::Approved {
+        let approved = Comment { status: CommentStatus::Approved, ..comment };
+        if is_news {
+            if let Err(e) = notify_new_comment(&db, &broker, &post, &approved).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to notify: {}", e)}));
+            }
+        }
+        match comment_views(&db, vec![approved]).await {
+            Ok(views) => broker.publish(&format!("post:{}", post.id.to_hex()), "comment", &views[0]),
+            Err(response) => return response,
+        }
+    }
+
+    HttpResponse::Ok().json(json!({"success": "Comment moderated", "status": request.status}))
+}
+
+// Comments waiting for approval or quarantined by the filters, for the post's author or an admin
+async fn fetch_pending(post_id: web::Path<String>, auth: AuthUser, query: web::Query<PageQuery>, db: web::Data<Database>) -> impl Responder {
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid Post ID"})),
+    };
+    let post = match find_post(&db, post_id).await {
+        Ok(post) => post,
+        Err(response) => return response,
+    };
+    if post.author != auth.id_str() {
+        match auth.has_capability(&db, Capability::CommentModerate).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::Forbidden().json(json!({"error": "Only the post author can see pending comments"})),
+            Err(response) => return response,
+        }
+    }
+
+    let comments = match find_comments(&db, doc! {"post_id": post_id, "status": {"$in": ["Pending", "Quarantined"]}}, query.options()).await {
+        Ok(comments) => comments,
+        Err(response) => return response,
+    };
+    match comment_views(&db, comments).await {
+        Ok(views) => HttpResponse::Ok().json(views),
+        Err(response) => response,
+    }
+}
+
+pub fn comment_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/comment/challenge")
+            .route(web::get().to(fetch_challenge))
+    )
+    .service(
+        web::resource("/post/addcomment/{id}")
+            .wrap(RateLimit::new("comment"))
+            .route(web::post().to(add_comment))
+    )
+    .service(
+        web::resource("/post/addreply/{id}")
+            .wrap(RateLimit::new("comment"))
+            .route(web::post().to(add_reply))
+    )
+    .service(
+        web::resource("/post/add_like/{id}")
+            .route(web::post().to(add_like))
+    )
+    .service(
+        web::resource("/post/comments/{id}")
+            .route(web::get().to(fetch_comments))
+    )
+    .service(
+        web::resource("/comment/thread/{id}")
+            .route(web::get().to(fetch_thread))
+    )
+    .service(
+        web::resource("/comment/edit/{id}")
+            .route(web::post().to(edit_comment))
+    )
+    .service(
+        web::resource("/comment/delete/{id}")
+            .route(web::post().to(delete_comment))
+    )
+    .service(
+        web::resource("/comment/moderate/{id}")
+            .route(web::post().to(moderate_comment))
+    )
+    .service(
+        web::resource("/post/comments/{id}/pending")
+            .route(web::get().to(fetch_pending))
+    );
+}
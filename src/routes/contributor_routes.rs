@@ -0,0 +1,265 @@
+use std::{collections::HashMap, str::FromStr};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{AuthorProfile, Contributor, ContributorRole, ContributorView, Invitation, InvitationStatus, NotificationKind, Post, PostStatus, User};
+use crate::utils::{notify, AuthUser, Broker};
+use super::post_routes::find_post_for;
+
+// Everyone working on a post with their role, the owner first
+pub(crate) async fn contributor_views(db: &Database, owner: &str, contributors: &[Contributor]) -> mongodb::error::Result<Vec<ContributorView>> {
+    let roles: Vec<(&str, ContributorRole)> = std::iter::once((owner, ContributorRole::Owner))
+        .chain(contributors.iter().map(|contributor| (contributor.user_id.as_str(), contributor.role)))
+        .collect();
+    let ids: Vec<ObjectId> = roles.iter().filter_map(|(id, _)| ObjectId::from_str(id).ok()).collect();
+    let options = FindOptions::builder().projection(AuthorProfile::projection()).build();
+    let profiles: HashMap<String, AuthorProfile> = db
+        .collection::<AuthorProfile>("users")
+        .find(doc! {"_id": {"$in": ids}}, options)
+        .await?
+        .try_collect::<Vec<AuthorProfile>>()
+        .await?
+        .into_iter()
+        .map(|profile| (profile.id.to_hex(), profile))
+        .collect();
+    Ok(roles
+        .into_iter()
+        .filter_map(|(id, role)| profiles.get(id).cloned().map(|profile| ContributorView { profile, role }))
+        .collect())
+}
+
+async fn fetch_contributors(post_id: web::Path<String>, db: web::Data<Database>) -> impl Responder {
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    let post = match db.collection::<Post>("posts").find_one(doc! {"_id": post_id}, None).await {
+        Ok(Some(post)) if post.status != PostStatus::Deleted => post,
+        Ok(_) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)})),
+    };
+    match contributor_views(&db, &post.author, &post.contributors).await {
+        Ok(views) => HttpResponse::Ok().json(views),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch contributors: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct InviteRequest {
+    user_id: String,
+    role: ContributorRole,
+}
+
+// Only the owner invites, the invitee joins the post when they accept
+async fn invite_contributor(post_id: web::Path<String>, auth: AuthUser, request: web::Json<InviteRequest>, broker: web::Data<Broker>, db: web::Data<Database>) -> impl Responder {
+    let (post, _) = match find_post_for(&db, &post_id, &auth, |role| *role == ContributorRole::Owner).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if request.role == ContributorRole::Owner {
+        return HttpResponse::BadRequest().json(json!({"error":"A post has a single owner"}));
+    }
+    if post.role_of(&request.user_id).is_some() {
+        return HttpResponse::Conflict().json(json!({"error":"This user already works on the post"}));
+    }
+    let invitee = match ObjectId::from_str(&request.user_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid user ID"})),
+    };
+    match db.collection::<User>("users").count_documents(doc! {"_id": invitee}, None).await {
+        Ok(0) => return HttpResponse::NotFound().json(json!({"error":"User not found"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)})),
+    }
+
+    let invitations = db.collection::<Invitation>("post_invitations");
+    let pending = doc! {"post_id": post.id, "invitee": &request.user_id, "status": "Pending"};
+    match invitations.count_documents(pending, None).await {
+        Ok(0) => {}
+        Ok(_) => return HttpResponse::Conflict().json(json!({"error":"This user already has a pending invitation"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch invitations: {}", e)})),
+    }
+    let invitation = Invitation::new(post.id, auth.id_str(), request.user_id.clone(), request.role);
+    if let Err(e) = invitations.insert_one(&invitation, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save invitation: {}", e)}));
+    }
+    if let Err(e) = notify(&db, &broker, &request.user_id, NotificationKind::Invitation, &auth.id_str(), Some(post.id), None).await {
+        println!("failed to notify about invitation: {}", e);
+    }
+    HttpResponse::Ok().json(invitation)
+}
+
+#[derive(Deserialize)]
+struct ContributorRequest {
+    user_id: String,
+}
+
+// The owner removes someone, or a contributor leaves
+async fn remove_contributor(post_id: web::Path<String>, auth: AuthUser, request: web::Json<ContributorRequest>, db: web::Data<Database>) -> impl Responder {
+    let (post, role) = match find_post_for(&db, &post_id, &auth, |_| true).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if role != ContributorRole::Owner && request.user_id != auth.id_str() {
+        return HttpResponse::Forbidden().json(json!({"error":"Only the owner can remove other contributors"}));
+    }
+    if request.user_id == post.author {
+        return HttpResponse::BadRequest().json(json!({"error":"The owner can't leave their own post"}));
+    }
+    let update = doc! {"$pull": {"contributors": {"user_id": &request.user_id}}};
+    match db.collection::<Post>("posts").update_one(doc! {"_id": post.id}, update, None).await {
+        Ok(result) if result.modified_count == 0 => HttpResponse::NotFound().json(json!({"error":"Not a contributor of this post"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Contributor removed"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleRequest {
+    user_id: String,
+    role: ContributorRole,
+}
+
+async fn change_role(post_id: web::Path<String>, auth: AuthUser, request: web::Json<RoleRequest>, db: web::Data<Database>) -> impl Responder {
+    let (post, _) = match find_post_for(&db, &post_id, &auth, |role| *role == ContributorRole::Owner).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if request.role == ContributorRole::Owner {
+        return HttpResponse::BadRequest().json(json!({"error":"A post has a single owner"}));
+    }
+    let filter = doc! {"_id": post.id, "contributors.user_id": &request.user_id};
+    let update = doc! {"$set": {"contributors.$.role": bson::to_bson(&request.role).unwrap()}};
+    match db.collection::<Post>("posts").update_one(filter, update, None).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::NotFound().json(json!({"error":"Not a contributor of this post"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Role changed"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})),
+    }
+}
+
+// Invitations waiting for the signed in user's answer, newest first
+async fn fetch_invitations(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let options = FindOptions::builder().sort(doc! {"created_at": -1}).build();
+    let filter = doc! {"invitee": auth.id_str(), "status": "Pending"};
+    match db.collection::<Invitation>("post_invitations").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Invitation>>().await {
+            Ok(invitations) => HttpResponse::Ok().json(invitations),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch invitations: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch invitations: {}", e)})),
+    }
+}
+
+async fn find_pending_invitation(db: &Database, invitation_id: &str) -> Result<Invitation, HttpResponse> {
+    let id = match ObjectId::from_str(invitation_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid invitation ID"}))),
+    };
+    match db.collection::<Invitation>("post_invitations").find_one(doc! {"_id": id}, None).await {
+        Ok(Some(invitation)) if invitation.status == InvitationStatus::Pending => Ok(invitation),
+        Ok(Some(_)) => Err(HttpResponse::Conflict().json(json!({"error":"The invitation was already answered"}))),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error":"Invitation not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch invitation: {}", e)}))),
+    }
+}
+
+// Only a pending invitation changes status, so an answer can't race a revoke
+async fn answer_invitation(db: &Database, invitation: &Invitation, status: InvitationStatus) -> Result<(), HttpResponse> {
+    let filter = doc! {"_id": invitation.id, "status": "Pending"};
+    let update = doc! {"$set": {"status": bson::to_bson(&status).unwrap(), "responded_at": Utc::now().timestamp()}};
+    match db.collection::<Invitation>("post_invitations").update_one(filter, update, None).await {
+        Ok(result) if result.matched_count == 0 => Err(HttpResponse::Conflict().json(json!({"error":"The invitation was already answered"}))),
+        Ok(_) => Ok(()),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update invitation: {}", e)}))),
+    }
+}
+
+async fn accept_invitation(invitation_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let invitation = match find_pending_invitation(&db, &invitation_id).await {
+        Ok(invitation) => invitation,
+        Err(response) => return response,
+    };
+    if invitation.invitee != auth.id_str() {
+        return HttpResponse::Forbidden().json(json!({"error":"This invitation is for someone else"}));
+    }
+    if let Err(response) = answer_invitation(&db, &invitation, InvitationStatus::Accepted).await {
+        return response;
+    }
+
+    let contributor = Contributor { user_id: auth.id_str(), role: invitation.role, added_at: Utc::now() };
+    let filter = doc! {"_id": invitation.post_id, "status": {"$ne": "Deleted"}, "contributors.user_id": {"$ne": auth.id_str()}};
+    let update = doc! {"$push": {"contributors": bson::to_bson(&contributor).unwrap()}};
+    match db.collection::<Post>("posts").update_one(filter, update, None).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::NotFound().json(json!({"error":"Post not found or already joined"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Invitation accepted", "post_id": invitation.post_id.to_hex()})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})),
+    }
+}
+
+async fn decline_invitation(invitation_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let invitation = match find_pending_invitation(&db, &invitation_id).await {
+        Ok(invitation) => invitation,
+        Err(response) => return response,
+    };
+    if invitation.invitee != auth.id_str() {
+        return HttpResponse::Forbidden().json(json!({"error":"This invitation is for someone else"}));
+    }
+    match answer_invitation(&db, &invitation, InvitationStatus::Declined).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"success":"Invitation declined"})),
+        Err(response) => response,
+    }
+}
+
+async fn revoke_invitation(invitation_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let invitation = match find_pending_invitation(&db, &invitation_id).await {
+        Ok(invitation) => invitation,
+        Err(response) => return response,
+    };
+    if let Err(response) = find_post_for(&db, &invitation.post_id.to_hex(), &auth, |role| *role == ContributorRole::Owner).await {
+        return response;
+    }
+    match answer_invitation(&db, &invitation, InvitationStatus::Revoked).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"success":"Invitation revoked"})),
+        Err(response) => response,
+    }
+}
+
+pub fn contributor_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/post/contributors/{id}")
+            .route(web::get().to(fetch_contributors))
+    )
+    .service(
+        web::resource("/post/contributors/{id}/invite")
+            .route(web::post().to(invite_contributor))
+    )
+    .service(
+        web::resource("/post/contributors/{id}/remove")
+            .route(web::post().to(remove_contributor))
+    )
+    .service(
+        web::resource("/post/contributors/{id}/role")
+            .route(web::post().to(change_role))
+    )
+    .service(
+        web::resource("/post/invitations")
+            .route(web::get().to(fetch_invitations))
+    )
+    .service(
+        web::resource("/post/invitations/{id}/accept")
+            .route(web::post().to(accept_invitation))
+    )
+    .service(
+        web::resource("/post/invitations/{id}/decline")
+            .route(web::post().to(decline_invitation))
+    )
+    .service(
+        web::resource("/post/invitations/{id}/revoke")
+            .route(web::post().to(revoke_invitation))
+    );
+}
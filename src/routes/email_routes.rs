@@ -0,0 +1,134 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{Capability, EmailStatus, OutgoingEmail, User};
+use crate::utils::{verify_unsubscribe_token, AuthUser};
+
+#[derive(Deserialize)]
+struct UnsubscribeQuery {
+    user: String,
+    token: String,
+}
+
+// The link at the bottom of every digest, also POSTed by mail clients' unsubscribe buttons
+async fn unsubscribe(query: web::Query<UnsubscribeQuery>, db: web::Data<Database>) -> impl Responder {
+    if !verify_unsubscribe_token(&query.user, &query.token) {
+        return HttpResponse::BadRequest().json(json!({"error":"Invalid unsubscribe link"}));
+    }
+    let user_id = match ObjectId::from_str(&query.user) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid unsubscribe link"})),
+    };
+    match db.collection::<User>("users").update_one(doc! {"_id": user_id}, doc! {"$set": {"email_digest": false}}, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"You will not get the weekly digest anymore"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update user: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct EmailPreferencesRequest {
+    email_digest: Option<bool>,
+    language: Option<String>,
+}
+
+async fn fetch_email_preferences(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    match auth.fetch(&db).await {
+        Ok(user) => HttpResponse::Ok().json(json!({"email_digest": user.email_digest, "language": user.language})),
+        Err(response) => response,
+    }
+}
+
+async fn update_email_preferences(auth: AuthUser, request: web::Json<EmailPreferencesRequest>, db: web::Data<Database>) -> impl Responder {
+    let mut update = doc! {};
+    if let Some(email_digest) = request.email_digest {
+        update.insert("email_digest", email_digest);
+    }
+    if let Some(language) = &request.language {
+        if language != "tr" && language != "en" {
+            return HttpResponse::BadRequest().json(json!({"error":"Language must be tr or en"}));
+        }
+        update.insert("language", language);
+    }
+    if update.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error":"Nothing to update"}));
+    }
+    match db.collection::<User>("users").update_one(doc! {"_id": auth.id}, doc! {"$set": update}, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Preferences saved"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save preferences: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct OutboxQuery {
+    status: Option<EmailStatus>, // Failed by default
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+// Emails the worker gave up on, or any other status, newest first
+async fn fetch_outbox(auth: AuthUser, query: web::Query<OutboxQuery>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::OutboxManage).await {
+        return response;
+    }
+    let filter = doc! {"status": bson::to_bson(query.status.as_ref().unwrap_or(&EmailStatus::Failed)).unwrap()};
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder()
+        .projection(doc! {"html": 0, "text": 0})
+        .sort(doc! {"created_at": -1})
+        .skip((page - 1) * page_size)
+        .limit(page_size as i64)
+        .build();
+
+    match db.collection::<bson::Document>("outbox").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<bson::Document>>().await {
+            Ok(emails) => HttpResponse::Ok().json(emails),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch emails: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch emails: {}", e)})),
+    }
+}
+
+// Gives a failed email a fresh set of attempts
+async fn retry_email(email_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::OutboxManage).await {
+        return response;
+    }
+    let email_id = match ObjectId::from_str(&email_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid email ID"})),
+    };
+    let update = doc! {"$set": {"status": "Pending", "attempts": 0, "next_attempt_at": Utc::now().timestamp()}};
+    match db.collection::<OutgoingEmail>("outbox").update_one(doc! {"_id": email_id, "status": "Failed"}, update, None).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::NotFound().json(json!({"error":"No failed email with this ID"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Email queued again"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update email: {}", e)})),
+    }
+}
+
+pub fn email_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/email/unsubscribe")
+            .route(web::get().to(unsubscribe))
+            .route(web::post().to(unsubscribe))
+    )
+    .service(
+        web::resource("/email/preferences")
+            .route(web::get().to(fetch_email_preferences))
+            .route(web::post().to(update_email_preferences))
+    )
+    .service(
+        web::resource("/admin/outbox")
+            .route(web::get().to(fetch_outbox))
+    )
+    .service(
+        web::resource("/admin/outbox/{id}/retry")
+            .route(web::post().to(retry_email))
+    );
+}
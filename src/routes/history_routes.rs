@@ -0,0 +1,172 @@
+use std::{collections::HashMap, str::FromStr};
+
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, oid::ObjectId, Document}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{PostSummary, ReadingHistoryEntry, ReadingHistoryView, User};
+use crate::utils::{record_reading, AuthUser, FINISHED_PROGRESS};
+
+// Entries with their posts, skipping posts that were deleted or can no longer be seen
+async fn history_views(db: &Database, user_id: &str, entries: Vec<ReadingHistoryEntry>) -> Result<Vec<ReadingHistoryView>, HttpResponse> {
+    let ids: Vec<ObjectId> = entries.iter().map(|entry| entry.post_id).collect();
+    let filter = doc! {"_id": {"$in": ids}, "$or": [{"status": "Public"}, {"author": user_id, "status": {"$ne": "Deleted"}}]};
+    let options = FindOptions::builder().projection(PostSummary::projection()).build();
+    let mut posts: HashMap<ObjectId, PostSummary> = match db.collection::<PostSummary>("posts").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<PostSummary>>().await {
+            Ok(posts) => posts.into_iter().map(|post| (post.id, post)).collect(),
+            Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)}))),
+        },
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)}))),
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| posts.remove(&entry.post_id).map(|post| ReadingHistoryView::new(entry, post)))
+        .collect())
+}
+
+async fn find_entries(db: &Database, filter: Document, options: FindOptions) -> Result<Vec<ReadingHistoryEntry>, HttpResponse> {
+    match db.collection::<ReadingHistoryEntry>("reading_history").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(entries) => Ok(entries),
+            Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch history: {}", e)}))),
+        },
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch history: {}", e)}))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ProgressRequest {
+    progress: Option<u8>, // 0-100, leave out when the post was just opened
+}
+
+// Called by the client when a post is opened and then every now and then while it is read
+async fn record_progress(post_id: web::Path<String>, auth: AuthUser, request: web::Json<ProgressRequest>, db: web::Data<Database>) -> impl Responder {
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    if request.progress.map(|progress| progress > 100).unwrap_or(false) {
+        return HttpResponse::BadRequest().json(json!({"error":"Progress must be between 0 and 100"}));
+    }
+    match db.collection::<Document>("posts").count_documents(doc! {"_id": post_id}, None).await {
+        Ok(0) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)})),
+    }
+
+    match record_reading(&db, auth.id, post_id, request.progress).await {
+        Ok(recorded) => HttpResponse::Ok().json(json!({"recorded": recorded})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to record history: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+// Everything the user read, last read first
+async fn fetch_history(auth: AuthUser, query: web::Query<HistoryQuery>, db: web::Data<Database>) -> impl Responder {
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder()
+        .sort(doc! {"last_read_at": -1})
+        .skip((page - 1) * page_size)
+        .limit(page_size as i64)
+        .build();
+
+    let entries = match find_entries(&db, doc! {"user_id": auth.id_str()}, options).await {
+        Ok(entries) => entries,
+        Err(response) => return response,
+    };
+    match history_views(&db, &auth.id_str(), entries).await {
+        Ok(views) => HttpResponse::Ok().json(views),
+        Err(response) => response,
+    }
+}
+
+// Posts the user started but didn't finish, to pick up where they left off
+async fn continue_reading(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let filter = doc! {"user_id": auth.id_str(), "finished": false, "progress": {"$gt": 0, "$lt": FINISHED_PROGRESS as i32}};
+    let options = FindOptions::builder().sort(doc! {"last_read_at": -1}).limit(10).build();
+
+    let entries = match find_entries(&db, filter, options).await {
+        Ok(entries) => entries,
+        Err(response) => return response,
+    };
+    match history_views(&db, &auth.id_str(), entries).await {
+        Ok(views) => HttpResponse::Ok().json(views),
+        Err(response) => response,
+    }
+}
+
+async fn remove_from_history(post_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    match db.collection::<ReadingHistoryEntry>("reading_history").delete_one(doc! {"user_id": auth.id_str(), "post_id": post_id}, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Removed from history"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update history: {}", e)})),
+    }
+}
+
+async fn clear_history(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    match db.collection::<ReadingHistoryEntry>("reading_history").delete_many(doc! {"user_id": auth.id_str()}, None).await {
+        Ok(result) => HttpResponse::Ok().json(json!({"success":"History cleared", "deleted": result.deleted_count})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to clear history: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct HistorySettingsRequest {
+    paused: bool,
+}
+
+async fn fetch_history_settings(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    match auth.fetch(&db).await {
+        Ok(user) => HttpResponse::Ok().json(json!({"paused": user.history_paused})),
+        Err(response) => response,
+    }
+}
+
+// Pausing keeps what is already there, clearing is separate
+async fn update_history_settings(auth: AuthUser, request: web::Json<HistorySettingsRequest>, db: web::Data<Database>) -> impl Responder {
+    let update = doc! {"$set": {"history_paused": request.paused}};
+    match db.collection::<User>("users").update_one(doc! {"_id": auth.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Settings saved", "paused": request.paused})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save settings: {}", e)})),
+    }
+}
+
+pub fn history_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/history")
+            .route(web::get().to(fetch_history))
+    )
+    .service(
+        web::resource("/history/continue")
+            .route(web::get().to(continue_reading))
+    )
+    .service(
+        web::resource("/history/record/{id}")
+            .route(web::post().to(record_progress))
+    )
+    .service(
+        web::resource("/history/remove/{id}")
+            .route(web::post().to(remove_from_history))
+    )
+    .service(
+        web::resource("/history/clear")
+            .route(web::post().to(clear_history))
+    )
+    .service(
+        web::resource("/history/settings")
+            .route(web::get().to(fetch_history_settings))
+            .route(web::post().to(update_history_settings))
+    );
+}
@@ -1,5 +1,33 @@
 mod post_routes;
 mod user_routes;
+mod revision_routes;
+mod comment_routes;
+mod moderation_routes;
+mod notification_routes;
+mod realtime_routes;
+mod email_routes;
+mod bookmark_routes;
+mod history_routes;
+mod series_routes;
+mod contributor_routes;
+mod review_routes;
+mod admin_routes;
+mod analytics_routes;
+mod post_analytics_routes;
 
 pub use post_routes::post_routes;
-pub use user_routes::user_routes;
\ No newline at end of file
+pub use user_routes::user_routes;
+pub use revision_routes::revision_routes;
+pub use comment_routes::comment_routes;
+pub use moderation_routes::moderation_routes;
+pub use notification_routes::notification_routes;
+pub use realtime_routes::realtime_routes;
+pub use email_routes::email_routes;
+pub use bookmark_routes::bookmark_routes;
+pub use history_routes::history_routes;
+pub use series_routes::series_routes;
+pub use contributor_routes::contributor_routes;
+pub use review_routes::review_routes;
+pub use admin_routes::admin_routes;
+pub use analytics_routes::analytics_routes;
+pub use post_analytics_routes::post_analytics_routes;
\ No newline at end of file
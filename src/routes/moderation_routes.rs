@@ -0,0 +1,321 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId, Document}, options::{FindOneOptions, FindOptions}};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use chrono::Utc;
+
+use crate::types::{
+    AuthorProfile, Capability, Comment, CommentStatus, ModerationAction, ModerationLogEntry, Permission, Post, PostStatus, PostSummary,
+    Report, ReportReason, ReportStatus, ReportTarget, User,
+};
+use crate::utils::{ban_user, has_capability, train_spam_model, AuthUser, Broker};
+use super::comment_routes::set_comment_status;
+
+// Adds an entry to the audit trail of moderator actions
+pub(crate) async fn log_moderation(
+    db: &Database,
+    moderator_id: String,
+    action: ModerationAction,
+    target_type: ReportTarget,
+    target_id: ObjectId,
+    report_id: Option<ObjectId>,
+    note: Option<String>,
+) -> mongodb::error::Result<()> {
+    let entry = ModerationLogEntry {
+        id: ObjectId::new(),
+        moderator_id,
+        action,
+        target_type,
+        target_id,
+        report_id,
+        note,
+        created_at: Utc::now(),
+    };
+    db.collection::<ModerationLogEntry>("moderation_log").insert_one(&entry, None).await.map(|_| ())
+}
+
+#[derive(Deserialize)]
+struct ReportRequest {
+    target_type: ReportTarget,
+    target_id: String,
+    reason: ReportReason,
+    details: Option<String>,
+}
+
+// What a report is about, None when it doesn't exist (anymore)
+async fn find_target(db: &Database, target_type: ReportTarget, target_id: ObjectId) -> mongodb::error::Result<Option<Value>> {
+    let filter = doc! {"_id": target_id};
+    let target = match target_type {
+        ReportTarget::Post => {
+            let options = FindOneOptions::builder().projection(PostSummary::projection()).build();
+            db.collection::<PostSummary>("posts").find_one(filter, options).await?.map(|post| json!(post))
+        }
+        ReportTarget::Comment => db.collection::<Comment>("comments").find_one(filter, None).await?.map(|comment| json!({
+            "_id": comment.id,
+            "post_id": comment.post_id,
+            "author_id": comment.author_id,
+            "content": comment.content,
+            "status": comment.status,
+        })),
+        ReportTarget::User => {
+            let options = FindOneOptions::builder().projection(AuthorProfile::projection()).build();
+            db.collection::<AuthorProfile>("users").find_one(filter, options).await?.map(|user| json!(user))
+        }
+    };
+    Ok(target)
+}
+
+// Any signed in reader can report a post, a comment or a user, once per target
+async fn create_report(auth: AuthUser, request: web::Json<ReportRequest>, db: web::Data<Database>) -> impl Responder {
+    let target_id = match ObjectId::from_str(&request.target_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid target ID"})),
+    };
+    if request.details.as_ref().map(|details| details.chars().count() > 1000).unwrap_or(false) {
+        return HttpResponse::BadRequest().json(json!({"error": "Details can be at most 1000 characters"}));
+    }
+    if request.reason == ReportReason::Other && request.details.as_deref().map(str::trim).unwrap_or("").is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "Tell us what is wrong in `details`"}));
+    }
+
+    match find_target(&db, request.target_type, target_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Reported content not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch reported content: {}", e)})),
+    }
+
+    let reports = db.collection::<Report>("reports");
+    let existing = doc! {"target_id": target_id, "reporter_id": auth.id_str(), "status": "Open"};
+    match reports.count_documents(existing, None).await {
+        Ok(0) => {}
+        Ok(_) => return HttpResponse::Conflict().json(json!({"error": "You already reported this"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save report: {}", e)})),
+    }
+
+    let report = Report::new(request.target_type, target_id, auth.id_str(), request.reason.clone(), request.details.clone());
+    match reports.insert_one(&report, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success": "Report received", "report": report})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save report: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    status: Option<ReportStatus>, // Open when missing
+    target_type: Option<ReportTarget>,
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+// The queue, oldest first, every report with what it is about
+async fn fetch_reports(auth: AuthUser, query: web::Query<ReportQuery>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::ReportManage).await {
+        return response;
+    }
+
+    let mut filter = doc! {"status": bson::to_bson(query.status.as_ref().unwrap_or(&ReportStatus::Open)).unwrap()};
+    if let Some(target_type) = &query.target_type {
+        filter.insert("target_type", bson::to_bson(target_type).unwrap());
+    }
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder()
+        .sort(doc! {"created_at": 1})
+        .skip((page - 1) * page_size)
+        .limit(page_size as i64)
+        .build();
+
+    let reports: Vec<Report> = match db.collection::<Report>("reports").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(reports) => reports,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch reports: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch reports: {}", e)})),
+    };
+
+    let mut queue = vec![];
+    for report in reports {
+        let target = match find_target(&db, report.target_type, report.target_id).await {
+            Ok(target) => target,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch reported content: {}", e)})),
+        };
+        queue.push(json!({"report": report, "target": target}));
+    }
+    HttpResponse::Ok().json(queue)
+}
+
+#[derive(Deserialize)]
+struct ResolveRequest {
+    action: ModerationAction,
+    note: Option<String>,
+}
+
+// Bans for good, an admin can lift or shorten it through /admin/users/{id}/unban and /ban
+async fn ban_author(db: &Database, user_id: &str, moderator: &str, report: &Report) -> Result<(), HttpResponse> {
+    let user_id = match ObjectId::from_str(user_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error": "Anonymous authors can't be banned"}))),
+    };
+    let user = match db.collection::<User>("users").find_one(doc! {"_id": user_id}, None).await {
+        Ok(Some(user)) if user.permission == Permission::Banned => return Ok(()),
+        Ok(Some(user)) if user.permission != Permission::Admin => user,
+        Ok(_) => return Err(HttpResponse::BadRequest().json(json!({"error": "User not found or an admin"}))),
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)}))),
+    };
+    let reason = format!("Reported for {:?}", report.reason);
+    match ban_user(db, &user, moderator, reason, None).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::Conflict().json(json!({"error": "User's permission changed meanwhile, try again"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to ban user: {}", e)}))),
+    }
+}
+
+// Hides the reported post or comment, or bans the user behind it
+async fn take_action(db: &Database, broker: &Broker, moderator: &str, report: &Report, action: &ModerationAction) -> Result<(), HttpResponse> {
+    let internal = |e: mongodb::error::Error| HttpResponse::InternalServerError().json(json!({"error": format!("Failed to moderate: {}", e)}));
+    let gone = || HttpResponse::NotFound().json(json!({"error": "Reported content not found"}));
+
+    match (report.target_type, action) {
+        (_, ModerationAction::Dismiss) | (_, ModerationAction::Resolve) => Ok(()),
+        (ReportTarget::User, ModerationAction::Ban) => ban_author(db, &report.target_id.to_hex(), moderator, report).await,
+        (ReportTarget::Post, ModerationAction::Hide) | (ReportTarget::Post, ModerationAction::Ban) => {
+            let posts = db.collection::<Post>("posts");
+            let post = posts.find_one(doc! {"_id": report.target_id}, None).await.map_err(internal)?.ok_or_else(gone)?;
+            if *action == ModerationAction::Ban {
+                return ban_author(db, &post.author, moderator, report).await;
+            }
+            let update = doc! {"$set": {"status": bson::to_bson(&PostStatus::Hidden).unwrap()}};
+            posts.update_one(doc! {"_id": post.id}, update, None).await.map_err(internal)?;
+            Ok(())
+        }
+        (ReportTarget::Comment, ModerationAction::Hide) | (ReportTarget::Comment, ModerationAction::Ban) => {
+            let comment = db.collection::<Comment>("comments")
+                .find_one(doc! {"_id": report.target_id}, None)
+                .await
+                .map_err(internal)?
+                .ok_or_else(gone)?;
+            if *action == ModerationAction::Ban {
+                return ban_author(db, &comment.author_id, moderator, report).await;
+            }
+            set_comment_status(db, broker, &comment, &CommentStatus::Hidden).await?;
+            if report.reason == ReportReason::Spam {
+                train_spam_model(db, &comment.content, true);
+            }
+            Ok(())
+        }
+        _ => Err(HttpResponse::BadRequest().json(json!({"error": "This action can't be taken on this kind of report"}))),
+    }
+}
+
+// Acting on a report closes every open report about the same target
+async fn resolve_report(
+    report_id: web::Path<String>,
+    auth: AuthUser,
+    request: web::Json<ResolveRequest>,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let moderator = match auth.require_capability(&db, Capability::ReportManage).await {
+        Ok(moderator) => moderator,
+        Err(response) => return response,
+    };
+    if request.action == ModerationAction::Ban && !has_capability(&moderator, Capability::UserBan) {
+        return HttpResponse::Forbidden().json(json!({"error": "You are not allowed to ban users", "capability": Capability::UserBan}));
+    }
+    let report_id = match ObjectId::from_str(&report_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid report ID"})),
+    };
+    let reports = db.collection::<Report>("reports");
+    let report = match reports.find_one(doc! {"_id": report_id}, None).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Report not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch report: {}", e)})),
+    };
+    if report.status != ReportStatus::Open {
+        return HttpResponse::BadRequest().json(json!({"error": "Report is already closed"}));
+    }
+
+    if let Err(response) = take_action(&db, &broker, &auth.id_str(), &report, &request.action).await {
+        return response;
+    }
+
+    let status = match request.action {
+        ModerationAction::Dismiss => ReportStatus::Dismissed,
+        _ => ReportStatus::Resolved,
+    };
+    let update = doc! {"$set": {
+        "status": bson::to_bson(&status).unwrap(),
+        "resolved_at": Utc::now().timestamp(),
+        "resolved_by": auth.id_str(),
+        "action": bson::to_bson(&request.action).unwrap(),
+    }};
+    let closed = match reports.update_many(doc! {"target_id": report.target_id, "status": "Open"}, update, None).await {
+        Ok(result) => result.modified_count,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to close reports: {}", e)})),
+    };
+
+    let logged = log_moderation(&db, auth.id_str(), request.action.clone(), report.target_type, report.target_id, Some(report.id), request.note.clone()).await;
+    if let Err(e) = logged {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to log moderation: {}", e)}));
+    }
+
+    HttpResponse::Ok().json(json!({"success": "Report closed", "status": status, "closed_reports": closed}))
+}
+
+#[derive(Deserialize)]
+struct LogQuery {
+    moderator_id: Option<String>,
+    target_id: Option<String>,
+    page: Option<u64>,
+}
+
+// The audit trail, newest first
+async fn fetch_moderation_log(auth: AuthUser, query: web::Query<LogQuery>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::ReportManage).await {
+        return response;
+    }
+
+    let mut filter = Document::new();
+    if let Some(moderator_id) = &query.moderator_id {
+        filter.insert("moderator_id", moderator_id);
+    }
+    if let Some(target_id) = &query.target_id {
+        match ObjectId::from_str(target_id) {
+            Ok(id) => filter.insert("target_id", id),
+            Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid target ID"})),
+        };
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder().sort(doc! {"created_at": -1}).skip((page - 1) * 50).limit(50).build();
+
+    match db.collection::<ModerationLogEntry>("moderation_log").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<ModerationLogEntry>>().await {
+            Ok(entries) => HttpResponse::Ok().json(entries),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch moderation log: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch moderation log: {}", e)})),
+    }
+}
+
+pub fn moderation_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/report")
+            .route(web::post().to(create_report))
+    )
+    .service(
+        web::resource("/admin/reports")
+            .route(web::get().to(fetch_reports))
+    )
+    .service(
+        web::resource("/admin/reports/{id}/resolve")
+            .route(web::post().to(resolve_report))
+    )
+    .service(
+        web::resource("/admin/moderation_log")
+            .route(web::get().to(fetch_moderation_log))
+    );
+}
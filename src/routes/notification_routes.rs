@@ -0,0 +1,136 @@
+use std::{collections::HashMap, str::FromStr};
+
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{AuthorProfile, Notification, NotificationPreferences, NotificationView, User};
+use crate::utils::AuthUser;
+
+#[derive(Deserialize)]
+struct NotificationQuery {
+    unread: Option<bool>, // only unread ones
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+// The signed in user's notifications, latest activity first
+async fn fetch_notifications(auth: AuthUser, query: web::Query<NotificationQuery>, db: web::Data<Database>) -> impl Responder {
+    let mut filter = doc! {"user_id": auth.id_str()};
+    if query.unread.unwrap_or(false) {
+        filter.insert("read", false);
+    }
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder()
+        .sort(doc! {"updated_at": -1})
+        .skip((page - 1) * page_size)
+        .limit(page_size as i64)
+        .build();
+
+    let notifications: Vec<Notification> = match db.collection::<Notification>("notifications").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(notifications) => notifications,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch notifications: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch notifications: {}", e)})),
+    };
+
+    // Profiles of the last three actors of every notification, in one query
+    let latest = |notification: &Notification| -> Vec<ObjectId> {
+        notification.actors.iter().rev().filter_map(|actor| ObjectId::from_str(actor).ok()).take(3).collect()
+    };
+    let ids: Vec<ObjectId> = notifications.iter().flat_map(latest).collect();
+    let options = FindOptions::builder().projection(AuthorProfile::projection()).build();
+    let profiles: HashMap<ObjectId, AuthorProfile> = match db.collection::<AuthorProfile>("users").find(doc! {"_id": {"$in": ids}}, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<AuthorProfile>>().await {
+            Ok(profiles) => profiles.into_iter().map(|profile| (profile.id, profile)).collect(),
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch users: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch users: {}", e)})),
+    };
+
+    let views: Vec<NotificationView> = notifications
+        .into_iter()
+        .map(|notification| {
+            let actors = latest(&notification).iter().filter_map(|id| profiles.get(id).cloned()).collect();
+            NotificationView::new(notification, actors)
+        })
+        .collect();
+    HttpResponse::Ok().json(views)
+}
+
+async fn unread_count(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    match db.collection::<Notification>("notifications").count_documents(doc! {"user_id": auth.id_str(), "read": false}, None).await {
+        Ok(count) => HttpResponse::Ok().json(json!({"unread": count})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to count notifications: {}", e)})),
+    }
+}
+
+async fn mark_read(notification_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let notification_id = match ObjectId::from_str(&notification_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid notification ID"})),
+    };
+    let result = db.collection::<Notification>("notifications")
+        .update_one(doc! {"_id": notification_id, "user_id": auth.id_str()}, doc! {"$set": {"read": true}}, None)
+        .await;
+
+    match result {
+        Ok(result) if result.matched_count == 0 => HttpResponse::NotFound().json(json!({"error": "Notification not found"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success": "Notification marked as read"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update notification: {}", e)})),
+    }
+}
+
+async fn mark_all_read(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let result = db.collection::<Notification>("notifications")
+        .update_many(doc! {"user_id": auth.id_str(), "read": false}, doc! {"$set": {"read": true}}, None)
+        .await;
+
+    match result {
+        Ok(result) => HttpResponse::Ok().json(json!({"success": "Notifications marked as read", "updated": result.modified_count})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update notifications: {}", e)})),
+    }
+}
+
+async fn fetch_preferences(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    match auth.fetch(&db).await {
+        Ok(user) => HttpResponse::Ok().json(user.notification_preferences),
+        Err(response) => response,
+    }
+}
+
+async fn update_preferences(auth: AuthUser, preferences: web::Json<NotificationPreferences>, db: web::Data<Database>) -> impl Responder {
+    let update = doc! {"$set": {"notification_preferences": bson::to_bson(&*preferences).unwrap()}};
+    match db.collection::<User>("users").update_one(doc! {"_id": auth.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success": "Preferences saved", "preferences": &*preferences})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save preferences: {}", e)})),
+    }
+}
+
+pub fn notification_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/notifications")
+            .route(web::get().to(fetch_notifications))
+    )
+    .service(
+        web::resource("/notifications/unread_count")
+            .route(web::get().to(unread_count))
+    )
+    .service(
+        web::resource("/notifications/read/{id}")
+            .route(web::post().to(mark_read))
+    )
+    .service(
+        web::resource("/notifications/read_all")
+            .route(web::post().to(mark_all_read))
+    )
+    .service(
+        web::resource("/notifications/preferences")
+            .route(web::get().to(fetch_preferences))
+            .route(web::post().to(update_preferences))
+    );
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId, Document}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{Capability, Post, PostAnalytics, PostComparison, PostStatsDay, ReaderStats, ReferrerCount};
+use crate::utils::AuthUser;
+use super::analytics_routes::{Range, RangeQuery};
+
+// Only the post's authors and people who can view the site's analytics see a post's numbers
+async fn can_view(db: &Database, auth: &AuthUser, post: &Post) -> Result<(), HttpResponse> {
+    if post.role_of(&auth.id_str()).is_some_and(|role| role.is_author()) {
+        return Ok(());
+    }
+    if auth.has_capability(db, Capability::AnalyticsView).await? {
+        return Ok(());
+    }
+    Err(HttpResponse::Forbidden().json(json!({"error":"Only the post's authors can see its analytics"})))
+}
+
+fn days_of(range: &Range) -> Document {
+    let days = range.days();
+    doc! {"$gte": days.first().cloned().unwrap_or_default(), "$lte": days.last().cloned().unwrap_or_default()}
+}
+
+async fn aggregate(db: &Database, collection: &str, pipeline: Vec<Document>) -> Result<Vec<Document>, HttpResponse> {
+    match db.collection::<Document>(collection).aggregate(pipeline, None).await {
+        Ok(cursor) => cursor.try_collect().await.map_err(|e| HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch analytics: {}", e)}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch analytics: {}", e)}))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReaderRow {
+    #[serde(rename = "_id")]
+    post_id: ObjectId,
+    unique_readers: i64,
+    finished_readers: i64,
+    average_progress: f64,
+}
+
+// Reading history of the posts, keyed by post
+async fn reader_stats(db: &Database, post_ids: &[ObjectId]) -> Result<HashMap<ObjectId, ReaderStats>, HttpResponse> {
+    let pipeline = vec![
+        doc! {"$match": {"post_id": {"$in": post_ids}}},
+        doc! {"$group": {
+            "_id": "$post_id",
+            "unique_readers": {"$sum": 1},
+            "finished_readers": {"$sum": {"$cond": ["$finished", 1, 0]}},
+            "average_progress": {"$avg": "$progress"},
+        }},
+    ];
+    let rows = aggregate(db, "reading_history", pipeline).await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| bson::from_document::<ReaderRow>(row).ok())
+        .map(|row| (row.post_id, ReaderStats {
+            unique_readers: row.unique_readers,
+            finished_readers: row.finished_readers,
+            average_progress: (row.average_progress * 10.0).round() / 10.0,
+        }))
+        .collect())
+}
+
+// Daily views, reactions, comments and new readers, readers and referrers of a post
+async fn post_analytics(post_id: web::Path<String>, auth: AuthUser, query: web::Query<RangeQuery>, db: web::Data<Database>) -> impl Responder {
+    let range = match query.range() {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+    let id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    let post = match db.collection::<Post>("posts").find_one(doc! {"_id": id}, None).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch post: {}", e)})),
+    };
+    if let Err(response) = can_view(&db, &auth, &post).await {
+        return response;
+    }
+
+    let stats = match db.collection::<PostStatsDay>("post_stats").find(doc! {"post_id": id, "date": days_of(&range)}, None).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<PostStatsDay>>().await {
+            Ok(stats) => stats,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch analytics: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch analytics: {}", e)})),
+    };
+    let mut by_day: HashMap<String, PostStatsDay> = stats.into_iter().map(|day| (day.date.clone(), day)).collect();
+    let daily = range.days().into_iter().map(|date| by_day.remove(&date).unwrap_or(PostStatsDay { date, ..Default::default() })).collect();
+
+    let pipeline = vec![
+        doc! {"$match": {"post_id": id, "date": days_of(&range)}},
+        doc! {"$group": {"_id": "$domain", "views": {"$sum": "$views"}}},
+        doc! {"$sort": {"views": -1, "_id": 1}},
+        doc! {"$limit": range.limit},
+        doc! {"$project": {"_id": 0, "domain": "$_id", "views": 1}},
+    ];
+    let referrers = match aggregate(&db, "post_referrers", pipeline).await {
+        Ok(rows) => rows.into_iter().filter_map(|row| bson::from_document::<ReferrerCount>(row).ok()).collect(),
+        Err(response) => return response,
+    };
+    let readers = match reader_stats(&db, &[id]).await {
+        Ok(mut readers) => readers.remove(&id).unwrap_or_default(),
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(PostAnalytics {
+        post_id: id,
+        title: post.title,
+        views: post.views,
+        likes: post.likes.len(),
+        dislikes: post.dislikes.len(),
+        comment_count: post.comment_count,
+        readers,
+        daily,
+        referrers,
+    })
+}
+
+#[derive(Deserialize)]
+struct AuthorQuery {
+    author: Option<String>, // the signed in user by default
+}
+
+// An author's public posts side by side, the most viewed in the range first
+async fn compare_posts(auth: AuthUser, query: web::Query<AuthorQuery>, range: web::Query<RangeQuery>, db: web::Data<Database>) -> impl Responder {
+    let range = match range.range() {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+    let author = query.author.clone().unwrap_or_else(|| auth.id_str());
+    if author != auth.id_str() {
+        if let Err(response) = auth.require_capability(&db, Capability::AnalyticsView).await {
+            return response;
+        }
+    }
+
+    let filter = doc! {
+        "status": "Public",
+        "$or": [
+            {"author": &author},
+            {"contributors": {"$elemMatch": {"user_id": &author, "role": "CoAuthor"}}},
+        ],
+    };
+    let options = FindOptions::builder().sort(doc! {"published_at": -1}).limit(100).build();
+    let posts = match db.collection::<Post>("posts").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Post>>().await {
+            Ok(posts) => posts,
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+    };
+    let ids: Vec<ObjectId> = posts.iter().map(|post| post.id).collect();
+
+    let pipeline = vec![
+        doc! {"$match": {"post_id": {"$in": &ids}, "date": days_of(&range)}},
+        doc! {"$group": {"_id": "$post_id", "views": {"$sum": "$views"}}},
+    ];
+    let views_in_range: HashMap<ObjectId, i64> = match aggregate(&db, "post_stats", pipeline).await {
+        Ok(rows) => rows
+            .into_iter()
+            .filter_map(|row| Some((row.get_object_id("_id").ok()?, bson::from_bson(row.get("views")?.clone()).ok()?)))
+            .collect(),
+        Err(response) => return response,
+    };
+    let mut readers = match reader_stats(&db, &ids).await {
+        Ok(readers) => readers,
+        Err(response) => return response,
+    };
+
+    let mut rows: Vec<PostComparison> = posts
+        .into_iter()
+        .map(|post| {
+            let id = post.id;
+            PostComparison {
+                post_id: id,
+                title: post.title,
+                published_at: post.published_at,
+                views: post.views,
+                views_in_range: views_in_range.get(&id).copied().unwrap_or(0),
+                likes: post.likes.len(),
+                comment_count: post.comment_count,
+                readers: readers.remove(&id).unwrap_or_default(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| b.views_in_range.cmp(&a.views_in_range).then(b.views.cmp(&a.views)));
+    HttpResponse::Ok().json(rows)
+}
+
+pub fn post_analytics_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/post/analytics/compare")
+            .route(web::get().to(compare_posts))
+    )
+    .service(
+        web::resource("/post/analytics/{id}")
+            .route(web::get().to(post_analytics))
+    );
+}
@@ -1,16 +1,21 @@
 use std::{str::FromStr, io::Write, collections::HashMap};
 
-use actix_web::{web::{self}, HttpResponse, Responder};
+use actix_web::{web::{self}, HttpRequest, HttpResponse, Responder};
 use actix_multipart::Multipart;
 use std::collections::HashSet;
 use mongodb::{Database, bson::{self, doc, from_document, oid::ObjectId, Regex, Document, document, Bson}, options::{FindOneAndUpdateOptions, ReturnDocument, FindOptions, FindOneOptions}, Collection};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
 use uuid::Uuid;
+use chrono::Utc;
 
-use crate::{types::Post, utils::upload_image_to_s3, types::Content, types::{PostStatus, Comment}, types::Tag, types::{DEFAULT_POST_IMAGE, User}};
+use crate::{types::Post, utils::upload_image_to_s3, types::Content, types::{Contributor, ContributorRole, ContributorView, PostStatus, PostSummary}, types::Tag, types::{DEFAULT_POST_IMAGE, User}, types::{Capability, ModerationAction, ReportTarget}};
 
-use crate::utils::calculate_reading_time;
+use crate::utils::{has_capability, needs_review, on_post_published, prepare_content, record_post_event, record_reading, record_view, schedule_or_publish, submit_for_review, train_spam_model, AuthUser, Broker, ContentFilterPipeline, PostEvent, RateLimit, Submission, Verdict};
+use super::series_routes::series_navigation;
+use super::contributor_routes::contributor_views;
+use super::revision_routes::record_revision;
+use super::moderation_routes::log_moderation;
 use futures::{StreamExt, TryStreamExt};
 
 
@@ -57,55 +62,70 @@ async fn upload_image(mut payload: Multipart) -> impl Responder {
 #[derive(Deserialize, Clone)]
 struct CreatePostRequest {
     title: String,
-    author: String,
     image: String,
     content: Content,
     status: PostStatus,
     tags: Vec<String>,
+    publish_at: Option<i64>, // unix seconds, schedules a public post for later
+    language: Option<String>, // "tr" when missing
 }
 
-async fn create_post( post_req: web::Json<CreatePostRequest>, db: web::Data<Database>)->impl Responder {
-    fn is_valid_objectid(id: &str) -> bool {
-        if let Ok(_) = ObjectId::from_str(id) {
-            true
-        } else {
-            false
-        }
-    }
-
-    if !is_valid_objectid(&post_req.author.clone()) {
-        return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"}));
-    }
+// A two or three letter code like "tr" or "en", languages without their own reading speed get the default one
+fn valid_language(language: &str) -> bool {
+    (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_lowercase())
+}
 
+// The signed in user becomes the post's owner
+async fn create_post(post_req: web::Json<CreatePostRequest>, auth: AuthUser, filters: web::Data<ContentFilterPipeline>, broker: web::Data<Broker>, db: web::Data<Database>)->impl Responder {
     let user_collection = db.collection::<User>("users");
+    let user_filter = doc! {"_id": auth.id};
 
-    let user_id = ObjectId::from_str(&post_req.author.clone()).unwrap();    
-
-    let user_filter = doc! {"_id": user_id};
-    
-
-    let user = match user_collection.find_one(user_filter.clone(), None).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return HttpResponse::BadRequest().body(format!(
-                "Error creating post: user with id {} not found",
-                &post_req.author
-            ))
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().body(format!(
-                "Error creating post: unable to query user: {}",
-                e
-            ))
-        }
+    let user = match auth.fetch(&db).await {
+        Ok(user) => user,
+        Err(response) => return response,
     };
 
-
+    if !has_capability(&user, Capability::PostCreate) {
+        return HttpResponse::Forbidden().json(json!({"error":"You are not allowed to create posts", "capability": Capability::PostCreate}));
+    }
 
     let mut post = post_req.clone();
-    let content_html = &post.content.html;
-    let reading_time =  calculate_reading_time(&content_html);
-    let new_post = Post::new(post.title, post.author, post.image, post.content, post.status, post.tags, reading_time as u32);
+    // Review, moderation and deletion states are only reached through their own endpoints
+    if !matches!(post.status, PostStatus::Draft | PostStatus::Private | PostStatus::Public | PostStatus::Scheduled) {
+        return HttpResponse::BadRequest().json(json!({"error":"A new post can only be a draft, private, public or scheduled"}));
+    }
+    // Never trust client html, it is rendered from markdown or sanitized here
+    let language = post.language.unwrap_or_else(|| "tr".to_string());
+    if !valid_language(&language) {
+        return HttpResponse::BadRequest().json(json!({"error":"Invalid language"}));
+    }
+    if matches!(post.status, PostStatus::Public | PostStatus::Scheduled) && !has_capability(&user, Capability::PostPublish) {
+        return HttpResponse::Forbidden().json(json!({"error":"You are not allowed to publish posts", "capability": Capability::PostPublish}));
+    }
+    let prepared = prepare_content(post.content, &language);
+    let mut new_post = Post::new(post.title, user.id.to_hex(), post.image, prepared.content.clone(), post.status, post.tags, prepared.read_time);
+    prepared.apply_to(&mut new_post);
+
+    let outcome = match filters.check(&db, &Submission::post(&new_post)).await {
+        Ok(outcome) => outcome,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error checking post: {}", e)),
+    };
+    match outcome.verdict {
+        Verdict::Allow => {}
+        Verdict::Quarantine(_) => {
+            new_post.status = PostStatus::Quarantined;
+            new_post.published_at = None;
+            new_post.quarantine_reasons = outcome.reasons;
+        }
+        Verdict::Reject(reason) => return HttpResponse::BadRequest().json(json!({"error": reason})),
+    }
+    if new_post.status == PostStatus::Public || new_post.status == PostStatus::Scheduled {
+        if needs_review(&user) {
+            submit_for_review(&mut new_post, post.publish_at, user.id.to_hex());
+        } else {
+            schedule_or_publish(&mut new_post, post.publish_at);
+        }
+    }
     let post_doc = bson::to_document(&new_post).unwrap();
     println!("POST DOC!, {}",post_doc);
     let result = db.collection("posts").insert_one(post_doc, None).await;
@@ -119,6 +139,14 @@ async fn create_post( post_req: web::Json<CreatePostRequest>, db: web::Data<Data
             if let Err(e) = update_result {
                 return HttpResponse::BadRequest().body(format!("Error updating user: {}", e));
             }
+            if let Err(e) = record_revision(&db, &new_post, new_post.author.clone(), None).await {
+                return HttpResponse::InternalServerError().body(format!("Error saving revision: {}", e));
+            }
+            if new_post.status == PostStatus::Public {
+                if let Err(e) = on_post_published(&db, &broker, &new_post).await {
+                    return HttpResponse::InternalServerError().body(format!("Error publishing post: {}", e));
+                }
+            }
             HttpResponse::Ok().json(json!({"Post": post}))
         },
         Err(e) => HttpResponse::InternalServerError().body(format!("Error creating post: {}", e))
@@ -135,12 +163,20 @@ impl AsRef<FetchOptions> for FetchOptions {
     }
 }
 
+// Whether the user is the author or a contributor of the post document
+fn works_on(doc: &Document, user_id: &str) -> bool {
+    let contributors: Vec<Contributor> = doc.get("contributors").cloned().and_then(|c| bson::from_bson(c).ok()).unwrap_or_default();
+    doc.get_str("author") == Ok(user_id) || contributors.iter().any(|contributor| contributor.user_id == user_id)
+}
+
 async fn fetch_post_by_id(
+    req: HttpRequest,
     Post_id: web::Path<String>,
+    auth: Option<AuthUser>,
     query: web::Query<HashMap<String, String>>,
     db: web::Data<Database>,
 ) -> impl Responder {
-    let collection = db.collection("posts");
+    let collection = db.collection::<Document>("posts");
 
     fn is_valid_objectid(id: &str) -> bool {
         if let Ok(_) = ObjectId::from_str(id) {
@@ -160,9 +196,10 @@ async fn fetch_post_by_id(
 
     // If the `fields` field is present in the query string,
     // create a projection document to fetch only the specified fields.
-    if let Some(fields) = query.get("fields") {
-        let field_vec: Vec<String> = fields.split(",").map(|s| s.to_owned()).collect();
-        let mut projection = doc! {};
+    // Who may see the post is decided on its status and contributors, so those are always fetched.
+    let requested: Option<Vec<String>> = query.get("fields").map(|fields| fields.split(",").map(|s| s.to_owned()).collect());
+    if let Some(field_vec) = &requested {
+        let mut projection = doc! {"status": 1, "author": 1, "contributors": 1};
         for field in field_vec {
             projection.insert(field, 1);
         }
@@ -171,10 +208,76 @@ async fn fetch_post_by_id(
 
     match collection.find_one(doc! {"_id": id}, options).await {
         Ok(result) => {
-            if let Some(doc) = result {
+            if let Some(mut doc) = result {
+                // Posts that aren't public are only shown to the people working on them and to reviewers and moderators
+                if doc.get_str("status") != Ok("Public") {
+                    let user = match &auth {
+                        Some(auth) => match auth.fetch(&db).await {
+                            Ok(user) => Some(user),
+                            Err(response) => return response,
+                        },
+                        None => None,
+                    };
+                    let allowed = user.is_some_and(|user| {
+                        works_on(&doc, &user.id.to_hex())
+                            || has_capability(&user, Capability::PostReview)
+                            || has_capability(&user, Capability::PostModerate)
+                    });
+                    if !allowed {
+                        return HttpResponse::NotFound().json(json!({"error":"Post not found"}));
+                    }
+                }
+                // Only readers count as views, not the post's own authors and contributors checking on it
+                let reader = !auth.as_ref().is_some_and(|auth| works_on(&doc, &auth.id_str()));
+                if doc.get_str("status") == Ok("Public") && reader {
+                    let referrer = req.headers().get("referer").and_then(|value| value.to_str().ok());
+                    if let Err(e) = record_view(&db, id, auth.as_ref().map(AuthUser::id_str), referrer).await {
+                        log::error!("failed to record view of post {}: {}", id, e);
+                    }
+                }
+                // Signed in readers of public posts get the post in their reading history
+                if let (Some(auth), Ok("Public")) = (&auth, doc.get_str("status")) {
+                    if let Err(e) = record_reading(&db, auth.id, id, None).await {
+                        log::error!("failed to record reading history of post {}: {}", id, e);
+                    }
+                }
+                // Parts of a series link to the previous and next part
+                let navigation = match doc.get_object_id("series_id") {
+                    Ok(series_id) => match series_navigation(&db, series_id, id).await {
+                        Ok(navigation) => navigation,
+                        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch series: {}", e)})),
+                    },
+                    Err(_) => None,
+                };
+                // Everyone working on the post, and the ones credited as its authors
+                let contributors = match doc.get_str("author") {
+                    Ok(owner) => {
+                        let contributors: Vec<Contributor> = doc.get("contributors").cloned().and_then(|c| bson::from_bson(c).ok()).unwrap_or_default();
+                        match contributor_views(&db, owner, &contributors).await {
+                            Ok(views) => Some(views),
+                            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch contributors: {}", e)})),
+                        }
+                    }
+                    Err(_) => None,
+                };
+                if let Some(field_vec) = &requested {
+                    for field in ["status", "author", "contributors"] {
+                        if !field_vec.iter().any(|requested| requested == field) {
+                            doc.remove(field);
+                        }
+                    }
+                }
                 // Deserialize the Post document to a Post struct
                 let document: serde_json::Value  = from_document(doc).unwrap();
-                let post_json: Value = serde_json::to_value(document).unwrap();
+                let mut post_json: Value = serde_json::to_value(document).unwrap();
+                if let (Some(navigation), Some(post)) = (navigation, post_json.as_object_mut()) {
+                    post.insert("series".to_string(), serde_json::to_value(navigation).unwrap());
+                }
+                if let (Some(contributors), Some(post)) = (contributors, post_json.as_object_mut()) {
+                    let authors: Vec<&ContributorView> = contributors.iter().filter(|view| view.role.is_author()).collect();
+                    post.insert("authors".to_string(), serde_json::to_value(authors).unwrap());
+                    post.insert("contributors".to_string(), serde_json::to_value(&contributors).unwrap());
+                }
 
                 // Return the Post as a JSON response
                 HttpResponse::Ok().json(post_json)
@@ -197,12 +300,36 @@ struct SearchParams {
     author: Option<String>,
     content: Option<String>,
     date: Option<i64>,
+    full: Option<bool>, // whole posts instead of summaries
+}
+
+// Listings return `PostSummary`s unless the whole posts are asked for with `full=true`
+async fn list_posts(db: &Database, query: Document, mut options: FindOptions, full: bool) -> HttpResponse {
+    async fn collect<T>(db: &Database, query: Document, options: FindOptions) -> HttpResponse
+    where
+        T: DeserializeOwned + Serialize + Unpin + Send + Sync,
+    {
+        match db.collection::<T>("posts").find(query, options).await {
+            Ok(cursor) => {
+                let posts: Vec<T> = cursor.filter_map(|result| async { result.ok() }).collect().await;
+                HttpResponse::Ok().json(posts)
+            }
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+        }
+    }
+
+    if full {
+        collect::<Post>(db, query, options).await
+    } else {
+        options.projection = Some(PostSummary::projection());
+        collect::<PostSummary>(db, query, options).await
+    }
 }
 
 
 async fn fetch_all(params: web::Query<SearchParams>,page: web::Path<i32>, db: web::Data<Database>) -> impl Responder {
     
-    let mut query = doc! {};
+    let mut query = doc! {"status": "Public"};
 
     if let Some(title) = &params.title {
         let regex_str = format!(".*{}.*", title);
@@ -229,269 +356,423 @@ async fn fetch_all(params: web::Query<SearchParams>,page: web::Path<i32>, db: we
     // Define the number of documents to skip and the number of documents to return
     let page_size = 5;
 
-    let collection = db.collection::<Post>("posts");
-    let allah = collection.find(query, None).await.unwrap();
-    let skip_size = (page.into_inner() - 1) * page_size;
-    let posts: Vec<Post> = allah
-        .skip((skip_size as u64).try_into().unwrap())
-        .take((page_size as u64).try_into().unwrap())
-        .filter_map(|result| async {
-            match result {
-                Ok(post) => Some(post),
-                Err(_) => None,
-            }
-        })
-        .collect().await;
+    let skip_size = (page.into_inner().max(1) - 1) * page_size;
+    let options = FindOptions::builder()
+        .sort(doc! {"published_at": -1})
+        .skip(skip_size as u64)
+        .limit(page_size as i64)
+        .build();
 
-    HttpResponse::Ok().json(posts)
+    list_posts(&db, query, options, params.full.unwrap_or(false)).await
 }
 
+// Fields only the owner may change, the rest is open to everyone who can edit the post
+const OWNER_FIELDS: [&str; 4] = ["status", "require_comment_approval", "allow_anonymous_comments", "image"];
+
 async fn update_post(
     user_id: web::Path<String>,
     new_data: web::Json<HashMap<String, Value>>,
+    auth: AuthUser,
     db: web::Data<Database>,
 ) -> impl Responder {
     let collection = db.collection::<Post>("posts");
 
-    fn is_valid_objectid(id: &str) -> bool {
-        ObjectId::from_str(id).is_ok()
+    if new_data.contains_key("author") || new_data.keys().any(|key| key == "contributors" || key.starts_with("contributors.")) {
+        return HttpResponse::BadRequest().json(json!({"error":"Use /post/contributors/{id} to manage who works on a post"}));
     }
-
-    if !is_valid_objectid(&user_id) {
-        return HttpResponse::BadRequest().json(json!({"error":"Invalid post ID"}));
+    let owner_only = new_data.keys().any(|key| OWNER_FIELDS.contains(&key.split('.').next().unwrap_or_default()));
+    let allowed: fn(&ContributorRole) -> bool = if owner_only { |role| *role == ContributorRole::Owner } else { ContributorRole::can_edit };
+    let (post, _) = match find_post_for(&db, &user_id, &auth, allowed).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if post.status == PostStatus::Rejected && new_data.contains_key("status") {
+        return HttpResponse::Forbidden().json(json!({"error":"Post was rejected by an editor"}));
+    }
+    // Hidden and deleted posts are locked, a moderator can restore hidden ones
+    if post.status.is_locked() {
+        return HttpResponse::Forbidden().json(json!({"error":"Post was taken down or deleted"}));
     }
-
     let id = ObjectId::from_str(&user_id).unwrap();
 
+    // Going public has side-effects, it must go through /post/publish.
+    // Only a moderator takes a post in or out of quarantine.
+    let publishes = matches!(new_data.get("status").and_then(|status| status.as_str()), Some("Public") | Some("Scheduled"));
+    if matches!(new_data.get("status").and_then(|status| status.as_str()), Some("Quarantined")) || new_data.contains_key("quarantine_reasons") {
+        return HttpResponse::Forbidden().json(json!({"error":"Quarantine is managed by moderators"}));
+    }
+    if matches!(new_data.get("status").and_then(|status| status.as_str()), Some("Hidden")) {
+        return HttpResponse::Forbidden().json(json!({"error":"Only a moderator can take a post down"}));
+    }
+    if publishes || new_data.contains_key("published_at") || new_data.contains_key("scheduled_at") {
+        return HttpResponse::BadRequest().json(json!({"error":"Use /post/publish/{id} to publish or schedule a post"}));
+    }
+    if matches!(new_data.get("status").and_then(|status| status.as_str()), Some("InReview") | Some("ChangesRequested") | Some("Rejected")) || new_data.contains_key("review_history") {
+        return HttpResponse::BadRequest().json(json!({"error":"Use /post/publish/{id} to submit a post for review"}));
+    }
+    if new_data.contains_key("series_id") {
+        return HttpResponse::BadRequest().json(json!({"error":"Use /series/{id}/add to add a post to a series"}));
+    }
+
     let mut update_fields = doc! {};
 
-    for (key, value) in new_data.iter() {
-        if key.contains('.') {
-            let mut parts = key.split('.').rev();
-            let last_key = parts.next().unwrap();
-    
-            // Check if the last key is an array element
-            if last_key.starts_with('[') && last_key.ends_with(']') {
-                let array_key = &last_key[1..last_key.len() - 1];
-                let mut array_doc = doc! {};
-                if let Ok(index) = array_key.parse::<usize>() {
-                    // If the array element is an integer index, use the $ positional operator
-                    let mut array_update = doc! {};
-                    array_update.insert("$", bson::to_bson(value).unwrap_or(Bson::Null));
-                    array_doc.insert(index.to_string(), array_update);
-                    let mut doc = doc! {};
-                    for part in parts {
-                        let mut new_doc = doc! {part: doc};
-                        doc = new_doc;
-                    }
-                    doc.insert(last_key.to_string(), array_doc);
-                    update_fields.insert(key.to_string(), doc);
-                } else {
-                    // If the array element is a string key, update the entire array element
-                    array_doc.insert(last_key.to_string(), bson::to_bson(value).unwrap_or(Bson::Null));
-                    let mut doc = doc! {};
-                    for part in parts {
-                        let mut new_doc = doc! {part: doc};
-                        doc = new_doc;
-                    }
-                    doc.insert(array_key.to_string(), array_doc);
-                    update_fields.insert(key.to_string(), doc);
-                }
-            } else {
-                // If the last key is not an array element, update the entire field
-                update_fields.insert(key.to_string(), bson::to_bson(value).unwrap_or(Bson::Null));
+    // Content is re-rendered as a whole, merge the changed parts into the stored content first.
+    // The reading metrics depend on the language, so changing it re-renders too.
+    let content_keys: Vec<&String> = new_data.keys().filter(|key| *key == "content" || key.starts_with("content.")).collect();
+    let new_language = match new_data.get("language") {
+        Some(language) => match language.as_str().filter(|language| valid_language(language)) {
+            Some(language) => Some(language),
+            None => return HttpResponse::BadRequest().json(json!({"error":"Invalid language"})),
+        },
+        None => None,
+    };
+    if !content_keys.is_empty() || new_language.is_some() {
+        let mut content = post.content.clone();
+        for key in content_keys {
+            let value = &new_data[key];
+            match key.as_str() {
+                "content" => match serde_json::from_value::<Content>(value.clone()) {
+                    Ok(new_content) => content = new_content,
+                    Err(e) => return HttpResponse::BadRequest().json(json!({"error": format!("Invalid content: {}", e)})),
+                },
+                "content.markdown" => content.markdown = value.as_str().unwrap_or_default().to_string(),
+                "content.html" => content.html = value.as_str().unwrap_or_default().to_string(),
+                _ => return HttpResponse::BadRequest().json(json!({"error": format!("Unknown content field: {}", key)})),
             }
-        } else {
-            // If the key does not contain a dot, update the entire field
-            update_fields.insert(key.to_string(), bson::to_bson(value).unwrap_or(Bson::Null));
         }
+        let language = new_language.unwrap_or(&post.language);
+        update_fields.extend(prepare_content(content, language).to_update());
     }
-    
+
+    // Everything else is checked against what it may be set to, counters and computed fields are never set from here
+    for (key, value) in new_data.iter() {
+        if key == "content" || key.starts_with("content.") || key == "language" {
+            continue;
+        }
+        let valid = match key.as_str() {
+            "title" | "image" => value.is_string(),
+            "tags" => serde_json::from_value::<Vec<String>>(value.clone()).is_ok(),
+            "require_comment_approval" | "allow_anonymous_comments" => value.is_boolean(),
+            "status" => serde_json::from_value::<PostStatus>(value.clone()).is_ok(),
+            _ => return HttpResponse::BadRequest().json(json!({"error": format!("{} can't be changed", key)})),
+        };
+        if !valid {
+            return HttpResponse::BadRequest().json(json!({"error": format!("Invalid value for {}", key)}));
+        }
+        update_fields.insert(key.to_string(), bson::to_bson(value).unwrap_or(Bson::Null));
+    }
+
+    // Title and content changes are kept as revisions
+    let content_changed = new_data.keys().any(|key| key == "title" || key == "content" || key.starts_with("content."));
+    if content_changed {
+        update_fields.insert("updated_at", Utc::now().timestamp());
+    }
+
     println!("update => {:?}",update_fields);
     let update_doc = doc! {"$set": update_fields};
-    let result = collection.update_one(doc! {"_id": id}, update_doc, None).await;
+    let mut filter = doc! {"_id": id, "status": {"$nin": ["Hidden", "Deleted"]}};
+    if new_data.contains_key("status") {
+        filter.insert("status", doc! {"$nin": ["Quarantined", "Hidden", "Deleted"]});
+    }
+    let result = collection.update_one(filter, update_doc, None).await;
 
     match result {
-        Ok(user) => HttpResponse::Ok().json(json!({"user": user})),
+        Ok(user) if user.matched_count == 0 => {
+            HttpResponse::Forbidden().json(json!({"error":"Post not found or waiting for a moderator"}))
+        }
+        Ok(user) => {
+            if content_changed {
+                match collection.find_one(doc! {"_id": id}, None).await {
+                    Ok(Some(post)) => {
+                        if let Err(e) = record_revision(&db, &post, auth.id_str(), None).await {
+                            return HttpResponse::InternalServerError().body(format!("Error saving revision: {}", e));
+                        }
+                    }
+                    Ok(None) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+                    Err(e) => return HttpResponse::InternalServerError().body(format!("Error saving revision: {}", e)),
+                }
+            }
+            HttpResponse::Ok().json(json!({"user": user}))
+        },
         Err(e) => HttpResponse::InternalServerError().body(format!("Error updating post: {}", e)),
     }
 }
 
+// The post, if the signed in user's role on it is `allowed`
+pub(crate) async fn find_post_for(db: &Database, post_id: &str, auth: &AuthUser, allowed: fn(&ContributorRole) -> bool) -> Result<(Post, ContributorRole), HttpResponse> {
+    let id = match ObjectId::from_str(post_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"}))),
+    };
+    match db.collection::<Post>("posts").find_one(doc! {"_id": id}, None).await {
+        Ok(Some(post)) => match post.role_of(&auth.id_str()) {
+            Some(role) if allowed(&role) => Ok((post, role)),
+            Some(_) => Err(HttpResponse::Forbidden().json(json!({"error":"Your role on this post doesn't allow this"}))),
+            None => Err(HttpResponse::Forbidden().json(json!({"error":"Only the author and contributors can do this"}))),
+        },
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error":"Post not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)}))),
+    }
+}
 
-
-
-#[derive(Deserialize, Clone)]
-struct AddCommentRequest{
-    author_id: Option<String>,
-    content: String
+#[derive(Deserialize)]
+struct AutosaveRequest {
+    title: Option<String>,
+    content: Option<Content>,
 }
-async fn add_comment(Post_id: web::Path<String>, comment_data: web::Json<AddCommentRequest>, db: web::Data<Database>)-> impl Responder{
 
-    let collection = db.collection::<Post>("posts");
-    fn is_valid_objectid(id: &str) -> bool {
-        if let Ok(_) = ObjectId::from_str(id) {
-            true
-        } else {
-            false
-        }
+// Saves a draft in place. Drafts change too often to keep every save as a revision,
+// the revision is recorded when the post is published.
+async fn autosave_draft(post_id: web::Path<String>, auth: AuthUser, draft: web::Json<AutosaveRequest>, db: web::Data<Database>) -> impl Responder {
+    let (post, _) = match find_post_for(&db, &post_id, &auth, ContributorRole::can_edit).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if post.status != PostStatus::Draft && post.status != PostStatus::ChangesRequested {
+        return HttpResponse::BadRequest().json(json!({"error":"Only drafts can be autosaved"}));
     }
-    if !is_valid_objectid(&Post_id) {
-        return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"}));
+
+    let now = Utc::now();
+    let mut update_fields = doc! {"updated_at": now.timestamp()};
+    if let Some(title) = &draft.title {
+        update_fields.insert("title", title);
     }
-    if comment_data.content.clone().len() > 400 || comment_data.content.clone().len() <=1 {
-        return HttpResponse::BadRequest().json(json!({"error":"comment content must be between 250 and 1 characters"}));
-    }
-    let post_id = ObjectId::from_str(&Post_id).unwrap();    
-    let comment = Comment::new(comment_data.author_id.clone(), comment_data.content.clone());
-    let filter = doc! {"_id": post_id};
-    let doc = bson::to_document(&comment).unwrap();
-    let update = doc! {"$push": {"comments": doc}};
-    let result = collection.update_one(filter, update, None).await;
-    
-    match result{
-        Ok(result)=>{HttpResponse::Ok().json(json!({"success":format!("{:?}",result)}))},
-        Err(error) => {
-            // Return an error message as a JSON response
-            HttpResponse::InternalServerError().json(format!("Failed to fetch Post: {}", error))
-        }
+    if let Some(content) = &draft.content {
+        update_fields.extend(prepare_content(content.clone(), &post.language).to_update());
     }
 
+    let result = db.collection::<Post>("posts")
+        .update_one(doc! {"_id": post.id, "status": {"$in": ["Draft", "ChangesRequested"]}}, doc! {"$set": update_fields}, None)
+        .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({"success": "Draft saved", "saved_at": now.timestamp()})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to save draft: {}", e)})),
+    }
 }
 
-async fn add_reply(
-    query: web::Query<HashMap<String, String>>,
-    post_id: web::Path<String>,
-    comment_data: web::Json<AddCommentRequest>,
-    db: web::Data<Database>,
-) -> impl Responder {
-    let collection = db.collection::<Post>("posts");
+#[derive(Deserialize)]
+struct PublishRequest {
+    publish_at: Option<i64>, // unix seconds, publishes immediately when missing or in the past
+}
 
-    // Define a function to check if a given ID is a valid ObjectId
-    fn is_valid_objectid(id: &str) -> bool {
-        ObjectId::from_str(id).is_ok()
+async fn publish_post(post_id: web::Path<String>, auth: AuthUser, request: web::Json<PublishRequest>, broker: web::Data<Broker>, db: web::Data<Database>) -> impl Responder {
+    let (mut post, _) = match find_post_for(&db, &post_id, &auth, ContributorRole::can_publish).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if post.status == PostStatus::Public {
+        return HttpResponse::BadRequest().json(json!({"error":"Post is already published"}));
     }
-
-    // Check if the post ID is a valid ObjectId
-    if !is_valid_objectid(&post_id) {
-        return HttpResponse::BadRequest().json(json!({"error": "Invalid Post ID"}));
+    if post.status == PostStatus::Quarantined {
+        return HttpResponse::Forbidden().json(json!({"error":"Post is waiting for a moderator"}));
     }
-
-    // Check if the comment content is between 1 and 400 characters
-    if comment_data.content.len() <= 1 || comment_data.content.len() > 400 {
-        return HttpResponse::BadRequest().json(json!({"error": "Comment content must be between 1 and 400 characters"}));
+    if post.status == PostStatus::InReview {
+        return HttpResponse::Conflict().json(json!({"error":"Post is already waiting for review"}));
     }
-
-    let post_id = ObjectId::from_str(&post_id).unwrap();
-    let new_comment = Comment::new(comment_data.author_id.clone(), comment_data.content.clone());
-    let filter = doc! {"_id": post_id};
-    let post = collection.find_one(filter.clone(), None).await;
-
-    // Check if the post exists
-    let mut post = match post {
-        Ok(Some(post)) => post,
-        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Post not found"})),
-        Err(error) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", error)})),
-    };
-
-    // Find the comment with matching ID in the comments vector
-    let comment_id = query.get("comment_id").unwrap();
-    let comment = post.comments.iter_mut().find(|c| c.id == comment_id.to_string());
-
-    // Check if the comment exists
-    let comment = match comment {
-        Some(comment) => comment,
-        None => return HttpResponse::NotFound().json(json!({"error": "Comment not found"})),
+    if post.status == PostStatus::Rejected {
+        return HttpResponse::Forbidden().json(json!({"error":"Post was rejected by an editor"}));
+    }
+    if post.status.is_locked() {
+        return HttpResponse::Forbidden().json(json!({"error":"Post was taken down or deleted"}));
+    }
+    let user = match auth.require_capability(&db, Capability::PostPublish).await {
+        Ok(user) => user,
+        Err(response) => return response,
     };
+    let previous_status = bson::to_bson(&post.status).unwrap();
 
-    // Add the new comment as a reply to the existing comment
-    comment.replies.push(new_comment);
+    // Authors who need review submit the post instead, an editor publishes it
+    let mut update = doc! {};
+    if needs_review(&user) {
+        submit_for_review(&mut post, request.publish_at, auth.id_str());
+        update.insert("$push", doc! {"review_history": bson::to_bson(post.review_history.last().unwrap()).unwrap()});
+    } else {
+        schedule_or_publish(&mut post, request.publish_at);
+    }
+    post.updated_at = Utc::now();
 
-    // Update the post in the database with the new comment
-    let doc = bson::to_document(&post).unwrap();
-    let update = doc! {"$set": {"comments": doc.get("comments").unwrap()}};
-    let result = collection.update_one(filter, update, None).await;
+    update.insert("$set", doc! {
+        "status": bson::to_bson(&post.status).unwrap(),
+        "published_at": post.published_at.map(|at| at.timestamp()),
+        "scheduled_at": post.scheduled_at.map(|at| at.timestamp()),
+        "updated_at": post.updated_at.timestamp(),
+    });
+    // Matching on the previous status keeps a concurrent publish from running the side-effects twice
+    let result = db.collection::<Post>("posts")
+        .update_one(doc! {"_id": post.id, "status": previous_status}, update, None)
+        .await;
 
     match result {
-        Ok(_) => HttpResponse::Ok().json(json!({"success": "Reply added!"})),
-        Err(error) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", error)})),
+        Ok(result) if result.modified_count == 0 => HttpResponse::Conflict().json(json!({"error":"Post status changed, try again"})),
+        Ok(_) => {
+            if post.status == PostStatus::Public {
+                if let Err(e) = record_revision(&db, &post, auth.id_str(), None).await {
+                    return HttpResponse::InternalServerError().json(json!({"error": format!("Error saving revision: {}", e)}));
+                }
+                if let Err(e) = on_post_published(&db, &broker, &post).await {
+                    return HttpResponse::InternalServerError().json(json!({"error": format!("Error publishing post: {}", e)}));
+                }
+            }
+            HttpResponse::Ok().json(json!({
+                "status": post.status,
+                "published_at": post.published_at.map(|at| at.timestamp()),
+                "scheduled_at": post.scheduled_at.map(|at| at.timestamp()),
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to publish post: {}", e)})),
     }
 }
 
+// Drafts, scheduled posts and posts in review the signed in user owns or contributes to
+async fn fetch_drafts(auth: AuthUser, params: web::Query<SearchParams>, db: web::Data<Database>) -> impl Responder {
+    let filter = doc! {
+        "$or": [{"author": auth.id_str()}, {"contributors.user_id": auth.id_str()}],
+        "status": {"$in": ["Draft", "Scheduled", "InReview", "ChangesRequested", "Rejected"]},
+    };
+    let options = FindOptions::builder().sort(doc! {"updated_at": -1}).build();
 
-#[derive(Deserialize, Clone)]
-struct AddLikeRequest{
-    user_id: String,
+    list_posts(&db, filter, options, params.full.unwrap_or(false)).await
 }
 
-async fn add_like(
-    query: web::Query<HashMap<String, String>>,
-    post_id: web::Path<String>,
-    db: web::Data<Database>,
-) -> impl Responder {
-    let collection = db.collection::<Post>("posts");
+#[derive(Deserialize)]
+struct ModeratePostRequest {
+    approve: bool,
+}
 
-    // Define a function to check if a given ID is a valid ObjectId
-    fn is_valid_objectid(id: &str) -> bool {
-        ObjectId::from_str(id).is_ok()
+// A moderator puts a hidden post back up, public again if it had been published
+async fn restore_post(post_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::PostModerate).await {
+        return response;
     }
+    let id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    let posts = db.collection::<Post>("posts");
+    let post = match posts.find_one(doc! {"_id": id, "status": "Hidden"}, None).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error":"No hidden post with this ID"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch post: {}", e)})),
+    };
 
-    // Check if the post ID is a valid ObjectId
-    if !is_valid_objectid(&post_id) {
-        return HttpResponse::BadRequest().json(json!({"error": "Invalid Post ID"}));
+    let status = if post.published_at.is_some() { PostStatus::Public } else { PostStatus::Draft };
+    let update = doc! {"$set": {"status": bson::to_bson(&status).unwrap()}};
+    match posts.update_one(doc! {"_id": id, "status": "Hidden"}, update, None).await {
+        Ok(result) if result.modified_count == 0 => HttpResponse::NotFound().json(json!({"error":"No hidden post with this ID"})),
+        Ok(_) => {
+            if let Err(e) = log_moderation(&db, auth.id_str(), ModerationAction::Restore, ReportTarget::Post, id, None, None).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to log moderation: {}", e)}));
+            }
+            HttpResponse::Ok().json(json!({"status": status}))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to restore post: {}", e)})),
     }
+}
 
-   
+// An admin releases a quarantined post back to its author as a draft, or deletes it.
+// The decision trains the spam filter.
+async fn moderate_post(post_id: web::Path<String>, auth: AuthUser, request: web::Json<ModeratePostRequest>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::PostModerate).await {
+        return response;
+    }
+    let id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
 
-    let post_id = ObjectId::from_str(&post_id).unwrap();
-    //let new_comment = Comment::new(comment_data.author_id.clone(), comment_data.content.clone());
+    let status = if request.approve { PostStatus::Draft } else { PostStatus::Deleted };
+    let update = doc! {"$set": {"status": bson::to_bson(&status).unwrap(), "quarantine_reasons": []}};
+    let result = db.collection::<Post>("posts")
+        .find_one_and_update(doc! {"_id": id, "status": "Quarantined"}, update, None)
+        .await;
 
-    let filter = doc! {"_id": post_id};
-    let post = collection.find_one(filter.clone(), None).await;
+    match result {
+        Ok(Some(post)) => {
+            train_spam_model(&db, &Submission::post(&post).text, !request.approve);
+            let action = if request.approve { ModerationAction::Approve } else { ModerationAction::Delete };
+            if let Err(e) = log_moderation(&db, auth.id_str(), action, ReportTarget::Post, post.id, None, None).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to log moderation: {}", e)}));
+            }
+            HttpResponse::Ok().json(json!({"status": status}))
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({"error":"No quarantined post with this ID"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to moderate post: {}", e)})),
+    }
+}
 
-    // Check if the post exists
-    let mut post = match post {
-        Ok(Some(post)) => post,
-        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Post not found"})),
-        Err(error) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", error)})),
-    };
+#[derive(Deserialize, PartialEq)]
+enum Reaction {
+    Like,
+    Dislike,
+    None, // takes a like or dislike back
+}
 
-    // Find the comment with matching ID in the comments vector
-    let comment_id = query.get("comment_id").unwrap();
-    let user_id = query.get("user_id").unwrap();
-    let comment = post.comments.iter_mut().find(|c| c.id == comment_id.to_string());
+#[derive(Deserialize)]
+struct ReactRequest {
+    reaction: Reaction,
+}
 
-    // Check if the comment exists
-    let comment = match comment {
-        Some(comment) => comment,
-        None => return HttpResponse::NotFound().json(json!({"error": "Comment not found"})),
+// Likes or dislikes a public post, a reader has one reaction at most
+async fn react_to_post(post_id: web::Path<String>, auth: AuthUser, request: web::Json<ReactRequest>, db: web::Data<Database>) -> impl Responder {
+    let id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
     };
-
-    // Add the new comment as a reply to the existing comment
-    //comment.replies.push(new_comment);
-    let mut is_deleted = false;
-    if comment.likes.contains(&user_id.clone()){
-        comment.likes.retain(|id| id != user_id);
-        is_deleted = true;
-    } else {
-        comment.likes.push(user_id.clone());
+    let posts = db.collection::<Post>("posts");
+    let user_id = auth.id_str();
+    let post_key = id.to_hex();
+
+    // The filter only matches when the reaction changes something, so of two requests at once only one counts it
+    let (state, post_update, user_update) = match request.reaction {
+        Reaction::Like => (
+            doc! {"likes": {"$ne": &user_id}},
+            doc! {"$addToSet": {"likes": &user_id}, "$pull": {"dislikes": &user_id}},
+            doc! {"$addToSet": {"likes": &post_key}, "$pull": {"dislikes": &post_key}},
+        ),
+        Reaction::Dislike => (
+            doc! {"dislikes": {"$ne": &user_id}},
+            doc! {"$addToSet": {"dislikes": &user_id}, "$pull": {"likes": &user_id}},
+            doc! {"$addToSet": {"dislikes": &post_key}, "$pull": {"likes": &post_key}},
+        ),
+        Reaction::None => (
+            doc! {"$or": [{"likes": &user_id}, {"dislikes": &user_id}]},
+            doc! {"$pull": {"likes": &user_id, "dislikes": &user_id}},
+            doc! {"$pull": {"likes": &post_key, "dislikes": &post_key}},
+        ),
+    };
+    let mut filter = doc! {"_id": id, "status": "Public"};
+    filter.extend(state);
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::Before).build();
+    let before = match posts.find_one_and_update(filter, post_update, options).await {
+        Ok(Some(post)) => post,
+        Ok(None) => match posts.count_documents(doc! {"_id": id, "status": "Public"}, None).await {
+            Ok(0) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+            Ok(_) => return HttpResponse::Ok().json(json!({"success":"Reaction saved"})),
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})),
+    };
+    if let Err(e) = db.collection::<User>("users").update_one(doc! {"_id": auth.id}, user_update, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update user: {}", e)}));
     }
-    
-    // Update the post in the database with the new comment
-    let doc = bson::to_document(&post).unwrap();
-    let update = doc! {"$set": {"comments": doc.get("comments").unwrap()}};
-    let result = collection.update_one(filter, update, None).await;
 
-    match result {
-        Ok(_) => HttpResponse::Ok().json(json!({"success": "Reply added!","isDeleted":is_deleted})),
-        Err(error) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", error)})),
+    let (liked, disliked) = (before.likes.contains(&user_id), before.dislikes.contains(&user_id));
+    let like_change = (request.reaction == Reaction::Like) as i64 - liked as i64;
+    let dislike_change = (request.reaction == Reaction::Dislike) as i64 - disliked as i64;
+    for (event, change) in [(PostEvent::Like, like_change), (PostEvent::Dislike, dislike_change)] {
+        if change != 0 {
+            if let Err(e) = record_post_event(&db, id, event, change).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to record reaction: {}", e)}));
+            }
+        }
     }
+    HttpResponse::Ok().json(json!({"success":"Reaction saved"}))
 }
 
 async fn search(params: web::Query<SearchParams>, db: web::Data<Database>) -> HttpResponse {
-    let collection = db.collection::<Post>("posts");
-
     // Build the search query based on the search parameters
-    let mut query = doc! {};
+    let mut query = doc! {"status": "Public"};
     if let Some(title) = &params.title {
         query.insert("title", title);
     }
@@ -505,20 +786,7 @@ async fn search(params: web::Query<SearchParams>, db: web::Data<Database>) -> Ht
         query.insert("date", *date);
     }
 
-    let mut cursor = collection.find(query, None).await.unwrap();
-    
-    let posts: Vec<Post> = cursor
-    .filter_map(|result| async {
-        match result {
-            Ok(post) => Some(post),
-            Err(_) => None,
-        }
-    })
-    .collect().await;
-
-    HttpResponse::Ok().json(posts)
-
-
+    list_posts(&db, query, FindOptions::default(), params.full.unwrap_or(false)).await
 }
 
 pub fn post_routes(cfg: &mut web::ServiceConfig) {
@@ -534,14 +802,6 @@ pub fn post_routes(cfg: &mut web::ServiceConfig) {
         web::resource("/post/update/{id}")
             .route(web::post().to(update_post))
     )
-    .service(
-        web::resource("/post/addcomment/{id}")
-            .route(web::post().to(add_comment))
-    )
-    .service(
-        web::resource("/post/addreply/{id}")
-            .route(web::post().to(add_reply))
-    )
     .service(
         web::resource("/post/fetchall/{page}")
             .route(web::get().to(fetch_all))
@@ -552,10 +812,31 @@ pub fn post_routes(cfg: &mut web::ServiceConfig) {
     )
     .service(
         web::resource("/post/upload_image")
+            .wrap(RateLimit::new("upload"))
             .route(web::post().to(upload_image))
     )
     .service(
-        web::resource("/post/add_like/{id}")
-            .route(web::post().to(add_like))
+        web::resource("/post/autosave/{id}")
+            .route(web::post().to(autosave_draft))
+    )
+    .service(
+        web::resource("/post/publish/{id}")
+            .route(web::post().to(publish_post))
+    )
+    .service(
+        web::resource("/post/drafts")
+            .route(web::get().to(fetch_drafts))
+    )
+    .service(
+        web::resource("/post/moderate/{id}")
+            .route(web::post().to(moderate_post))
+    )
+    .service(
+        web::resource("/post/moderate/{id}/restore")
+            .route(web::post().to(restore_post))
+    )
+    .service(
+        web::resource("/post/react/{id}")
+            .route(web::post().to(react_to_post))
     );
 }
@@ -0,0 +1,86 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::{Database, bson::{doc, oid::ObjectId}};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{Post, PostStatus};
+use crate::utils::{AuthUser, Broker};
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    token: Option<String>, // EventSource can't send headers, the token may come in the url instead
+    last_event_id: Option<u64>,
+}
+
+// The user from the Authorization header or the `token` parameter
+fn stream_user(auth: Option<AuthUser>, query: &StreamQuery) -> Result<AuthUser, HttpResponse> {
+    match auth.or_else(|| query.token.as_deref().and_then(AuthUser::from_token)) {
+        Some(user) => Ok(user),
+        None => Err(HttpResponse::Unauthorized().json(json!({"error":"Missing or invalid token"}))),
+    }
+}
+
+// Browsers send Last-Event-ID by themselves when they reconnect
+fn last_event_id(req: &HttpRequest, query: &StreamQuery) -> Option<u64> {
+    req.headers()
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or(query.last_event_id)
+}
+
+fn event_stream(broker: &Broker, channel: String, last_event_id: Option<u64>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(broker.subscribe(channel, last_event_id))
+}
+
+// New comments and replies, like counts and deletions on a post
+async fn post_stream(
+    req: HttpRequest,
+    post_id: web::Path<String>,
+    auth: Option<AuthUser>,
+    query: web::Query<StreamQuery>,
+    broker: web::Data<Broker>,
+    db: web::Data<Database>,
+) -> impl Responder {
+    let user = match stream_user(auth, &query) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let post_id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    match db.collection::<Post>("posts").find_one(doc! {"_id": post_id}, None).await {
+        Ok(Some(post)) if post.status == PostStatus::Public || post.role_of(&user.id_str()).is_some() => {}
+        Ok(_) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)})),
+    }
+
+    event_stream(&broker, format!("post:{}", post_id.to_hex()), last_event_id(&req, &query))
+}
+
+// The signed in user's notifications as they come
+async fn notification_stream(req: HttpRequest, auth: Option<AuthUser>, query: web::Query<StreamQuery>, broker: web::Data<Broker>) -> impl Responder {
+    let user = match stream_user(auth, &query) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    event_stream(&broker, format!("user:{}", user.id_str()), last_event_id(&req, &query))
+}
+
+pub fn realtime_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/realtime/post/{id}")
+            .route(web::get().to(post_stream))
+    )
+    .service(
+        web::resource("/realtime/notifications")
+            .route(web::get().to(notification_stream))
+    );
+}
@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{Capability, NotificationKind, Post, PostStatus, PostSummary, ReviewDecision, ReviewEvent};
+use crate::utils::{notify, on_post_published, schedule_or_publish, AuthUser, Broker};
+use super::revision_routes::record_revision;
+
+#[derive(Deserialize)]
+struct QueueQuery {
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+// Posts waiting for review, the ones waiting longest first
+async fn review_queue(auth: AuthUser, query: web::Query<QueueQuery>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::PostReview).await {
+        return response;
+    }
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder()
+        .projection(PostSummary::projection())
+        .sort(doc! {"updated_at": 1})
+        .skip((page - 1) * page_size)
+        .limit(page_size as i64)
+        .build();
+
+    match db.collection::<PostSummary>("posts").find(doc! {"status": "InReview"}, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<PostSummary>>().await {
+            Ok(posts) => HttpResponse::Ok().json(posts),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReviewRequest {
+    decision: ReviewDecision,
+    feedback: Option<String>, // required unless the post is approved
+}
+
+async fn review_post(post_id: web::Path<String>, auth: AuthUser, request: web::Json<ReviewRequest>, broker: web::Data<Broker>, db: web::Data<Database>) -> impl Responder {
+    if let Err(response) = auth.require_capability(&db, Capability::PostReview).await {
+        return response;
+    }
+    let id = match ObjectId::from_str(&post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    let collection = db.collection::<Post>("posts");
+    let mut post = match collection.find_one(doc! {"_id": id}, None).await {
+        Ok(Some(post)) if post.status == PostStatus::InReview => post,
+        Ok(Some(_)) => return HttpResponse::Conflict().json(json!({"error":"Post is not waiting for review"})),
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)})),
+    };
+    if post.role_of(&auth.id_str()).is_some() {
+        return HttpResponse::Forbidden().json(json!({"error":"You can't review a post you work on"}));
+    }
+    let feedback = request.feedback.as_deref().map(str::trim).filter(|feedback| !feedback.is_empty()).map(str::to_string);
+    if request.decision != ReviewDecision::Approve && feedback.is_none() {
+        return HttpResponse::BadRequest().json(json!({"error":"Tell the author why"}));
+    }
+
+    let (status, kind) = match request.decision {
+        ReviewDecision::Approve => {
+            // Scheduled for the date the author asked for, if it is still ahead
+            let publish_at = post.scheduled_at.map(|at| at.timestamp());
+            schedule_or_publish(&mut post, publish_at);
+            (post.status.clone(), NotificationKind::PostApproved)
+        }
+        ReviewDecision::RequestChanges => (PostStatus::ChangesRequested, NotificationKind::ChangesRequested),
+        ReviewDecision::Reject => (PostStatus::Rejected, NotificationKind::PostRejected),
+    };
+    post.status = status.clone();
+    post.updated_at = Utc::now();
+    let event = ReviewEvent::new(PostStatus::InReview, status.clone(), auth.id_str(), feedback);
+
+    let update = doc! {
+        "$set": {
+            "status": bson::to_bson(&status).unwrap(),
+            "published_at": post.published_at.map(|at| at.timestamp()),
+            "scheduled_at": post.scheduled_at.map(|at| at.timestamp()),
+            "updated_at": post.updated_at.timestamp(),
+        },
+        "$push": {"review_history": bson::to_bson(&event).unwrap()},
+    };
+    // Matching on the status keeps two editors from deciding at once
+    match collection.update_one(doc! {"_id": id, "status": "InReview"}, update, None).await {
+        Ok(result) if result.modified_count == 0 => return HttpResponse::Conflict().json(json!({"error":"Post was reviewed in the meantime"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})),
+    }
+
+    if post.status == PostStatus::Public {
+        if let Err(e) = record_revision(&db, &post, post.author.clone(), None).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Error saving revision: {}", e)}));
+        }
+        if let Err(e) = on_post_published(&db, &broker, &post).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Error publishing post: {}", e)}));
+        }
+    }
+    if let Err(e) = notify(&db, &broker, &post.author, kind, &auth.id_str(), Some(post.id), None).await {
+        println!("failed to notify about review: {}", e);
+    }
+    HttpResponse::Ok().json(json!({"status": status, "review": event}))
+}
+
+pub fn review_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/post/review/queue")
+            .route(web::get().to(review_queue))
+    )
+    .service(
+        web::resource("/post/review/{id}")
+            .route(web::post().to(review_post))
+    );
+}
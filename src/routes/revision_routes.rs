@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{self, doc, oid::ObjectId}, options::{FindOneOptions, FindOptions}};
+use serde::Deserialize;
+use serde_json::json;
+use chrono::Utc;
+
+use crate::types::{ContributorRole, Post, PostStatus, Revision};
+use crate::utils::{is_duplicate, line_diff, prepare_content, AuthUser};
+use super::post_routes::find_post_for;
+
+// Stores the current title and content of `post` as its next revision
+pub(crate) async fn record_revision(db: &Database, post: &Post, author: String, restored_from: Option<u32>) -> mongodb::error::Result<Revision> {
+    let collection = db.collection::<Revision>("revisions");
+
+    // Numbers are unique per post, an edit saved at the same time takes the next one
+    let mut attempts = 0;
+    loop {
+        let options = FindOneOptions::builder().sort(doc! {"number": -1}).build();
+        let last = collection.find_one(doc! {"post_id": post.id}, options).await?;
+        let number = last.map(|revision| revision.number + 1).unwrap_or(1);
+
+        let revision = Revision::from_post(post, number, author.clone(), restored_from);
+        match collection.insert_one(&revision, None).await {
+            Ok(_) => return Ok(revision),
+            Err(e) if is_duplicate(&e) && attempts < 5 => attempts += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// The post's history is public along with the post, otherwise only its editors see it
+async fn find_readable_post(db: &Database, post_id: &str, auth: Option<&AuthUser>) -> Result<ObjectId, HttpResponse> {
+    let id = match ObjectId::from_str(post_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"}))),
+    };
+    let post = match db.collection::<Post>("posts").find_one(doc! {"_id": id}, None).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return Err(HttpResponse::NotFound().json(json!({"error":"Post not found"}))),
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)}))),
+    };
+    let can_edit = auth.and_then(|auth| post.role_of(&auth.id_str())).is_some_and(|role| role.can_edit());
+    if post.status != PostStatus::Public && !can_edit {
+        return Err(HttpResponse::NotFound().json(json!({"error":"Post not found"})));
+    }
+    Ok(id)
+}
+
+async fn find_revision(db: &Database, post_id: ObjectId, number: u32) -> Result<Revision, HttpResponse> {
+    match db.collection::<Revision>("revisions").find_one(doc! {"post_id": post_id, "number": number}, None).await {
+        Ok(Some(revision)) => Ok(revision),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error": format!("Revision {} not found", number)}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch revision: {}", e)}))),
+    }
+}
+
+async fn list_revisions(post_id: web::Path<String>, auth: Option<AuthUser>, db: web::Data<Database>) -> impl Responder {
+    let post_id = match find_readable_post(&db, &post_id, auth.as_ref()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // The content can be large, fetch it one revision at a time through the diff endpoint
+    let options = FindOptions::builder()
+        .sort(doc! {"number": -1})
+        .projection(doc! {"content": 0})
+        .build();
+
+    let cursor = match db.collection::<bson::Document>("revisions").find(doc! {"post_id": post_id}, options).await {
+        Ok(cursor) => cursor,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch revisions: {}", e)})),
+    };
+
+    match cursor.try_collect::<Vec<bson::Document>>().await {
+        Ok(revisions) => HttpResponse::Ok().json(revisions),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch revisions: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct DiffQuery {
+    from: u32,
+    to: u32,
+}
+
+async fn diff_revisions(post_id: web::Path<String>, auth: Option<AuthUser>, query: web::Query<DiffQuery>, db: web::Data<Database>) -> impl Responder {
+    let post_id = match find_readable_post(&db, &post_id, auth.as_ref()).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let from = match find_revision(&db, post_id, query.from).await {
+        Ok(revision) => revision,
+        Err(response) => return response,
+    };
+    let to = match find_revision(&db, post_id, query.to).await {
+        Ok(revision) => revision,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(json!({
+        "from": from.number,
+        "to": to.number,
+        "title": {"from": from.title, "to": to.title},
+        "lines": line_diff(&from.content.markdown, &to.content.markdown),
+    }))
+}
+
+// Anyone who can edit the post can roll it back
+async fn rollback(path: web::Path<(String, u32)>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let (post_id, number) = path.into_inner();
+    let (mut post, _) = match find_post_for(&db, &post_id, &auth, ContributorRole::can_edit).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let post_id = post.id;
+
+    let revision = match find_revision(&db, post_id, number).await {
+        Ok(revision) => revision,
+        Err(response) => return response,
+    };
+
+    let collection = db.collection::<Post>("posts");
+
+    // Re-prepare in case the sanitizer allowlist changed since the revision was stored
+    let prepared = prepare_content(revision.content, &post.language);
+    let mut update_fields = prepared.to_update();
+    prepared.apply_to(&mut post);
+    post.title = revision.title;
+    post.updated_at = Utc::now();
+
+    update_fields.insert("title", &post.title);
+    update_fields.insert("updated_at", post.updated_at.timestamp());
+    let update = doc! {"$set": update_fields};
+    if let Err(e) = collection.update_one(doc! {"_id": post_id}, update, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Error updating post: {}", e)}));
+    }
+
+    // The rollback itself is a new revision, so history is never rewritten
+    match record_revision(&db, &post, auth.id_str(), Some(number)).await {
+        Ok(revision) => HttpResponse::Ok().json(json!({"success": "Post rolled back", "revision": revision.number})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Error saving revision: {}", e)})),
+    }
+}
+
+pub fn revision_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/post/revisions/{id}")
+            .route(web::get().to(list_revisions))
+    )
+    .service(
+        web::resource("/post/revisions/{id}/diff")
+            .route(web::get().to(diff_revisions))
+    )
+    .service(
+        web::resource("/post/revisions/{id}/rollback/{number}")
+            .route(web::post().to(rollback))
+    );
+}
@@ -0,0 +1,387 @@
+use std::{collections::HashMap, str::FromStr};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, oid::ObjectId, Document}, options::FindOptions};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::types::{AuthorProfile, Post, PostStatus, PostSummary, Series, SeriesLink, SeriesNavigation, SeriesView, User};
+use crate::utils::AuthUser;
+
+// The series, if the signed in user wrote it
+async fn owned_series(db: &Database, series_id: &str, auth: &AuthUser) -> Result<Series, HttpResponse> {
+    let series_id = match ObjectId::from_str(series_id) {
+        Ok(id) => id,
+        Err(_) => return Err(HttpResponse::BadRequest().json(json!({"error":"Invalid series ID"}))),
+    };
+    match db.collection::<Series>("series").find_one(doc! {"_id": series_id}, None).await {
+        Ok(Some(series)) if series.author == auth.id_str() => Ok(series),
+        Ok(Some(_)) => Err(HttpResponse::Forbidden().json(json!({"error":"Only the author can change a series"}))),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({"error":"Series not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch series: {}", e)}))),
+    }
+}
+
+// Series pages for `viewer`, with the parts they can see: the public ones, and all but deleted ones of their own series
+async fn series_views(db: &Database, series: Vec<Series>, viewer: Option<&AuthUser>) -> Result<Vec<SeriesView>, HttpResponse> {
+    let post_ids: Vec<ObjectId> = series.iter().flat_map(|series| series.posts.iter()).filter_map(|id| ObjectId::from_str(id).ok()).collect();
+    let mut filter = doc! {"_id": {"$in": post_ids}};
+    match viewer {
+        Some(viewer) => filter.insert("$or", vec![doc! {"status": "Public"}, doc! {"author": viewer.id_str(), "status": {"$ne": "Deleted"}}]),
+        None => filter.insert("status", "Public"),
+    };
+    let options = FindOptions::builder().projection(PostSummary::projection()).build();
+    let mut posts: HashMap<String, PostSummary> = match db.collection::<PostSummary>("posts").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<PostSummary>>().await {
+            Ok(posts) => posts.into_iter().map(|post| (post.id.to_hex(), post)).collect(),
+            Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)}))),
+        },
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)}))),
+    };
+
+    let author_ids: Vec<ObjectId> = series.iter().filter_map(|series| ObjectId::from_str(&series.author).ok()).collect();
+    let options = FindOptions::builder().projection(AuthorProfile::projection()).build();
+    let authors: HashMap<String, AuthorProfile> = match db.collection::<AuthorProfile>("users").find(doc! {"_id": {"$in": author_ids}}, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<AuthorProfile>>().await {
+            Ok(profiles) => profiles.into_iter().map(|profile| (profile.id.to_hex(), profile)).collect(),
+            Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch users: {}", e)}))),
+        },
+        Err(e) => return Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch users: {}", e)}))),
+    };
+
+    let followed = match viewer {
+        Some(viewer) => match viewer.fetch(db).await {
+            Ok(user) => user.followed_series,
+            Err(response) => return Err(response),
+        },
+        None => vec![],
+    };
+
+    Ok(series
+        .into_iter()
+        .map(|series| {
+            let parts: Vec<PostSummary> = series.posts.iter().filter_map(|id| posts.remove(id)).collect();
+            SeriesView {
+                id: series.id,
+                title: series.title,
+                description: series.description,
+                author: authors.get(&series.author).cloned(),
+                post_count: parts.len(),
+                total_read_time: parts.iter().map(|post| post.read_time).sum(),
+                posts: parts,
+                follower_count: series.follower_count,
+                following: followed.contains(&series.id.to_hex()),
+                updated_at: series.updated_at,
+            }
+        })
+        .collect())
+}
+
+async fn find_series(db: &Database, filter: Document) -> Result<Vec<Series>, HttpResponse> {
+    let options = FindOptions::builder().sort(doc! {"updated_at": -1}).build();
+    match db.collection::<Series>("series").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(series) => Ok(series),
+            Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch series: {}", e)}))),
+        },
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch series: {}", e)}))),
+    }
+}
+
+// Where the post stands in its series, for the previous/next links under it
+pub(crate) async fn series_navigation(db: &Database, series_id: ObjectId, post_id: ObjectId) -> mongodb::error::Result<Option<SeriesNavigation>> {
+    let series = match db.collection::<Series>("series").find_one(doc! {"_id": series_id}, None).await? {
+        Some(series) => series,
+        None => return Ok(None),
+    };
+    let ids: Vec<ObjectId> = series.posts.iter().filter_map(|id| ObjectId::from_str(id).ok()).collect();
+    let options = FindOptions::builder().projection(doc! {"title": 1}).build();
+    let mut titles: HashMap<ObjectId, SeriesLink> = db.collection::<SeriesLink>("posts")
+        .find(doc! {"_id": {"$in": ids}, "status": "Public"}, options).await?
+        .try_collect::<Vec<SeriesLink>>().await?
+        .into_iter()
+        .map(|link| (link.id, link))
+        .collect();
+
+    let parts: Vec<SeriesLink> = series.posts.iter().filter_map(|id| ObjectId::from_str(id).ok()).filter_map(|id| titles.remove(&id)).collect();
+    let position = parts.iter().position(|part| part.id == post_id);
+    Ok(Some(SeriesNavigation {
+        series_id: series.id,
+        title: series.title,
+        part: position.map(|index| index + 1).unwrap_or(0),
+        total_parts: parts.len(),
+        previous: position.and_then(|index| index.checked_sub(1)).and_then(|index| parts.get(index).cloned()),
+        next: position.and_then(|index| parts.get(index + 1).cloned()),
+    }))
+}
+
+#[derive(Deserialize)]
+struct SeriesRequest {
+    title: String,
+    description: Option<String>,
+}
+
+fn check_series(request: &SeriesRequest) -> Result<(String, String), HttpResponse> {
+    let title = request.title.trim();
+    if title.is_empty() || title.chars().count() > 120 {
+        return Err(HttpResponse::BadRequest().json(json!({"error":"Title must be 1-120 characters"})));
+    }
+    let description = request.description.as_deref().unwrap_or("").trim();
+    if description.chars().count() > 1000 {
+        return Err(HttpResponse::BadRequest().json(json!({"error":"Description can be at most 1000 characters"})));
+    }
+    Ok((title.to_string(), description.to_string()))
+}
+
+async fn create_series(auth: AuthUser, request: web::Json<SeriesRequest>, db: web::Data<Database>) -> impl Responder {
+    let (title, description) = match check_series(&request) {
+        Ok(fields) => fields,
+        Err(response) => return response,
+    };
+    let series = Series::new(auth.id_str(), title, description);
+    match db.collection::<Series>("series").insert_one(&series, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Series created", "id": series.id})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to create series: {}", e)})),
+    }
+}
+
+async fn fetch_series(series_id: web::Path<String>, auth: Option<AuthUser>, db: web::Data<Database>) -> impl Responder {
+    let series_id = match ObjectId::from_str(&series_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid series ID"})),
+    };
+    let series = match find_series(&db, doc! {"_id": series_id}).await {
+        Ok(series) if !series.is_empty() => series,
+        Ok(_) => return HttpResponse::NotFound().json(json!({"error":"Series not found"})),
+        Err(response) => return response,
+    };
+    match series_views(&db, series, auth.as_ref()).await {
+        Ok(mut views) => HttpResponse::Ok().json(views.remove(0)),
+        Err(response) => response,
+    }
+}
+
+// An author's series, the last updated first. Series without a public part are only listed to their author.
+async fn fetch_author_series(user_id: web::Path<String>, auth: Option<AuthUser>, db: web::Data<Database>) -> impl Responder {
+    let series = match find_series(&db, doc! {"author": user_id.as_str()}).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let is_author = auth.as_ref().map(|auth| auth.id_str() == *user_id).unwrap_or(false);
+    match series_views(&db, series, auth.as_ref()).await {
+        Ok(views) => HttpResponse::Ok().json(views.into_iter().filter(|view| is_author || view.post_count > 0).collect::<Vec<_>>()),
+        Err(response) => response,
+    }
+}
+
+async fn fetch_followed_series(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let user = match auth.fetch(&db).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let ids: Vec<ObjectId> = user.followed_series.iter().filter_map(|id| ObjectId::from_str(id).ok()).collect();
+    let series = match find_series(&db, doc! {"_id": {"$in": ids}}).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    match series_views(&db, series, Some(&auth)).await {
+        Ok(views) => HttpResponse::Ok().json(views),
+        Err(response) => response,
+    }
+}
+
+async fn update_series(series_id: web::Path<String>, auth: AuthUser, request: web::Json<SeriesRequest>, db: web::Data<Database>) -> impl Responder {
+    let series = match owned_series(&db, &series_id, &auth).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let (title, description) = match check_series(&request) {
+        Ok(fields) => fields,
+        Err(response) => return response,
+    };
+    let update = doc! {"$set": {"title": title, "description": description, "updated_at": Utc::now().timestamp()}};
+    match db.collection::<Series>("series").update_one(doc! {"_id": series.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Series updated"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update series: {}", e)})),
+    }
+}
+
+// The posts stay, they just aren't parts of anything anymore
+async fn delete_series(series_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let series = match owned_series(&db, &series_id, &auth).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    if let Err(e) = db.collection::<Post>("posts").update_many(doc! {"series_id": series.id}, doc! {"$set": {"series_id": null}}, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update posts: {}", e)}));
+    }
+    let id = series.id.to_hex();
+    if let Err(e) = db.collection::<User>("users").update_many(doc! {"followed_series": &id}, doc! {"$pull": {"followed_series": &id}}, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update followers: {}", e)}));
+    }
+    match db.collection::<Series>("series").delete_one(doc! {"_id": series.id}, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Series deleted"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to delete series: {}", e)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct PartRequest {
+    post_id: String,
+}
+
+// Adds one of the author's posts as the last part
+async fn add_part(series_id: web::Path<String>, auth: AuthUser, request: web::Json<PartRequest>, db: web::Data<Database>) -> impl Responder {
+    let series = match owned_series(&db, &series_id, &auth).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let post_id = match ObjectId::from_str(&request.post_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid Post ID"})),
+    };
+    let posts = db.collection::<Post>("posts");
+    let post = match posts.find_one(doc! {"_id": post_id}, None).await {
+        Ok(Some(post)) if post.author == auth.id_str() && post.status != PostStatus::Deleted => post,
+        Ok(_) => return HttpResponse::NotFound().json(json!({"error":"Post not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch Post: {}", e)})),
+    };
+    if post.series_id == Some(series.id) {
+        return HttpResponse::Ok().json(json!({"success":"Post is already part of the series"}));
+    }
+
+    // Claims the post, so it can't be added to two series at once
+    match posts.update_one(doc! {"_id": post_id, "series_id": null}, doc! {"$set": {"series_id": series.id}}, None).await {
+        Ok(result) if result.matched_count == 0 => return HttpResponse::Conflict().json(json!({"error":"Post is already part of another series"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)})),
+    }
+    let update = doc! {"$push": {"posts": post_id.to_hex()}, "$set": {"updated_at": Utc::now().timestamp()}};
+    match db.collection::<Series>("series").update_one(doc! {"_id": series.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Post added to the series", "part": series.posts.len() + 1})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update series: {}", e)})),
+    }
+}
+
+async fn remove_part(series_id: web::Path<String>, auth: AuthUser, request: web::Json<PartRequest>, db: web::Data<Database>) -> impl Responder {
+    let series = match owned_series(&db, &series_id, &auth).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let update = doc! {"$pull": {"posts": &request.post_id}, "$set": {"updated_at": Utc::now().timestamp()}};
+    if let Err(e) = db.collection::<Series>("series").update_one(doc! {"_id": series.id}, update, None).await {
+        return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update series: {}", e)}));
+    }
+    if let Ok(post_id) = ObjectId::from_str(&request.post_id) {
+        let filter = doc! {"_id": post_id, "series_id": series.id};
+        if let Err(e) = db.collection::<Post>("posts").update_one(filter, doc! {"$set": {"series_id": null}}, None).await {
+            return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update Post: {}", e)}));
+        }
+    }
+    HttpResponse::Ok().json(json!({"success":"Post removed from the series"}))
+}
+
+#[derive(Deserialize)]
+struct ReorderRequest {
+    posts: Vec<String>, // every part, in the new order
+}
+
+async fn reorder_series(series_id: web::Path<String>, auth: AuthUser, request: web::Json<ReorderRequest>, db: web::Data<Database>) -> impl Responder {
+    let series = match owned_series(&db, &series_id, &auth).await {
+        Ok(series) => series,
+        Err(response) => return response,
+    };
+    let mut current = series.posts.clone();
+    let mut requested = request.posts.clone();
+    current.sort();
+    requested.sort();
+    if current != requested {
+        return HttpResponse::BadRequest().json(json!({"error":"The new order must contain every part of the series once"}));
+    }
+
+    let filter = doc! {"_id": series.id, "posts": &series.posts};
+    let update = doc! {"$set": {"posts": &request.posts, "updated_at": Utc::now().timestamp()}};
+    match db.collection::<Series>("series").update_one(filter, update, None).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::Conflict().json(json!({"error":"The series has changed, fetch it again"})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Series reordered"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update series: {}", e)})),
+    }
+}
+
+// Follows a series, or unfollows it when already following
+async fn follow_series(series_id: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let id = match ObjectId::from_str(&series_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid series ID"})),
+    };
+    let series = db.collection::<Series>("series");
+    match series.count_documents(doc! {"_id": id}, None).await {
+        Ok(0) => return HttpResponse::NotFound().json(json!({"error":"Series not found"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch series: {}", e)})),
+    }
+
+    let users = db.collection::<User>("users");
+    let target = id.to_hex();
+    let follow = users
+        .update_one(doc! {"_id": auth.id, "followed_series": {"$ne": &target}}, doc! {"$push": {"followed_series": &target}}, None)
+        .await;
+    let (change, following) = match follow {
+        Ok(result) if result.matched_count > 0 => (1, true),
+        Ok(_) => match users.update_one(doc! {"_id": auth.id}, doc! {"$pull": {"followed_series": &target}}, None).await {
+            Ok(_) => (-1, false),
+            Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to unfollow: {}", e)})),
+        },
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to follow: {}", e)})),
+    };
+    match series.update_one(doc! {"_id": id}, doc! {"$inc": {"follower_count": change}}, None).await {
+        Ok(_) if following => HttpResponse::Ok().json(json!({"success":"Followed", "following": true})),
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Unfollowed", "following": false})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update series: {}", e)})),
+    }
+}
+
+pub fn series_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/series/create")
+            .route(web::post().to(create_series))
+    )
+    .service(
+        web::resource("/series/following")
+            .route(web::get().to(fetch_followed_series))
+    )
+    .service(
+        web::resource("/series/author/{id}")
+            .route(web::get().to(fetch_author_series))
+    )
+    .service(
+        web::resource("/series/{id}")
+            .route(web::get().to(fetch_series))
+    )
+    .service(
+        web::resource("/series/{id}/update")
+            .route(web::post().to(update_series))
+    )
+    .service(
+        web::resource("/series/{id}/delete")
+            .route(web::post().to(delete_series))
+    )
+    .service(
+        web::resource("/series/{id}/add")
+            .route(web::post().to(add_part))
+    )
+    .service(
+        web::resource("/series/{id}/remove")
+            .route(web::post().to(remove_part))
+    )
+    .service(
+        web::resource("/series/{id}/reorder")
+            .route(web::post().to(reorder_series))
+    )
+    .service(
+        web::resource("/series/{id}/follow")
+            .route(web::post().to(follow_series))
+    );
+}
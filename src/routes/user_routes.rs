@@ -5,16 +5,18 @@ use actix_multipart::Multipart;
 use reqwest::{Client, Url};
 use reqwest::RequestBuilder;
 
-use mongodb::{Database, bson::{self, doc, from_document, oid::ObjectId, Bson}, options::{FindOneAndUpdateOptions, ReturnDocument, FindOneOptions, FindOptions}, Collection};
+use mongodb::{Database, bson::{self, doc, from_document, oid::ObjectId, Bson, Document}, options::{FindOneAndUpdateOptions, ReturnDocument, FindOneOptions, FindOptions}, Collection};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha256::digest;
 use dotenv::dotenv;
 
-use crate::{types::User, utils::upload_image_to_s3};
-use crate::utils::sign_jwt;
+use crate::{types::{AuthorStats, Bookmark, NotificationKind, PostSummary, ProfileView, PublicProfile, SocialLink, User}, utils::upload_image_to_s3};
+use super::bookmark_routes::{bookmarkable_post, favorites};
+use crate::utils::{effective_capabilities, notify, sign_jwt, AuthUser, Broker, RateLimit};
 use futures::{StreamExt, TryStreamExt, FutureExt};
 use uuid::Uuid;
+use chrono::{Duration, Utc};
 async fn print_headers_middleware<AppState>(
     req: ServiceRequest,
     srv: &Resource<AppState>,
@@ -120,8 +122,10 @@ impl AsRef<FetchOptions> for FetchOptions {
     }
 }
 
+// Users get any of their own fields except the password, other users only the public ones
 async fn fetch_user_by_id(
     Post_id: web::Path<String>,
+    auth: Option<AuthUser>,
     query: web::Query<HashMap<String, String>>,
     db: web::Data<Database>,
 ) -> impl Responder {
@@ -140,20 +144,30 @@ async fn fetch_user_by_id(
     }
 
     let id = ObjectId::from_str(&Post_id).unwrap();
+    let is_self = auth.map(|auth| auth.id == id).unwrap_or(false);
 
     let mut options = FindOneOptions::default();
 
-   // If the `fields` field is present in the query string,
+    // If the `fields` field is present in the query string,
     // create a projection document to fetch only the specified fields.
-    if let Some(fields) = query.get("fields") {
-        let field_vec: Vec<String> = fields.split(",").map(|s| s.to_owned()).collect();
-        let mut projection = doc! {};
-        for field in field_vec {
-            projection.insert(field, 1);
+    options.projection = match query.get("fields") {
+        Some(fields) => {
+            let mut projection = doc! {};
+            for field in fields.split(',').map(|field| field.trim()) {
+                let allowed = if is_self { field != "password" && !field.starts_with("password.") } else { PublicProfile::FIELDS.contains(&field) };
+                if allowed {
+                    projection.insert(field, 1);
+                }
+            }
+            // An empty projection would return the whole document
+            if projection.is_empty() {
+                return HttpResponse::BadRequest().json(json!({"error":"None of the requested fields can be fetched"}));
+            }
+            Some(projection)
         }
-        options.projection = Some(projection);
-    }
-   
+        None if is_self => Some(doc! {"password": 0}),
+        None => Some(PublicProfile::projection()),
+    };
 
     match collection.find_one(doc! {"_id": id}, options).await {
         Ok(result) => {
@@ -260,6 +274,33 @@ struct LoginRequest {
     email: Option<String>,
 }
 
+// Counts a wrong password. After LOGIN_MAX_FAILURES (default 5) in a row the account is locked
+// for LOGIN_LOCKOUT_MINUTES (default 15), and returns until when.
+async fn record_failed_login(db: &Database, user_id: ObjectId) -> mongodb::error::Result<Option<i64>> {
+    let max_failures = env::var("LOGIN_MAX_FAILURES").ok().and_then(|value| value.parse().ok()).unwrap_or(5i64);
+    let lockout_minutes = env::var("LOGIN_LOCKOUT_MINUTES").ok().and_then(|value| value.parse().ok()).unwrap_or(15i64);
+    let locked_until = (Utc::now() + Duration::minutes(lockout_minutes)).timestamp();
+
+    // One pipeline update, parallel attempts can't slip past the limit
+    let update = vec![
+        doc! {"$set": {"failed_logins": {"$add": [{"$ifNull": ["$failed_logins", 0]}, 1]}}},
+        doc! {"$set": {
+            "locked_until": {"$cond": [{"$gte": ["$failed_logins", max_failures]}, locked_until, {"$ifNull": ["$locked_until", null]}]},
+            "failed_logins": {"$cond": [{"$gte": ["$failed_logins", max_failures]}, 0, "$failed_logins"]},
+        }},
+    ];
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+    let user = db.collection::<User>("users").find_one_and_update(doc! {"_id": user_id}, update, options).await?;
+    Ok(user.and_then(|user| user.locked_until).map(|at| at.timestamp()).filter(|at| *at == locked_until))
+}
+
+fn locked_response(locked_until: i64) -> HttpResponse {
+    let retry_after = (locked_until - Utc::now().timestamp()).max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(json!({"message":"Too many failed logins, try again later", "retry_after": retry_after}))
+}
+
 async fn login(request_user: web::Json<LoginRequest>, db: web::Data<Database>) -> impl Responder {
     let collection = db.collection("users");
     let filter = doc! {
@@ -273,6 +314,9 @@ async fn login(request_user: web::Json<LoginRequest>, db: web::Data<Database>) -
             if let Some(doc) = result {
                 // Deserialize the user document to a User struct
                 let user: User = from_document(doc).unwrap();
+                if let Some(locked_until) = user.locked_until.filter(|at| *at > Utc::now()) {
+                    return locked_response(locked_until.timestamp());
+                }
                 //check user code if login with google
                 async fn send_request(token: &str) -> Result<reqwest::Response, reqwest::Error> {
                     let client = Client::new();
@@ -313,6 +357,12 @@ async fn login(request_user: web::Json<LoginRequest>, db: web::Data<Database>) -
                     }
                     let input_password_hash = hash_password(request_user.password.clone());
                     if input_password_hash == user.password.unwrap() {
+                        if user.failed_logins > 0 || user.locked_until.is_some() {
+                            let reset = doc! {"$set": {"failed_logins": 0, "locked_until": null}};
+                            if let Err(e) = db.collection::<User>("users").update_one(doc! {"_id": user.id}, reset, None).await {
+                                return HttpResponse::InternalServerError().json(json!({"server_error":e.to_string()}));
+                            }
+                        }
                         // Passwords match, return an OK  response
                         match sign_jwt(user.id.to_string().as_str()) {
                             Ok(token) => {
@@ -326,7 +376,11 @@ async fn login(request_user: web::Json<LoginRequest>, db: web::Data<Database>) -
                         }
                     } else {
                         // Passwords don't match, return a 401 Unauthorized error as a JSON response
-                        HttpResponse::Unauthorized().json(json!({"message":"Invalid password"}))
+                        match record_failed_login(&db, user.id).await {
+                            Ok(Some(locked_until)) => locked_response(locked_until),
+                            Ok(None) => HttpResponse::Unauthorized().json(json!({"message":"Invalid password"})),
+                            Err(e) => HttpResponse::InternalServerError().json(json!({"server_error":e.to_string()})),
+                        }
                     }
                 }
                 // Check if the input password matches the stored password hash
@@ -426,9 +480,13 @@ async fn login_google(request_data: web::Json<LoginGoogleRequest>, db: web::Data
 
 
 
+// Fields users change on their own, roles and bans go through /admin/users/{id}
+const UPDATABLE_FIELDS: [&str; 3] = ["name", "avatar", "forgot_mail"];
+
 async fn update_user(
     user_id: web::Path<String>,
     new_data: web::Json<HashMap<String, String>>,
+    auth: AuthUser,
     db: web::Data<Database>,
 ) -> impl Responder {
     let collection = db.collection::<User>("users");
@@ -442,23 +500,22 @@ async fn update_user(
     }
 
     let id = ObjectId::from_str(&user_id).unwrap();
+    if id != auth.id {
+        return HttpResponse::Forbidden().json(json!({"error":"You can only update your own account"}));
+    }
 
     let mut update_fields = doc! {};
 
     for (key, value) in new_data.iter() {
-        if key.contains('.') {
-            let mut parts = key.split('.').rev();
-            let last_key = parts.next().unwrap();
-            let mut doc = doc! {last_key: bson::to_bson(value).unwrap_or(Bson::Null)};
-            for part in parts {
-                let mut new_doc = doc! {part: doc};
-                doc = new_doc;
-            }
-            update_fields.insert(key.to_string(), doc);
-        } else {
-            update_fields.insert(key.to_string(), bson::to_bson(value).unwrap_or(Bson::Null));
+        if !UPDATABLE_FIELDS.contains(&key.as_str()) {
+            return HttpResponse::BadRequest().json(json!({"error": format!("{} can't be changed here", key)}));
         }
+        update_fields.insert(key.to_string(), value);
+    }
+    if update_fields.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error":"Nothing to update"}));
     }
+    update_fields.insert("updated_at", Utc::now().timestamp());
     println!("update => {:?}",update_fields);
     let update_doc = doc! {"$set": update_fields};
     let result = collection.update_one(doc! {"_id": id}, update_doc, None).await;
@@ -591,96 +648,236 @@ async fn get_google_user(
 }
 
 #[derive(Deserialize, Clone)]
-struct AddCommentRequest{
+struct AddBookmarkRequest{
     post_id: String
 }
 
-async fn add_favorite(User_id: web::Path<String>, request_data: web::Json<AddCommentRequest>, db: web::Data<Database>) -> impl Responder {
-
-    let collection = db.collection::<User>("users");
+// Adds the post to the user's Favorites collection, or takes it out when it's already there
+async fn add_favorite(user_id: web::Path<String>, auth: AuthUser, request_data: web::Json<AddBookmarkRequest>, db: web::Data<Database>) -> impl Responder {
+    if ObjectId::from_str(&user_id).ok() != Some(auth.id) {
+        return HttpResponse::Forbidden().json(json!({"error":"You can only change your own bookmarks"}));
+    }
+    let favorites = match favorites(&db, &auth.id_str()).await {
+        Ok(favorites) => favorites,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch bookmarks: {}", e)})),
+    };
 
-    fn is_valid_objectid(id: &str) -> bool {
-        if let Ok(_) = ObjectId::from_str(id) {
-            true
-        } else {
-            false
-        }
+    let collection = db.collection::<Bookmark>("bookmarks");
+    let now = Utc::now().timestamp();
+    let (update, response) = if favorites.posts.contains(&request_data.post_id) {
+        (doc! {"$pull": {"posts": &request_data.post_id}, "$set": {"updated_at": now}}, "bookmark removed")
+    } else {
+        let post_id = match bookmarkable_post(&db, &request_data.post_id, &auth).await {
+            Ok(post_id) => post_id,
+            Err(response) => return response,
+        };
+        (doc! {"$push": {"posts": post_id}, "$set": {"updated_at": now}}, "bookmark added")
+    };
+    match collection.update_one(doc! {"_id": favorites.id}, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"result": response, "collection_id": favorites.id})),
+        Err(error) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update bookmarks: {}", error)})),
     }
+}
 
-    if !is_valid_objectid(&User_id) {
-        return HttpResponse::BadRequest().json(json!({"error":"Invalid User ID"}));
+
+fn as_count(document: &Document, key: &str) -> u64 {
+    match document.get(key) {
+        Some(Bson::Int32(value)) => *value as u64,
+        Some(Bson::Int64(value)) => *value as u64,
+        Some(Bson::Double(value)) => *value as u64,
+        _ => 0,
     }
-    
-    let user_id = ObjectId::from_str(&User_id).unwrap();
-    let filter = doc! {"_id": user_id};
+}
 
-    let update_push = doc! {"$push": {"favorites": request_data.post_id.clone()}};
-    let update_pull = doc! {"$pull": {"favorites": request_data.post_id.clone()}};
+// Totals over the author's public posts, and how many follow them
+async fn author_stats(db: &Database, user_id: &str) -> mongodb::error::Result<AuthorStats> {
+    let pipeline = vec![
+        doc! {"$match": {"author": user_id, "status": "Public"}},
+        doc! {"$group": {
+            "_id": null,
+            "post_count": {"$sum": 1},
+            "total_views": {"$sum": "$views"},
+            "total_likes": {"$sum": {"$size": {"$ifNull": ["$likes", []]}}},
+        }},
+    ];
+    let totals = db.collection::<Document>("posts").aggregate(pipeline, None).await?.try_next().await?.unwrap_or_default();
+    let follower_count = db.collection::<Document>("users").count_documents(doc! {"following": user_id}, None).await?;
+
+    Ok(AuthorStats {
+        post_count: as_count(&totals, "post_count"),
+        total_views: as_count(&totals, "total_views"),
+        total_likes: as_count(&totals, "total_likes"),
+        follower_count,
+    })
+}
 
-    let mut response = String::new();
+async fn profile_view(db: &Database, filter: Document) -> HttpResponse {
+    let options = FindOneOptions::builder().projection(PublicProfile::projection()).build();
+    let profile = match db.collection::<PublicProfile>("users").find_one(filter, options).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error":"user not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)})),
+    };
+    match author_stats(db, &profile.id.to_hex()).await {
+        Ok(stats) => HttpResponse::Ok().json(ProfileView { profile, stats }),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to compute stats: {}", e)})),
+    }
+}
 
-    match collection.find_one(filter.clone(), None).await {
-        Ok(Some(user)) => {
-            if user.favorites.contains(&request_data.post_id) {
-                // Post already exists in favorites, delete it
-                let result = collection.update_one(filter, update_pull, None).await;
+async fn get_user_by_name(
+    name: web::Path<String>,
+    db: web::Data<Database>
+) -> impl Responder{
+    profile_view(&db, doc! {"name": name.as_str()}).await
+}
 
-                match result{
-                    Ok(_) => {
-                        response = String::from("deleted");
-                    },
-                    Err(error) => {
-                        return HttpResponse::InternalServerError().json(format!("Failed to delete post: {}", error));
-                    }
-                }
-            } else {
-                // Post does not exist in favorites, add it
-                let result = collection.update_one(filter, update_push, None).await;
+async fn fetch_profile(user_id: web::Path<String>, db: web::Data<Database>) -> impl Responder {
+    match ObjectId::from_str(&user_id) {
+        Ok(id) => profile_view(&db, doc! {"_id": id}).await,
+        Err(_) => HttpResponse::BadRequest().json(json!({"error":"Invalid user ID"})),
+    }
+}
 
-                match result{
-                    Ok(_) => {
-                        response = String::from("comment added!");
-                    },
-                    Err(error) => {
-                        return HttpResponse::InternalServerError().json(format!("Failed to add comment: {}", error));
-                    }
-                }
+#[derive(Deserialize)]
+struct UpdateProfileRequest {
+    bio: Option<String>,
+    social_links: Option<Vec<SocialLink>>,
+}
+
+// Changes the signed in user's bio and social links
+async fn update_profile(auth: AuthUser, request: web::Json<UpdateProfileRequest>, db: web::Data<Database>) -> impl Responder {
+    let mut update = doc! {};
+    if let Some(bio) = &request.bio {
+        let bio = bio.trim();
+        if bio.chars().count() > 500 {
+            return HttpResponse::BadRequest().json(json!({"error":"Bio can be at most 500 characters"}));
+        }
+        update.insert("bio", if bio.is_empty() { None } else { Some(bio) });
+    }
+    if let Some(social_links) = &request.social_links {
+        if social_links.len() > 10 {
+            return HttpResponse::BadRequest().json(json!({"error":"At most 10 social links"}));
+        }
+        for link in social_links {
+            let valid_url = Url::parse(&link.url).map(|url| url.scheme() == "http" || url.scheme() == "https").unwrap_or(false);
+            if !valid_url || link.platform.trim().is_empty() || link.platform.chars().count() > 30 {
+                return HttpResponse::BadRequest().json(json!({"error": format!("Invalid social link: {}", link.url)}));
             }
-        },
-        Ok(None) => {
-            return HttpResponse::BadRequest().json(json!({"error":"User not found"}));
-        },
-        Err(error) => {
-            return HttpResponse::InternalServerError().json(format!("Failed to fetch user: {}", error));
         }
+        update.insert("social_links", bson::to_bson(social_links).unwrap());
+    }
+    if update.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error":"Nothing to update"}));
+    }
+    update.insert("updated_at", Utc::now().timestamp());
+
+    match db.collection::<User>("users").update_one(doc! {"_id": auth.id}, doc! {"$set": update}, None).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"success":"Profile updated"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update profile: {}", e)})),
     }
+}
 
-    HttpResponse::Ok().json(json!({"result": response}))
+#[derive(Deserialize)]
+struct AuthorPostsQuery {
+    page: Option<u64>,
+    page_size: Option<u64>,
 }
 
+// The author's public posts, including the ones they co-authored, newest first
+async fn fetch_author_posts(user_id: web::Path<String>, query: web::Query<AuthorPostsQuery>, db: web::Data<Database>) -> impl Responder {
+    if ObjectId::from_str(&user_id).is_err() {
+        return HttpResponse::BadRequest().json(json!({"error":"Invalid user ID"}));
+    }
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let options = FindOptions::builder()
+        .projection(PostSummary::projection())
+        .sort(doc! {"published_at": -1})
+        .skip((page - 1) * page_size)
+        .limit(page_size as i64)
+        .build();
 
-async fn get_user_by_name(
-    name: web::Path<String>,
-    db: web::Data<Database>
-) -> impl Responder{
-    // Search for user by name
+    let filter = doc! {
+        "$or": [
+            {"author": user_id.as_str()},
+            {"contributors": {"$elemMatch": {"user_id": user_id.as_str(), "role": "CoAuthor"}}},
+        ],
+        "status": "Public",
+    };
+    match db.collection::<PostSummary>("posts").find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<PostSummary>>().await {
+            Ok(posts) => HttpResponse::Ok().json(posts),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch posts: {}", e)})),
+    }
+}
+
+// Follows a user, or unfollows them when already following
+async fn follow_user(user_id: web::Path<String>, auth: AuthUser, broker: web::Data<Broker>, db: web::Data<Database>) -> impl Responder {
     let collection = db.collection::<User>("users");
-    let result = collection.find_one(doc! { "name": name.to_string() }, None).await.unwrap();
+    let target_id = match ObjectId::from_str(&user_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error":"Invalid user ID"})),
+    };
+    if target_id == auth.id {
+        return HttpResponse::BadRequest().json(json!({"error":"You can't follow yourself"}));
+    }
+    match collection.count_documents(doc! {"_id": target_id}, None).await {
+        Ok(0) => return HttpResponse::NotFound().json(json!({"error":"user not found"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)})),
+    }
 
-    if(result.is_none()){
-        HttpResponse::NotFound().json(json!({"error":"user not found"}))
-    }else{
-        HttpResponse::Ok().json(result)
+    let target = target_id.to_hex();
+    let follow = collection
+        .update_one(doc! {"_id": auth.id, "following": {"$ne": &target}}, doc! {"$push": {"following": &target}}, None)
+        .await;
+    match follow {
+        Ok(result) if result.matched_count > 0 => {
+            if let Err(e) = notify(&db, &broker, &target, NotificationKind::Follow, &auth.id_str(), None, None).await {
+                return HttpResponse::InternalServerError().json(json!({"error": format!("Failed to notify: {}", e)}));
+            }
+            HttpResponse::Ok().json(json!({"success":"Followed", "following": true}))
+        }
+        Ok(_) => match collection.update_one(doc! {"_id": auth.id}, doc! {"$pull": {"following": &target}}, None).await {
+            Ok(_) => HttpResponse::Ok().json(json!({"success":"Unfollowed", "following": false})),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to unfollow: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to follow: {}", e)})),
     }
 }
 
+// Posts with a followed tag show up in the weekly digest
+async fn follow_tag(tag: web::Path<String>, auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    let collection = db.collection::<User>("users");
+    let tag = tag.into_inner();
+    let follow = collection
+        .update_one(doc! {"_id": auth.id, "followed_tags": {"$ne": &tag}}, doc! {"$push": {"followed_tags": &tag}}, None)
+        .await;
+    match follow {
+        Ok(result) if result.matched_count > 0 => HttpResponse::Ok().json(json!({"success":"Followed", "following": true})),
+        Ok(_) => match collection.update_one(doc! {"_id": auth.id}, doc! {"$pull": {"followed_tags": &tag}}, None).await {
+            Ok(_) => HttpResponse::Ok().json(json!({"success":"Unfollowed", "following": false})),
+            Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to unfollow: {}", e)})),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("Failed to follow: {}", e)})),
+    }
+}
 
+// What the signed in user may do, so the frontend can hide what they can't
+async fn fetch_capabilities(auth: AuthUser, db: web::Data<Database>) -> impl Responder {
+    match auth.fetch(&db).await {
+        Ok(user) => HttpResponse::Ok().json(json!({"permission": user.permission, "capabilities": effective_capabilities(&user)})),
+        Err(response) => response,
+    }
+}
 
 pub fn user_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/user/create")
-
-    .route(web::post().to(create_user))
+            .wrap(RateLimit::new("signup"))
+            .route(web::post().to(create_user))
     )
     .service(
         web::resource("/user/fetch/{id}")
@@ -689,14 +886,17 @@ pub fn user_routes(cfg: &mut web::ServiceConfig) {
     )
     .service(
         web::resource("/user/login")
+            .wrap(RateLimit::new("login"))
             .route(web::post().to(login))
     )
     .service(
         web::resource("/user/logingoogle")
+            .wrap(RateLimit::new("login"))
             .route(web::post().to(login_google))
     )
     .service(
         web::resource("/user/changeavatar/{id}")
+            .wrap(RateLimit::new("upload"))
             .route(web::post().to(upload_avatar))
     )
     .service(
@@ -719,5 +919,29 @@ pub fn user_routes(cfg: &mut web::ServiceConfig) {
     .service(
         web::resource("/user/getbyname/{name}")
             .route(web::get().to(get_user_by_name))
+    )
+    .service(
+        web::resource("/user/follow/{id}")
+            .route(web::post().to(follow_user))
+    )
+    .service(
+        web::resource("/user/followtag/{name}")
+            .route(web::post().to(follow_tag))
+    )
+    .service(
+        web::resource("/user/capabilities")
+            .route(web::get().to(fetch_capabilities))
+    )
+    .service(
+        web::resource("/user/profile")
+            .route(web::post().to(update_profile))
+    )
+    .service(
+        web::resource("/user/profile/{id}")
+            .route(web::get().to(fetch_profile))
+    )
+    .service(
+        web::resource("/user/posts/{id}")
+            .route(web::get().to(fetch_author_posts))
     );
 }
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::{AuthorProfile, PostSummary};
+
+// The collection `/user/addbookmark` adds to, and where favorites from before collections were moved
+pub const FAVORITES: &str = "Favorites";
+
+// A named collection of bookmarked posts, a user can have many
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bookmark{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub user_id: String,
+    pub name: String, // unique per user
+    pub posts: Vec<String>, // post.id, in the user's order
+    pub position: u32, // of the collection among the user's collections
+    pub share_token: Option<String>, // anyone with the link can see the collection
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Bookmark{
+    pub fn new(user_id: String, name: String, position: u32) -> Bookmark{
+        let now = Utc::now();
+        Bookmark{
+            id: ObjectId::new(),
+            user_id,
+            name,
+            posts: vec![],
+            position,
+            share_token: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+// A collection in listings, without its posts
+#[derive(Debug, Serialize)]
+pub struct BookmarkSummary{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub post_count: usize,
+    pub share_token: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<&Bookmark> for BookmarkSummary{
+    fn from(bookmark: &Bookmark) -> Self{
+        BookmarkSummary{
+            id: bookmark.id,
+            name: bookmark.name.clone(),
+            post_count: bookmark.posts.len(),
+            share_token: bookmark.share_token.clone(),
+            updated_at: bookmark.updated_at,
+        }
+    }
+}
+
+// A collection with its posts in order. Posts that were deleted or can't be seen are left out.
+#[derive(Debug, Serialize)]
+pub struct BookmarkView{
+    #[serde(flatten)]
+    pub summary: BookmarkSummary,
+    pub owner: Option<AuthorProfile>, // on shared collections
+    pub posts: Vec<PostSummary>,
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::AuthorProfile;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum CommentStatus{
+    Pending, // waiting for the post author's approval
+    #[default]
+    Approved,
+    Hidden, // removed by a moderator, kept for the record
+    Quarantined, // flagged by the content filters, waits for a moderator
+}
+
+// A previous version of an edited comment
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommentEdit{
+    pub content: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub replaced_at: DateTime<Utc>,
+}
+
+pub const DELETED_COMMENT: &str = "[deleted]";
+pub const ANONYMOUS: &str = "anon";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Comment{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub post_id: ObjectId,
+    pub parent_id: Option<ObjectId>, // None for comments on the post itself
+    #[serde(default)]
+    pub ancestors: Vec<ObjectId>, // path from the top-level comment down to the parent
+    #[serde(default)]
+    pub depth: u32, // 0 for comments on the post, ancestors.len()
+    pub author_id: String, //anon or user.id
+    #[serde(default)]
+    pub display_name: Option<String>, // name an anonymous commenter chose
+    pub content: String, //max 400 characters
+    pub likes: Vec<String>, //user.id
+    pub like_count: u32,
+    pub reply_count: u32, // direct replies only
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub status: CommentStatus,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub edited_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub history: Vec<CommentEdit>, // oldest first
+    #[serde(default)]
+    pub deleted: bool, // tombstone kept because the comment has replies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_hash: Option<String>, // of anonymous commenters, for duplicate detection
+    #[serde(default)]
+    pub flags: Vec<String>, // why the content filters quarantined it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legacy_id: Option<String>, // uuid of the comment when it was embedded in the post
+}
+
+impl Comment{
+    pub fn new(post_id: ObjectId, parent: Option<&Comment>, author_id: Option<String>, content: String) -> Comment{
+        let ancestors = match parent {
+            Some(parent) => parent.path(),
+            None => vec![],
+        };
+        Comment{
+            id: ObjectId::new(),
+            post_id,
+            parent_id: parent.map(|parent| parent.id),
+            depth: ancestors.len() as u32,
+            ancestors,
+            author_id: author_id.unwrap_or_else(|| ANONYMOUS.to_string()),
+            display_name: None,
+            content,
+            likes: vec![],
+            like_count: 0,
+            reply_count: 0,
+            created_at: Utc::now(),
+            status: CommentStatus::Approved,
+            edited_at: None,
+            history: vec![],
+            deleted: false,
+            ip_hash: None,
+            flags: vec![],
+            legacy_id: None,
+        }
+    }
+
+    // Ids from the top-level comment down to this one
+    pub fn path(&self) -> Vec<ObjectId>{
+        let mut path = self.ancestors.clone();
+        path.push(self.id);
+        path
+    }
+}
+
+// What the api returns for a comment: the author's public profile instead of their id,
+// and nothing about who liked it or what it said before an edit
+#[derive(Debug, Serialize)]
+pub struct CommentView{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub post_id: ObjectId,
+    pub parent_id: Option<ObjectId>,
+    pub ancestors: Vec<ObjectId>,
+    pub depth: u32,
+    pub author: Option<AuthorProfile>, // None for anonymous and deleted comments
+    pub display_name: Option<String>,
+    pub content: String,
+    pub like_count: u32,
+    pub reply_count: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    pub status: CommentStatus,
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+}
+
+impl CommentView{
+    pub fn new(comment: Comment, author: Option<AuthorProfile>) -> CommentView{
+        CommentView{
+            id: comment.id,
+            post_id: comment.post_id,
+            parent_id: comment.parent_id,
+            ancestors: comment.ancestors,
+            depth: comment.depth,
+            author,
+            display_name: comment.display_name,
+            content: comment.content,
+            like_count: comment.like_count,
+            reply_count: comment.reply_count,
+            created_at: comment.created_at,
+            status: comment.status,
+            edited_at: comment.edited_at,
+            deleted: comment.deleted,
+            flags: comment.flags,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::{AuthorProfile, ContributorRole};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum InvitationStatus{
+    Pending,
+    Accepted,
+    Declined,
+    Revoked, // taken back by the owner before it was answered
+}
+
+// The owner asking someone to work on a post, they join `Post.contributors` when they accept
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Invitation{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub post_id: ObjectId,
+    pub inviter: String, // user.id
+    pub invitee: String, // user.id
+    pub role: ContributorRole,
+    pub status: InvitationStatus,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub responded_at: Option<DateTime<Utc>>,
+}
+
+impl Invitation{
+    pub fn new(post_id: ObjectId, inviter: String, invitee: String, role: ContributorRole) -> Invitation{
+        Invitation{
+            id: ObjectId::new(),
+            post_id,
+            inviter,
+            invitee,
+            role,
+            status: InvitationStatus::Pending,
+            created_at: Utc::now(),
+            responded_at: None,
+        }
+    }
+}
+
+// Someone who works on a post, as shown with it
+#[derive(Debug, Serialize, Clone)]
+pub struct ContributorView{
+    #[serde(flatten)]
+    pub profile: AuthorProfile,
+    pub role: ContributorRole,
+}
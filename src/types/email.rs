@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum EmailStatus{
+    Pending, // waiting for its next attempt
+    Sent,
+    Failed, // gave up after MAIL_MAX_ATTEMPTS
+}
+
+// An email in the outbox, the mail worker delivers it and retries when the transport fails
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutgoingEmail{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    pub unsubscribe_url: Option<String>, // sent as List-Unsubscribe
+    pub status: EmailStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl OutgoingEmail{
+    pub fn new(to: String, subject: String, html: String, text: String, unsubscribe_url: Option<String>) -> OutgoingEmail{
+        let now = Utc::now();
+        OutgoingEmail{
+            id: ObjectId::new(),
+            to,
+            subject,
+            html,
+            text,
+            unsubscribe_url,
+            status: EmailStatus::Pending,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+            sent_at: None,
+        }
+    }
+}
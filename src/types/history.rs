@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::PostSummary;
+
+// One per user and post, updated every time they open it or scroll further
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadingHistoryEntry{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub user_id: String,
+    pub post_id: ObjectId,
+    pub progress: u8, // percent of the post scrolled through, as last reported by the client
+    pub finished: bool, // progress reached the end once
+    pub open_count: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub first_read_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub last_read_at: DateTime<Utc>,
+}
+
+// What history listings return, with the post instead of its id
+#[derive(Debug, Serialize)]
+pub struct ReadingHistoryView{
+    pub post: PostSummary,
+    pub progress: u8,
+    pub finished: bool,
+    pub open_count: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub first_read_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub last_read_at: DateTime<Utc>,
+}
+
+impl ReadingHistoryView{
+    pub fn new(entry: ReadingHistoryEntry, post: PostSummary) -> ReadingHistoryView{
+        ReadingHistoryView{
+            post,
+            progress: entry.progress,
+            finished: entry.finished,
+            open_count: entry.open_count,
+            first_read_at: entry.first_read_at,
+            last_read_at: entry.last_read_at,
+        }
+    }
+}
@@ -1,6 +1,17 @@
+mod bookmark;
+mod comment;
+mod email;
+mod history;
+mod notification;
 mod common;
+mod contributor;
 mod permissions;
 mod post;
+mod post_stats;
+mod report;
+mod review;
+mod revision;
+mod series;
 mod tag;
 mod user;
 
@@ -9,11 +20,31 @@ pub static DEFAULT_POST_IMAGE: &'static str = "https://www.eska.org.tr/wp-conten
 
 
 pub use common::Common;
-pub use permissions::Permission;
+pub use permissions::{BanInfo, Capability, Permission, RoleChange};
 pub use post::Post;
 pub use tag::Tag;
 pub use user::User;
-pub use post::Comment;
+pub use user::AuthorProfile;
+pub use user::{AuthorStats, ProfileView, PublicProfile, SocialLink};
+pub use comment::Comment;
+pub use comment::CommentStatus;
+pub use comment::CommentView;
+pub use comment::DELETED_COMMENT;
+pub use post::LegacyComment;
 pub use post::Content;
+pub use post::{Contributor, ContributorRole};
 pub use post::PostStatus;
+pub use post::PostSummary;
+pub use post_stats::{PostAnalytics, PostComparison, PostStatsDay, ReaderStats, ReferrerCount};
+pub use post::ReadingMetrics;
+pub use post::TocEntry;
+pub use revision::Revision;
+pub use review::{ReviewDecision, ReviewEvent};
+pub use contributor::{ContributorView, Invitation, InvitationStatus};
+pub use series::{Series, SeriesLink, SeriesNavigation, SeriesView};
+pub use bookmark::{Bookmark, BookmarkSummary, BookmarkView, FAVORITES};
+pub use email::{EmailStatus, OutgoingEmail};
+pub use history::{ReadingHistoryEntry, ReadingHistoryView};
+pub use notification::{Notification, NotificationKind, NotificationPreferences, NotificationView};
+pub use report::{ModerationAction, ModerationLogEntry, Report, ReportReason, ReportStatus, ReportTarget};
 
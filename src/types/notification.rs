@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::AuthorProfile;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum NotificationKind{
+    Comment, // on your post
+    Reply, // to your comment
+    Like, // of your comment
+    Follow,
+    Invitation, // to work on a post
+    PostApproved, // by an editor, the post is published
+    ChangesRequested, // on a post submitted for review
+    PostRejected,
+    NewPost, // by an author you follow
+}
+
+// Which notifications a user wants, all of them by default
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationPreferences{
+    pub comment: bool,
+    pub reply: bool,
+    pub like: bool,
+    pub follow: bool,
+    #[serde(default = "default_true")]
+    pub invitation: bool,
+    #[serde(default = "default_true")]
+    pub review: bool, // decisions on posts submitted for review
+    #[serde(default = "default_true")]
+    pub new_post: bool,
+}
+
+fn default_true() -> bool{
+    true
+}
+
+impl Default for NotificationPreferences{
+    fn default() -> Self{
+        NotificationPreferences{ comment: true, reply: true, like: true, follow: true, invitation: true, review: true, new_post: true }
+    }
+}
+
+impl NotificationPreferences{
+    pub fn wants(&self, kind: NotificationKind) -> bool{
+        match kind {
+            NotificationKind::Comment => self.comment,
+            NotificationKind::Reply => self.reply,
+            NotificationKind::Like => self.like,
+            NotificationKind::Follow => self.follow,
+            NotificationKind::Invitation => self.invitation,
+            NotificationKind::PostApproved | NotificationKind::ChangesRequested | NotificationKind::PostRejected => self.review,
+            NotificationKind::NewPost => self.new_post,
+        }
+    }
+}
+
+// Events of the same kind about the same thing are coalesced into one unread notification
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub user_id: String, // who is notified
+    pub kind: NotificationKind,
+    pub post_id: Option<ObjectId>,
+    pub comment_id: Option<ObjectId>, // the comment replied to or liked
+    pub actors: Vec<String>, // user.id or "anon", in the order they came
+    pub actor_count: u32,
+    pub read: bool,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>, // the last event
+}
+
+// What the api returns, with the latest actors' profiles and a readable message
+#[derive(Debug, Serialize)]
+pub struct NotificationView{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub kind: NotificationKind,
+    pub post_id: Option<ObjectId>,
+    pub comment_id: Option<ObjectId>,
+    pub actors: Vec<AuthorProfile>, // the last three who are still around, newest first
+    pub actor_count: u32,
+    pub message: String, // "Ayşe and 4 others liked your comment"
+    pub read: bool,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NotificationView{
+    pub fn new(notification: Notification, actors: Vec<AuthorProfile>) -> NotificationView{
+        let who = match (actors.first(), notification.actor_count) {
+            (Some(actor), 1) => actor.name.clone(),
+            (Some(actor), 2) => format!("{} and 1 other", actor.name),
+            (Some(actor), count) => format!("{} and {} others", actor.name, count - 1),
+            (None, 1) => "Someone".to_string(),
+            (None, count) => format!("{} people", count),
+        };
+        let what = match notification.kind {
+            NotificationKind::Comment => "commented on your post",
+            NotificationKind::Reply => "replied to your comment",
+            NotificationKind::Like => "liked your comment",
+            NotificationKind::Follow => "started following you",
+            NotificationKind::Invitation => "invited you to work on a post",
+            NotificationKind::PostApproved => "approved your post",
+            NotificationKind::ChangesRequested => "requested changes to your post",
+            NotificationKind::PostRejected => "rejected your post",
+            NotificationKind::NewPost => "published a new post",
+        };
+        NotificationView{
+            id: notification.id,
+            kind: notification.kind,
+            post_id: notification.post_id,
+            comment_id: notification.comment_id,
+            actors,
+            actor_count: notification.actor_count,
+            message: format!("{} {}", who, what),
+            read: notification.read,
+            created_at: notification.created_at,
+            updated_at: notification.updated_at,
+        }
+    }
+}
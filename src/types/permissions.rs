@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub enum Permission{
     Banned,
     Guest,
     Author,
+    Editor, // reviews posts before they are published
     Admin
 }
 impl Permission{
@@ -13,7 +16,87 @@ impl Permission{
             Self::Banned => "banned".to_string(),
             Self::Guest => "guest".to_string(),
             Self::Author => "author".to_string(),
+            Self::Editor => "editor".to_string(),
             Self::Admin => "admin".to_string(),
         }
     }
-}
\ No newline at end of file
+}
+
+// Something a user may do. Roles map to sets of capabilities in CAPABILITIES_FILE,
+// and single users can be granted or denied ones on top of their role.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Capability{
+    #[serde(rename = "post.create")]
+    PostCreate,
+    #[serde(rename = "post.publish")]
+    PostPublish, // make a post public or schedule it, directly or by submitting it for review
+    #[serde(rename = "post.review")]
+    PostReview, // approve, reject or send back posts submitted for review
+    #[serde(rename = "post.moderate")]
+    PostModerate, // release or delete quarantined posts
+    #[serde(rename = "comment.create")]
+    CommentCreate,
+    #[serde(rename = "comment.moderate")]
+    CommentModerate, // on every post, authors moderate their own posts' comments anyway
+    #[serde(rename = "tag.manage")]
+    TagManage, // curate the tags posts can be filed under
+    #[serde(rename = "report.manage")]
+    ReportManage,
+    #[serde(rename = "user.ban")]
+    UserBan,
+    #[serde(rename = "user.roles")]
+    UserRoles, // change roles and capabilities of other users
+    #[serde(rename = "outbox.manage")]
+    OutboxManage,
+    #[serde(rename = "analytics.view")]
+    AnalyticsView, // site wide metrics on the admin dashboard
+}
+
+impl Capability{
+    pub const ALL: [Capability; 12] = [
+        Capability::PostCreate,
+        Capability::PostPublish,
+        Capability::PostReview,
+        Capability::PostModerate,
+        Capability::CommentCreate,
+        Capability::CommentModerate,
+        Capability::TagManage,
+        Capability::ReportManage,
+        Capability::UserBan,
+        Capability::UserRoles,
+        Capability::OutboxManage,
+        Capability::AnalyticsView,
+    ];
+}
+
+// Why and until when a user is banned, cleared when the ban is lifted
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BanInfo{
+    pub reason: String,
+    pub banned_by: String, // user.id of the admin or moderator
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub banned_at: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub expires_at: Option<DateTime<Utc>>, // None for a permanent ban
+    pub previous: Permission, // given back when the ban is lifted
+}
+
+impl BanInfo{
+    pub fn expired(&self) -> bool{
+        self.expires_at.map(|at| at <= Utc::now()).unwrap_or(false)
+    }
+}
+
+// One entry of the history of a user's permission
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoleChange{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub user_id: String,
+    pub from: Permission,
+    pub to: Permission,
+    pub changed_by: String, // user.id, or "bootstrap", "cli" and "system" for expired bans
+    pub reason: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+}
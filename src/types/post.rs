@@ -1,11 +1,11 @@
 use serde::{Deserialize, Serialize};
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{self, Document};
+use mongodb::bson::{self, doc, Document};
 use super::Tag;
+use super::ReviewEvent;
 use chrono::serde::ts_seconds::deserialize as from_ts;
 use chrono::{DateTime, Utc};
 use std::time::Duration;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Content{
@@ -13,37 +13,97 @@ pub struct Content{
     pub markdown:String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TocEntry{
+    pub level: u8, // 1-6, from <h1>..<h6>
+    pub title: String,
+    pub anchor: String, // id of the heading in content.html
+}
+
+// Computed from the sanitized html whenever the content changes
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReadingMetrics{
+    pub language: String, // "tr", "en", ...
+    pub word_count: u32,
+    pub character_count: u32, // without whitespace
+    pub image_count: u32,
+    pub code_block_count: u32,
+    pub seconds: u32, // estimated reading time
+    pub minutes: u32, // `seconds` rounded up, same as `Post.read_time`
+    pub readability: Option<f64>, // 0 (hard) - 100 (easy), None for empty posts
+}
+
+// Comments as they were embedded in `Post.comments`, only read by the migration to the comments collection
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Comment{
+pub struct LegacyComment{
     pub author_id:String, //anon or user.id
     pub content:String, //max 250 characters
     pub id: String,
     pub likes: Vec<String>, //user.id
-    pub replies: Vec<Comment>
+    pub replies: Vec<LegacyComment>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum PostStatus{
+   Draft,
+   Scheduled, // becomes Public at `scheduled_at`
+   Public,
+   Private,
+   OnlyFriends,
+   Deleted,
+   Quarantined, // flagged by the content filters when it was created, an admin releases it
+   Hidden, // taken down by a moderator
+   InReview, // submitted by an author who needs review, waits for an editor
+   ChangesRequested, // sent back to the author by an editor
+   Rejected,
 }
 
+impl PostStatus{
+    // Taken down by a moderator or deleted, neither the author nor contributors can change it
+    pub fn is_locked(&self) -> bool{
+        matches!(self, PostStatus::Hidden | PostStatus::Deleted)
+    }
+}
 
+// What someone other than the owner may do on a post
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ContributorRole{
+   Owner, // `Post.author`, never stored in `Post.contributors`
+   CoAuthor, // edits, publishes and is credited as an author
+   Editor, // edits
+   Reviewer, // reads drafts
+}
 
-impl Comment{
-    pub fn new(author_name:Option<String>, content:String)->Comment{
-        let mut name = author_name.unwrap_or("anon".to_string()); 
-        let id = Uuid::new_v4();
-        Comment{
-            author_id: name,
-            content: content,
-            id: id.to_string(),
-            likes:vec![],
-            replies:vec![]
-        }
+impl ContributorRole{
+    pub fn can_edit(&self) -> bool{
+        matches!(self, ContributorRole::Owner | ContributorRole::CoAuthor | ContributorRole::Editor)
+    }
+
+    pub fn can_publish(&self) -> bool{
+        matches!(self, ContributorRole::Owner | ContributorRole::CoAuthor)
+    }
+
+    // Credited as an author of the post
+    pub fn is_author(&self) -> bool{
+        matches!(self, ContributorRole::Owner | ContributorRole::CoAuthor)
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub enum PostStatus{
-   Public,
-   Private,
-   OnlyFriends,
-   Deleted
+pub struct Contributor{
+    pub user_id: String,
+    pub role: ContributorRole,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub added_at: DateTime<Utc>,
+}
+
+fn default_true() -> bool{
+    true
+}
+
+// Posts written before languages were stored are Turkish
+fn default_language() -> String{
+    "tr".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,6 +116,10 @@ pub struct Post{
     pub created_at: DateTime<Utc>,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub published_at: Option<DateTime<Utc>>, // None until the post goes public
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub scheduled_at: Option<DateTime<Utc>>,
     pub image: String, //optional, url of image    
     pub content: Content,
     pub likes: Vec<String>, //user.id
@@ -64,11 +128,69 @@ pub struct Post{
     pub status:PostStatus,
     pub tags: Vec<String>,
     pub read_time: u32, // in minutes, for example => 5 = 5 Minutes 
-    pub comments: Vec<Comment> 
+    #[serde(default)]
+    pub excerpt: String, // first words of the plain text, for listings
+    #[serde(default)]
+    pub toc: Vec<TocEntry>,
+    #[serde(default = "default_language")]
+    pub language: String,
+    #[serde(default)]
+    pub metrics: ReadingMetrics,
+    #[serde(default)]
+    pub comment_count: u32, // approved comments and replies
+    #[serde(default)]
+    pub require_comment_approval: bool, // new comments stay pending until the author approves them
+    #[serde(default = "default_true")]
+    pub allow_anonymous_comments: bool, // also needs ALLOW_ANONYMOUS_COMMENTS on the site
+    #[serde(default)]
+    pub quarantine_reasons: Vec<String>,
+    #[serde(default)]
+    pub series_id: Option<ObjectId>,
+    #[serde(default)]
+    pub contributors: Vec<Contributor>, // accepted invitations, without the owner
+    #[serde(default)]
+    pub review_history: Vec<ReviewEvent>,
+}
+
+// What listing endpoints return instead of the whole post
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostSummary{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub title: String,
+    pub author: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub published_at: Option<DateTime<Utc>>,
+    pub image: String,
+    pub status: PostStatus,
+    pub tags: Vec<String>,
+    pub views: u32,
+    pub read_time: u32,
+    #[serde(default)]
+    pub excerpt: String,
+    #[serde(default)]
+    pub comment_count: u32,
+    #[serde(default)]
+    pub contributors: Vec<Contributor>,
+}
+
+impl PostSummary{
+    pub fn projection() -> Document{
+        doc! {
+            "title": 1, "author": 1, "created_at": 1, "published_at": 1, "image": 1,
+            "status": 1, "tags": 1, "views": 1, "read_time": 1, "excerpt": 1, "comment_count": 1, "contributors": 1,
+        }
+    }
 }
 impl Post{
     pub fn new(title:String, author:String, image:String, content:Content, status:PostStatus, tags:Vec<String>, read_time:u32) -> Post{
         let now = Utc::now();
+        let published_at = match status {
+            PostStatus::Public => Some(now),
+            _ => None,
+        };
 
         Post{
             id: ObjectId::new(),
@@ -84,7 +206,26 @@ impl Post{
             tags:tags,
             updated_at: now,
             created_at: now,
-            comments: vec![]
+            published_at,
+            scheduled_at: None,
+            excerpt: String::new(),
+            toc: vec![],
+            language: default_language(),
+            metrics: ReadingMetrics::default(),
+            comment_count: 0,
+            require_comment_approval: false,
+            allow_anonymous_comments: true,
+            quarantine_reasons: vec![],
+            series_id: None,
+            contributors: vec![],
+            review_history: vec![],
+        }
+    }
+
+    pub fn role_of(&self, user_id: &str) -> Option<ContributorRole>{
+        if self.author == user_id {
+            return Some(ContributorRole::Owner);
         }
+        self.contributors.iter().find(|contributor| contributor.user_id == user_id).map(|contributor| contributor.role)
     }
 }
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+
+// What happened to a post on one UTC day, one document per post and day
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PostStatsDay{
+    pub date: String, // "2023-05-17"
+    #[serde(default)]
+    pub views: i64,
+    #[serde(default)]
+    pub likes: i64, // net, likes taken back that day are subtracted
+    #[serde(default)]
+    pub dislikes: i64,
+    #[serde(default)]
+    pub comments: i64, // approved comments, net of removed ones
+    #[serde(default)]
+    pub new_readers: i64, // signed in readers opening the post for the first time
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReferrerCount{
+    pub domain: String, // "direct" without a Referer header
+    pub views: i64,
+}
+
+// From the reading history, so only signed in readers who didn't pause their history count
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReaderStats{
+    pub unique_readers: i64,
+    pub finished_readers: i64,
+    pub average_progress: f64, // percent
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostAnalytics{
+    pub post_id: ObjectId,
+    pub title: String,
+    pub views: u32, // all time
+    pub likes: usize, // all time
+    pub dislikes: usize,
+    pub comment_count: u32,
+    #[serde(flatten)]
+    pub readers: ReaderStats,
+    pub daily: Vec<PostStatsDay>, // every day of the range, days without events are zero
+    pub referrers: Vec<ReferrerCount>, // in the range, most views first
+}
+
+// One row of the comparison of an author's posts
+#[derive(Debug, Serialize)]
+pub struct PostComparison{
+    pub post_id: ObjectId,
+    pub title: String,
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub published_at: Option<DateTime<Utc>>,
+    pub views: u32, // all time
+    pub views_in_range: i64,
+    pub likes: usize,
+    pub comment_count: u32,
+    #[serde(flatten)]
+    pub readers: ReaderStats,
+}
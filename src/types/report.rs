@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ReportTarget{
+    Post,
+    Comment,
+    User,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ReportReason{
+    Spam,
+    Harassment,
+    HateSpeech,
+    Violence,
+    SexualContent,
+    Misinformation,
+    Copyright,
+    Other, // `details` says what
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ReportStatus{
+    Open,
+    Dismissed, // nothing wrong with the content
+    Resolved, // a moderator acted on it
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ModerationAction{
+    Dismiss,
+    Resolve, // handled outside the api, e.g. by talking to the author
+    Hide,
+    Ban, // the author of the content, or the reported user
+    Approve, // released from quarantine or the approval queue
+    Delete,
+    Restore, // a hidden post put back up
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Report{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub target_type: ReportTarget,
+    pub target_id: ObjectId,
+    pub reporter_id: String, // user.id
+    pub reason: ReportReason,
+    pub details: Option<String>,
+    pub status: ReportStatus,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<String>, // user.id of the moderator
+    pub action: Option<ModerationAction>,
+}
+
+impl Report{
+    pub fn new(target_type: ReportTarget, target_id: ObjectId, reporter_id: String, reason: ReportReason, details: Option<String>) -> Report{
+        Report{
+            id: ObjectId::new(),
+            target_type,
+            target_id,
+            reporter_id,
+            reason,
+            details,
+            status: ReportStatus::Open,
+            created_at: Utc::now(),
+            resolved_at: None,
+            resolved_by: None,
+            action: None,
+        }
+    }
+}
+
+// The audit trail, one entry for every moderator action
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModerationLogEntry{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub moderator_id: String, // user.id
+    pub action: ModerationAction,
+    pub target_type: ReportTarget,
+    pub target_id: ObjectId,
+    pub report_id: Option<ObjectId>, // when the action came from the report queue
+    pub note: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use super::PostStatus;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ReviewDecision{
+    Approve, // publishes the post, or schedules it when the author asked for a later date
+    RequestChanges, // back to the author, who can edit and submit again
+    Reject,
+}
+
+// A status change of a post going through review, kept on the post in order
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReviewEvent{
+    pub from: PostStatus,
+    pub to: PostStatus,
+    pub by: String, // user.id of the author submitting or the reviewer deciding
+    pub feedback: Option<String>, // why changes were requested or the post was rejected
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub at: DateTime<Utc>,
+}
+
+impl ReviewEvent{
+    pub fn new(from: PostStatus, to: PostStatus, by: String, feedback: Option<String>) -> ReviewEvent{
+        ReviewEvent{ from, to, by, feedback, at: Utc::now() }
+    }
+}
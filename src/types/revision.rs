@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::post::{Content, Post};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Revision{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub post_id: ObjectId,
+    pub number: u32, // 1 = the version the post was created with
+    pub author: String, // user.id of whoever made the change
+    pub title: String,
+    pub content: Content,
+    pub restored_from: Option<u32>, // set when the revision is a rollback
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl Revision{
+    pub fn from_post(post: &Post, number: u32, author: String, restored_from: Option<u32>) -> Revision{
+        Revision{
+            id: ObjectId::new(),
+            post_id: post.id,
+            number,
+            author,
+            title: post.title.clone(),
+            content: post.content.clone(),
+            restored_from,
+            created_at: Utc::now(),
+        }
+    }
+}
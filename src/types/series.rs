@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use super::{AuthorProfile, PostSummary};
+
+// Posts of one author that are read in order, a post is part of at most one series
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Series{
+    #[serde(rename = "_id", default)]
+    pub id: ObjectId,
+    pub author: String, // user.id
+    pub title: String,
+    pub description: String,
+    pub posts: Vec<String>, // post.id, part 1 first
+    pub follower_count: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Series{
+    pub fn new(author: String, title: String, description: String) -> Series{
+        let now = Utc::now();
+        Series{
+            id: ObjectId::new(),
+            author,
+            title,
+            description,
+            posts: vec![],
+            follower_count: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+// A series page, with the parts the reader can see
+#[derive(Debug, Serialize)]
+pub struct SeriesView{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub title: String,
+    pub description: String,
+    pub author: Option<AuthorProfile>,
+    pub posts: Vec<PostSummary>,
+    pub post_count: usize,
+    pub total_read_time: u32, // minutes, the sum of the parts' `read_time`
+    pub follower_count: u32,
+    pub following: bool, // whether the reader follows it
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SeriesLink{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub title: String,
+}
+
+// Returned with a post that is part of a series, counting only public parts
+#[derive(Debug, Serialize)]
+pub struct SeriesNavigation{
+    pub series_id: ObjectId,
+    pub title: String,
+    pub part: usize, // 1-based, 0 when the post itself isn't public yet
+    pub total_parts: usize,
+    pub previous: Option<SeriesLink>,
+    pub next: Option<SeriesLink>,
+}
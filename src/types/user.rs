@@ -1,15 +1,14 @@
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{self, Document};
+use mongodb::bson::{self, doc, Document};
 use serde::{Deserialize, Serialize};
-use super::permissions::Permission;
+use super::permissions::{BanInfo, Capability, Permission};
 use super::post::Post;
+use super::notification::NotificationPreferences;
 use chrono::serde::ts_seconds::deserialize as from_ts;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Bookmark{
-    name:String,
-    posts:Vec<String> // post.id
+fn default_language() -> String{
+    "tr".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,13 +27,107 @@ pub struct User{
     pub updated_at: DateTime<Utc>,
     pub registred_via: String, // "google" or "email"
     pub avatar: Option<String>, // aws s3 object link
-    pub view_list: Vec<String>, //Vec<blog.id>
     pub likes: Vec<String>,    // Vec<blog.id>
     pub dislikes: Vec<String>, // Vec<blog.id>
-    pub favorites: Vec<String> // Vec<blog.id>
+    #[serde(default)]
+    pub failed_logins: u32, // wrong passwords since the last successful login or lockout
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub locked_until: Option<DateTime<Utc>>, // set after too many wrong passwords
+    #[serde(default)]
+    pub notification_preferences: NotificationPreferences,
+    #[serde(default)]
+    pub following: Vec<String>, // user.id
+    #[serde(default)]
+    pub followed_tags: Vec<String>, // tag.name
+    #[serde(default)]
+    pub followed_series: Vec<String>, // series.id
+    #[serde(default)]
+    pub email_digest: bool, // weekly digest of followed authors and tags, opt in
+    #[serde(default = "default_language")]
+    pub language: String, // of the emails, "tr" or "en"
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub last_digest_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub history_paused: bool, // opened posts are not added to the reading history
+    #[serde(default)]
+    pub bio: Option<String>,
+    #[serde(default)]
+    pub social_links: Vec<SocialLink>,
+    #[serde(default)]
+    pub ban: Option<BanInfo>, // while `permission` is Banned
+    #[serde(default)]
+    pub granted_capabilities: Vec<Capability>, // on top of the role's
+    #[serde(default)]
+    pub revoked_capabilities: Vec<Capability>, // taken away from the role's
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SocialLink{
+    pub platform: String, // "twitter", "github", "website"...
+    pub url: String,
+}
+
+// The public part of a user, embedded where their content is shown
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthorProfile{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub avatar: Option<String>,
+}
+
+impl AuthorProfile{
+    pub fn projection() -> Document{
+        doc! {"name": 1, "avatar": 1}
+    }
+}
+
+// Everything anyone may see about a user, on their profile page
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublicProfile{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub bio: Option<String>,
+    #[serde(default)]
+    pub social_links: Vec<SocialLink>,
+    #[serde(alias = "created_at", with = "chrono::serde::ts_seconds")]
+    pub joined_at: DateTime<Utc>,
+}
+
+impl PublicProfile{
+    // The fields a profile is read from, also the only ones `/user/fetch` hands out about other users
+    pub const FIELDS: [&'static str; 5] = ["name", "avatar", "bio", "social_links", "created_at"];
+
+    pub fn projection() -> Document{
+        PublicProfile::FIELDS.iter().map(|field| (field.to_string(), 1.into())).collect()
+    }
+}
+
+// Computed from the author's public posts and followers
+#[derive(Debug, Serialize, Default)]
+pub struct AuthorStats{
+    pub post_count: u64,
+    pub total_views: u64,
+    pub total_likes: u64,
+    pub follower_count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileView{
+    #[serde(flatten)]
+    pub profile: PublicProfile,
+    pub stats: AuthorStats,
 }
 
 impl User {
+    pub fn profile(&self) -> AuthorProfile {
+        AuthorProfile { id: self.id, name: self.name.clone(), avatar: self.avatar.clone() }
+    }
+
+
     pub fn to_document(&self) -> Document {
         bson::to_document(self).unwrap()
     }
@@ -56,10 +149,23 @@ impl User {
             updated_at: now,
             registred_via: registered_via,
             avatar: avatar,
-            view_list: vec![],
             likes: vec![],
             dislikes: vec![],
-            favorites: vec![],
+            failed_logins: 0,
+            locked_until: None,
+            notification_preferences: NotificationPreferences::default(),
+            following: vec![],
+            followed_tags: vec![],
+            followed_series: vec![],
+            email_digest: false,
+            language: default_language(),
+            last_digest_at: None,
+            history_paused: false,
+            bio: None,
+            social_links: vec![],
+            ban: None,
+            granted_capabilities: vec![],
+            revoked_capabilities: vec![],
         }
     }
 }
@@ -0,0 +1,110 @@
+use std::str::FromStr;
+
+use actix_web::{dev::Payload, error::InternalError, http::Method, web, FromRequest, HttpRequest, HttpResponse};
+use futures::future::{ready, FutureExt, LocalBoxFuture};
+use mongodb::{Database, bson::{doc, oid::ObjectId}};
+use serde_json::json;
+
+use crate::types::{Capability, Permission, User};
+use super::jwt::verify_jwt;
+use super::roles::lift_expired_ban;
+use super::capabilities::has_capability;
+
+// The user behind the `Authorization: Bearer <token>` header.
+// Use `Option<AuthUser>` in a handler when signing in is not required to read,
+// and `OptionalAuthUser` when it is not required to write.
+// Banned users can still read, every request that changes something is refused here.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: ObjectId,
+}
+
+impl AuthUser {
+    pub fn from_token(token: &str) -> Option<AuthUser> {
+        let user_id = verify_jwt(token).ok()?;
+        let id = ObjectId::from_str(&user_id).ok()?;
+        Some(AuthUser { id })
+    }
+
+    pub fn id_str(&self) -> String {
+        self.id.to_hex()
+    }
+
+    // The user document, a token of a deleted user is not accepted
+    pub async fn fetch(&self, db: &Database) -> Result<User, HttpResponse> {
+        match db.collection::<User>("users").find_one(doc! {"_id": self.id}, None).await {
+            Ok(Some(user)) => lift_expired_ban(db, user)
+                .await
+                .map_err(|e| HttpResponse::InternalServerError().json(json!({"error": format!("Failed to update user: {}", e)}))),
+            Ok(None) => Err(HttpResponse::Unauthorized().json(json!({"error":"User not found"}))),
+            Err(e) => Err(HttpResponse::InternalServerError().json(json!({"error": format!("Failed to fetch user: {}", e)}))),
+        }
+    }
+
+    pub async fn has_capability(&self, db: &Database, capability: Capability) -> Result<bool, HttpResponse> {
+        Ok(has_capability(&self.fetch(db).await?, capability))
+    }
+
+    // The user, if they have `capability`
+    pub async fn require_capability(&self, db: &Database, capability: Capability) -> Result<User, HttpResponse> {
+        let user = self.fetch(db).await?;
+        if !has_capability(&user, capability) {
+            let name = serde_json::to_value(capability).unwrap();
+            return Err(HttpResponse::Forbidden().json(json!({"error":"You are not allowed to do this", "capability": name})));
+        }
+        Ok(user)
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user = req
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| AuthUser::from_token(token.trim()));
+
+        let user = match user {
+            Some(user) => user,
+            None => {
+                let response = HttpResponse::Unauthorized().json(json!({"error":"Missing or invalid token"}));
+                return ready(Err(InternalError::from_response("unauthorized", response).into())).boxed_local();
+            }
+        };
+        let db = match req.app_data::<web::Data<Database>>() {
+            Some(db) if !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) => db.clone(),
+            _ => return ready(Ok(user)).boxed_local(),
+        };
+        async move {
+            let found = user.fetch(&db).await.map_err(|response| InternalError::from_response("user", response))?;
+            if found.permission == Permission::Banned {
+                let ban = found.ban.map(|ban| json!({"reason": ban.reason, "expires_at": ban.expires_at.map(|at| at.timestamp())}));
+                let response = HttpResponse::Forbidden().json(json!({"error":"Your account is banned", "ban": ban}));
+                return Err(InternalError::from_response("banned", response).into());
+            }
+            Ok(user)
+        }
+        .boxed_local()
+    }
+}
+
+// The signed in user, or None when there is no Authorization header at all.
+// An invalid token or a banned user is refused instead of falling back to anonymous.
+#[derive(Debug, Clone)]
+pub struct OptionalAuthUser(pub Option<AuthUser>);
+
+impl FromRequest for OptionalAuthUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if !req.headers().contains_key("authorization") {
+            return ready(Ok(OptionalAuthUser(None))).boxed_local();
+        }
+        AuthUser::from_request(req, payload).map(|user| user.map(|user| OptionalAuthUser(Some(user)))).boxed_local()
+    }
+}
@@ -0,0 +1,59 @@
+use std::{collections::{BTreeSet, HashMap}, env, fs, sync::OnceLock};
+
+use crate::types::{Capability, Permission, User};
+
+static ROLE_CAPABILITIES: OnceLock<HashMap<Permission, Vec<Capability>>> = OnceLock::new();
+
+fn default_capabilities(permission: &Permission) -> Vec<Capability> {
+    // Guests write drafts and comment, publishing starts at Author
+    let guest = vec![Capability::PostCreate, Capability::CommentCreate];
+    let author = [guest.clone(), vec![Capability::PostPublish]].concat();
+    match permission {
+        Permission::Banned => vec![],
+        Permission::Guest => guest,
+        Permission::Author => author,
+        Permission::Editor => [author, vec![Capability::PostReview, Capability::CommentModerate, Capability::TagManage]].concat(),
+        Permission::Admin => Capability::ALL.to_vec(),
+    }
+}
+
+// CAPABILITIES_FILE is a JSON object from role to capability names, for example
+// {"Editor": ["post.create", "post.publish", "comment.create", "post.review"]}.
+// Roles left out of it, or every role without it, keep the defaults above.
+pub fn load_capabilities() {
+    let mut roles: HashMap<Permission, Vec<Capability>> = match env::var("CAPABILITIES_FILE") {
+        Ok(path) => {
+            let config = fs::read_to_string(&path).unwrap_or_else(|e| panic!("can't read CAPABILITIES_FILE from {}: {}", path, e));
+            serde_json::from_str(&config).unwrap_or_else(|e| panic!("invalid CAPABILITIES_FILE {}: {}", path, e))
+        }
+        Err(_) => HashMap::new(),
+    };
+    for permission in [Permission::Banned, Permission::Guest, Permission::Author, Permission::Editor, Permission::Admin] {
+        let defaults = default_capabilities(&permission);
+        roles.entry(permission).or_insert(defaults);
+    }
+    let _ = ROLE_CAPABILITIES.set(roles);
+}
+
+pub fn role_capabilities(permission: &Permission) -> Vec<Capability> {
+    match ROLE_CAPABILITIES.get() {
+        Some(roles) => roles.get(permission).cloned().unwrap_or_default(),
+        None => default_capabilities(permission),
+    }
+}
+
+// The role's capabilities with the user's own grants and revokes applied. Grants don't outlive a ban.
+pub fn effective_capabilities(user: &User) -> BTreeSet<Capability> {
+    let mut capabilities: BTreeSet<Capability> = role_capabilities(&user.permission).into_iter().collect();
+    if user.permission != Permission::Banned {
+        capabilities.extend(user.granted_capabilities.iter().copied());
+    }
+    for capability in &user.revoked_capabilities {
+        capabilities.remove(capability);
+    }
+    capabilities
+}
+
+pub fn has_capability(user: &User, capability: Capability) -> bool {
+    effective_capabilities(user).contains(&capability)
+}
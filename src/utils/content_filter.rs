@@ -0,0 +1,331 @@
+use std::{collections::{HashMap, HashSet}, env, fs};
+
+use actix_web::HttpRequest;
+use chrono::{Duration, Utc};
+use dotenv::dotenv;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, oid::ObjectId, Document}, options::{FindOptions, UpdateOptions}};
+use scraper::{Html, Selector};
+
+use crate::types::Post;
+use super::rate_limit::client_ip;
+use super::reading_metrics::html_to_text;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentKind {
+    Comment,
+    Post,
+}
+
+// Something a user wants to publish
+pub struct Submission {
+    pub kind: ContentKind,
+    pub text: String,
+    pub links: usize,
+    pub author_id: Option<String>, // None for anonymous comments
+    pub ip_hash: Option<String>,
+    pub comment_id: Option<ObjectId>, // an edited comment is not a duplicate of itself
+}
+
+impl Submission {
+    pub fn comment(text: &str, comment_id: ObjectId, author_id: Option<String>, ip_hash: Option<String>) -> Submission {
+        // Every word that looks like an address counts, whether or not it will be linked
+        let links = text.split_whitespace().filter(|word| word.contains("://") || word.starts_with("www.")).count();
+        Submission { kind: ContentKind::Comment, text: text.to_string(), links, author_id, ip_hash, comment_id: Some(comment_id) }
+    }
+
+    // Post html is sanitized before it gets here, so the links are its <a> tags
+    pub fn post(post: &Post) -> Submission {
+        let document = Html::parse_fragment(&post.content.html);
+        let links = document.select(&Selector::parse("a[href]").unwrap()).count();
+        let text = format!("{}\n{}", post.title, html_to_text(&post.content.html));
+        Submission { kind: ContentKind::Post, text, links, author_id: Some(post.author.clone()), ip_hash: None, comment_id: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Quarantine(String), // published only after a moderator looks at it
+    Reject(String),
+}
+
+// What the filters know besides the submission itself, loaded once for all of them
+pub struct FilterContext {
+    pub folded: String,
+    pub tokens: Vec<String>,
+    pub recent: Vec<String>, // folded texts the same user or IP sent in the last day
+    pub spam_probability: Option<f64>, // None until the model has been trained enough
+}
+
+pub trait ContentFilter: Send + Sync {
+    fn check(&self, submission: &Submission, context: &FilterContext) -> Verdict;
+}
+
+// Lower case without Turkish letters, so "ŞAPŞAL", "şapşal" and "sapsal" are the same word
+pub fn fold(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match c {
+            'I' | 'İ' | 'ı' | 'î' | 'Î' => vec!['i'],
+            'ç' | 'Ç' => vec!['c'],
+            'ğ' | 'Ğ' => vec!['g'],
+            'ö' | 'Ö' => vec!['o'],
+            'ş' | 'Ş' => vec!['s'],
+            'ü' | 'Ü' | 'û' | 'Û' => vec!['u'],
+            'â' | 'Â' => vec!['a'],
+            c => c.to_lowercase().collect(),
+        })
+        .collect()
+}
+
+pub fn tokens(folded: &str) -> Vec<String> {
+    folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| (2..=30).contains(&token.chars().count()))
+        .map(str::to_string)
+        .collect()
+}
+
+// Hash of the client's address, enough to recognize them again without storing the address
+pub fn client_ip_hash(req: &HttpRequest) -> Option<String> {
+    dotenv().ok();
+    let ip = client_ip(req)?;
+    let secret = env::var("JSON_SECRET").unwrap_or_default();
+    Some(sha256::digest(format!("{}{}", secret, ip)))
+}
+
+// A comma separated list, or "@<path>" for a file with one word per line
+fn word_list(var: &str) -> Vec<String> {
+    let value = env::var(var).unwrap_or_default();
+    let words = match value.strip_prefix('@') {
+        Some(path) => fs::read_to_string(path).unwrap_or_else(|e| panic!("can't read {} from {}: {}", var, path, e)),
+        None => value.replace(',', "\n"),
+    };
+    words.lines().map(|word| fold(word.trim())).filter(|word| !word.is_empty()).collect()
+}
+
+fn env_number<T: std::str::FromStr>(var: &str, default: T) -> T {
+    env::var(var).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+// Words are matched at the start of a token, Turkish adds suffixes to the word ("aptallar", "aptalsın")
+pub struct WordListFilter {
+    words: Vec<String>,
+    reject: bool,
+}
+
+impl ContentFilter for WordListFilter {
+    fn check(&self, _: &Submission, context: &FilterContext) -> Verdict {
+        let found = context.tokens.iter().find(|token| self.words.iter().any(|word| token.starts_with(word.as_str())));
+        match found {
+            Some(_) if self.reject => Verdict::Reject("Content contains a blocked word".to_string()),
+            Some(_) => Verdict::Quarantine("Content contains a flagged word".to_string()),
+            None => Verdict::Allow,
+        }
+    }
+}
+
+pub struct LinkFilter {
+    max_comment_links: usize,
+    max_post_links: usize,
+}
+
+impl ContentFilter for LinkFilter {
+    fn check(&self, submission: &Submission, _: &FilterContext) -> Verdict {
+        let max = match submission.kind {
+            ContentKind::Comment => self.max_comment_links,
+            ContentKind::Post => self.max_post_links,
+        };
+        if submission.links > max {
+            Verdict::Quarantine(format!("Content has more than {} links", max))
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+pub struct DuplicateFilter;
+
+impl ContentFilter for DuplicateFilter {
+    fn check(&self, _: &Submission, context: &FilterContext) -> Verdict {
+        let text = context.folded.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if context.recent.contains(&text) {
+            Verdict::Reject("The same message was already sent".to_string())
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+pub struct SpamFilter {
+    threshold: f64,
+}
+
+impl ContentFilter for SpamFilter {
+    fn check(&self, _: &Submission, context: &FilterContext) -> Verdict {
+        match context.spam_probability {
+            Some(probability) if probability >= self.threshold => Verdict::Quarantine("Content looks like spam".to_string()),
+            _ => Verdict::Allow,
+        }
+    }
+}
+
+pub struct FilterOutcome {
+    pub verdict: Verdict,
+    pub reasons: Vec<String>, // of every filter that did not allow the content
+}
+
+// Runs every filter, the strictest verdict wins
+pub struct ContentFilterPipeline {
+    filters: Vec<Box<dyn ContentFilter>>,
+}
+
+impl ContentFilterPipeline {
+    pub fn new(filters: Vec<Box<dyn ContentFilter>>) -> ContentFilterPipeline {
+        ContentFilterPipeline { filters }
+    }
+
+    // BLOCKED_WORDS reject, FLAGGED_WORDS quarantine, COMMENT_MAX_LINKS (default 2), POST_MAX_LINKS (default 30),
+    // SPAM_THRESHOLD (default 0.9)
+    pub fn from_env() -> ContentFilterPipeline {
+        dotenv().ok();
+        let mut filters: Vec<Box<dyn ContentFilter>> = vec![];
+        let blocked = word_list("BLOCKED_WORDS");
+        if !blocked.is_empty() {
+            filters.push(Box::new(WordListFilter { words: blocked, reject: true }));
+        }
+        let flagged = word_list("FLAGGED_WORDS");
+        if !flagged.is_empty() {
+            filters.push(Box::new(WordListFilter { words: flagged, reject: false }));
+        }
+        filters.push(Box::new(LinkFilter {
+            max_comment_links: env_number("COMMENT_MAX_LINKS", 2),
+            max_post_links: env_number("POST_MAX_LINKS", 30),
+        }));
+        filters.push(Box::new(DuplicateFilter));
+        filters.push(Box::new(SpamFilter { threshold: env_number("SPAM_THRESHOLD", 0.9) }));
+        ContentFilterPipeline::new(filters)
+    }
+
+    pub async fn check(&self, db: &Database, submission: &Submission) -> mongodb::error::Result<FilterOutcome> {
+        let folded = fold(&submission.text);
+        let tokens = tokens(&folded);
+        let context = FilterContext {
+            recent: recent_messages(db, submission).await?,
+            spam_probability: spam_probability(db, &tokens).await?,
+            tokens,
+            folded,
+        };
+
+        let mut verdict = Verdict::Allow;
+        let mut reasons = vec![];
+        for filter in &self.filters {
+            match filter.check(submission, &context) {
+                Verdict::Allow => {}
+                Verdict::Quarantine(reason) => {
+                    if verdict == Verdict::Allow {
+                        verdict = Verdict::Quarantine(reason.clone());
+                    }
+                    reasons.push(reason);
+                }
+                Verdict::Reject(reason) => {
+                    if !matches!(verdict, Verdict::Reject(_)) {
+                        verdict = Verdict::Reject(reason.clone());
+                    }
+                    reasons.push(reason);
+                }
+            }
+        }
+
+        Ok(FilterOutcome { verdict, reasons })
+    }
+}
+
+// Comments the same user, or anonymous comments from the same address, sent in the last day
+async fn recent_messages(db: &Database, submission: &Submission) -> mongodb::error::Result<Vec<String>> {
+    if submission.kind != ContentKind::Comment {
+        return Ok(vec![]);
+    }
+    let mut filter = match (&submission.author_id, &submission.ip_hash) {
+        (Some(author_id), _) => doc! {"author_id": author_id},
+        (None, Some(ip_hash)) => doc! {"ip_hash": ip_hash},
+        (None, None) => return Ok(vec![]),
+    };
+    filter.insert("created_at", doc! {"$gte": (Utc::now() - Duration::days(1)).timestamp()});
+    if let Some(comment_id) = submission.comment_id {
+        filter.insert("_id", doc! {"$ne": comment_id});
+    }
+
+    let options = FindOptions::builder().projection(doc! {"content": 1}).sort(doc! {"created_at": -1}).limit(50).build();
+    let comments: Vec<Document> = db.collection::<Document>("comments").find(filter, options).await?.try_collect().await?;
+    Ok(comments
+        .iter()
+        .filter_map(|comment| comment.get_str("content").ok())
+        .map(|content| fold(content).split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect())
+}
+
+const TOTALS: &str = "__documents__";
+
+// Naive Bayes over the tokens moderators have seen in spam and in good content.
+// `spam_tokens` has a document per token with the number of spam and ham documents it was in,
+// and the number of documents of each kind under TOTALS.
+async fn spam_probability(db: &Database, tokens: &[String]) -> mongodb::error::Result<Option<f64>> {
+    let unique: HashSet<&String> = tokens.iter().take(500).collect();
+    let mut ids: Vec<&str> = unique.iter().map(|token| token.as_str()).collect();
+    ids.push(TOTALS);
+
+    let counts: Vec<Document> = db.collection::<Document>("spam_tokens")
+        .find(doc! {"_id": {"$in": ids}}, None)
+        .await?
+        .try_collect()
+        .await?;
+    let counts: HashMap<String, (f64, f64)> = counts
+        .iter()
+        .filter_map(|count| {
+            let id = count.get_str("_id").ok()?;
+            let spam = count.get_i64("spam").unwrap_or(0) as f64;
+            let ham = count.get_i64("ham").unwrap_or(0) as f64;
+            Some((id.to_string(), (spam, ham)))
+        })
+        .collect();
+
+    let (spam_documents, ham_documents) = counts.get(TOTALS).copied().unwrap_or((0.0, 0.0));
+    let min_training = env_number("SPAM_MIN_TRAINING", 20.0);
+    if spam_documents < 1.0 || ham_documents < 1.0 || spam_documents + ham_documents < min_training {
+        return Ok(None);
+    }
+
+    // Log odds with add-one smoothing, tokens nobody has seen yet say nothing
+    let mut log_odds = (spam_documents / ham_documents).ln();
+    for token in unique {
+        if let Some((spam, ham)) = counts.get(token.as_str()) {
+            let in_spam = (spam + 1.0) / (spam_documents + 2.0);
+            let in_ham = (ham + 1.0) / (ham_documents + 2.0);
+            log_odds += (in_spam / in_ham).ln();
+        }
+    }
+    Ok(Some(1.0 / (1.0 + (-log_odds).exp())))
+}
+
+// Teaches the model a moderator's decision about a text.
+// A text has up to 500 tokens to count, so it's done in the background instead of in the moderator's request.
+pub fn train_spam_model(db: &Database, text: &str, is_spam: bool) {
+    let db = db.clone();
+    let unique: HashSet<String> = tokens(&fold(text)).into_iter().take(500).collect();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = count_tokens(&db, unique, is_spam).await {
+            log::error!("failed to train spam filter: {}", e);
+        }
+    });
+}
+
+async fn count_tokens(db: &Database, unique: HashSet<String>, is_spam: bool) -> mongodb::error::Result<()> {
+    let field = if is_spam { "spam" } else { "ham" };
+    let spam_tokens = db.collection::<Document>("spam_tokens");
+    let upsert = UpdateOptions::builder().upsert(true).build();
+    for token in unique.iter().map(String::as_str).chain([TOTALS]) {
+        spam_tokens.update_one(doc! {"_id": token}, doc! {"$inc": {field: 1i64}}, upsert.clone()).await?;
+    }
+    Ok(())
+}
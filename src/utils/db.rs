@@ -0,0 +1,6 @@
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+
+// A write refused by a unique index
+pub fn is_duplicate(e: &Error) -> bool {
+    matches!(*e.kind, ErrorKind::Write(WriteFailure::WriteError(ref error)) if error.code == 11000)
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub kind: &'static str, // "equal", "added" or "removed"
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+// Line-level diff of two texts, line numbers start at 1
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Insert => "added",
+                ChangeTag::Delete => "removed",
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, env, str::FromStr, time::Duration as StdDuration};
+
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, oid::ObjectId}, options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument}};
+
+use crate::types::{AuthorProfile, Notification, NotificationView, PostSummary, User};
+use super::email_templates::{digest_email, Digest, DigestPost};
+use super::mailer::{queue_email, site_url, unsubscribe_url};
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+// Queues the weekly digest of everyone who is due, checking every DIGEST_CHECK_INTERVAL seconds (default 3600).
+// A user gets one every DIGEST_PERIOD_DAYS (default 7).
+pub fn spawn_digest_scheduler(db: Database) {
+    let seconds = env_or("DIGEST_CHECK_INTERVAL", 3600);
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(StdDuration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if let Err(e) = queue_due_digests(&db).await {
+                log::error!("digest scheduler error: {}", e);
+            }
+        }
+    });
+}
+
+async fn queue_due_digests(db: &Database) -> mongodb::error::Result<()> {
+    let users = db.collection::<User>("users");
+    let period = Duration::days(env_or("DIGEST_PERIOD_DAYS", 7));
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::Before).build();
+
+    // Claim users one at a time so that two running instances never send the same digest twice
+    loop {
+        let now = Utc::now();
+        let filter = doc! {
+            "email_digest": true,
+            "$or": [{"last_digest_at": null}, {"last_digest_at": {"$lte": (now - period).timestamp()}}],
+        };
+        let user = match users.find_one_and_update(filter, doc! {"$set": {"last_digest_at": now.timestamp()}}, options.clone()).await? {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        let since = user.last_digest_at.unwrap_or(now - period);
+
+        let digest = match build_digest(db, &user, since.timestamp()).await? {
+            Some(digest) => digest,
+            None => continue, // nothing happened, no email
+        };
+        let email = digest_email(&user.language, &digest);
+        queue_email(db, &user.email, email, Some(digest.unsubscribe_url)).await?;
+    }
+}
+
+async fn build_digest(db: &Database, user: &User, since: i64) -> mongodb::error::Result<Option<Digest>> {
+    let user_id = user.id.to_hex();
+
+    // The most read posts of followed authors and tags since the last digest
+    let mut posts = vec![];
+    if !user.following.is_empty() || !user.followed_tags.is_empty() {
+        let filter = doc! {
+            "status": "Public",
+            "published_at": {"$gte": since},
+            "author": {"$ne": &user_id},
+            "$or": [{"author": {"$in": &user.following}}, {"tags": {"$in": &user.followed_tags}}],
+        };
+        let options = FindOptions::builder()
+            .projection(PostSummary::projection())
+            .sort(doc! {"views": -1})
+            .limit(env_or("DIGEST_POST_COUNT", 5))
+            .build();
+        posts = db.collection::<PostSummary>("posts").find(filter, options).await?.try_collect().await?;
+    }
+
+    let filter = doc! {"user_id": &user_id, "read": false};
+    let unread_count = db.collection::<Notification>("notifications").count_documents(filter.clone(), None).await?;
+    let options = FindOptions::builder().sort(doc! {"updated_at": -1}).limit(5).build();
+    let notifications: Vec<Notification> = db.collection::<Notification>("notifications").find(filter, options).await?.try_collect().await?;
+
+    if posts.is_empty() && notifications.is_empty() {
+        return Ok(None);
+    }
+
+    // Post authors and the latest actor of every notification, in one query
+    let latest_actor = |notification: &Notification| notification.actors.last().and_then(|actor| ObjectId::from_str(actor).ok());
+    let ids: Vec<ObjectId> = posts
+        .iter()
+        .filter_map(|post| ObjectId::from_str(&post.author).ok())
+        .chain(notifications.iter().filter_map(latest_actor))
+        .collect();
+    let options = FindOptions::builder().projection(AuthorProfile::projection()).build();
+    let profiles: HashMap<ObjectId, AuthorProfile> = db.collection::<AuthorProfile>("users")
+        .find(doc! {"_id": {"$in": ids}}, options).await?
+        .try_collect::<Vec<AuthorProfile>>().await?
+        .into_iter()
+        .map(|profile| (profile.id, profile))
+        .collect();
+
+    let site = site_url();
+    let posts = posts
+        .into_iter()
+        .map(|post| DigestPost {
+            author: ObjectId::from_str(&post.author).ok().and_then(|id| profiles.get(&id)).map(|profile| profile.name.clone()).unwrap_or_default(),
+            url: format!("{}/post/{}", site.trim_end_matches('/'), post.id.to_hex()),
+            title: post.title,
+            excerpt: post.excerpt,
+            read_time: post.read_time,
+        })
+        .collect();
+    let notifications = notifications
+        .into_iter()
+        .map(|notification| {
+            let actors = latest_actor(&notification).and_then(|id| profiles.get(&id).cloned()).into_iter().collect();
+            NotificationView::new(notification, actors)
+        })
+        .collect();
+
+    Ok(Some(Digest {
+        name: user.name.clone(),
+        posts,
+        notifications,
+        unread_count,
+        unsubscribe_url: unsubscribe_url(&user_id),
+    }))
+}
@@ -0,0 +1,151 @@
+use ammonia::clean_text;
+
+use crate::types::{NotificationKind, NotificationView};
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+pub struct DigestPost {
+    pub title: String,
+    pub author: String,
+    pub excerpt: String,
+    pub read_time: u32,
+    pub url: String,
+}
+
+pub struct Digest {
+    pub name: String,
+    pub posts: Vec<DigestPost>,
+    pub notifications: Vec<NotificationView>, // the latest unread ones
+    pub unread_count: u64,
+    pub unsubscribe_url: String,
+}
+
+// Everything an email says, in Turkish or English. Unknown languages get Turkish like the rest of the site.
+struct Strings {
+    digest_subject: &'static str,
+    greeting: &'static str,
+    posts_heading: &'static str,
+    notifications_heading: &'static str,
+    more_notifications: &'static str, // "{count}" is replaced
+    minutes: &'static str,
+    why: &'static str,
+    unsubscribe: &'static str,
+}
+
+const TR: Strings = Strings {
+    digest_subject: "Bu hafta takip ettiklerinden",
+    greeting: "Merhaba",
+    posts_heading: "Takip ettiğin yazar ve etiketlerden öne çıkanlar",
+    notifications_heading: "Okunmamış bildirimlerin",
+    more_notifications: "ve {count} bildirim daha",
+    minutes: "dk okuma",
+    why: "Bu e-postayı haftalık özet almayı seçtiğin için alıyorsun.",
+    unsubscribe: "Haftalık özetten çık",
+};
+
+const EN: Strings = Strings {
+    digest_subject: "This week from the people you follow",
+    greeting: "Hi",
+    posts_heading: "Popular posts from authors and tags you follow",
+    notifications_heading: "Your unread notifications",
+    more_notifications: "and {count} more",
+    minutes: "min read",
+    why: "You are getting this email because you are subscribed to the weekly digest.",
+    unsubscribe: "Unsubscribe from the weekly digest",
+};
+
+fn strings(language: &str) -> &'static Strings {
+    match language {
+        "en" => &EN,
+        _ => &TR,
+    }
+}
+
+// "Ayşe and 2 others liked your comment", in the email's language
+fn notification_line(language: &str, notification: &NotificationView) -> String {
+    let first = notification.actors.first().map(|actor| actor.name.clone());
+    let others = notification.actor_count.saturating_sub(1);
+    if language != "en" {
+        let who = match (first, others) {
+            (Some(name), 0) => name,
+            (Some(name), others) => format!("{} ve {} kişi daha", name, others),
+            (None, _) => format!("{} kişi", notification.actor_count),
+        };
+        let what = match notification.kind {
+            NotificationKind::Comment => "yazına yorum yaptı",
+            NotificationKind::Reply => "yorumuna cevap verdi",
+            NotificationKind::Like => "yorumunu beğendi",
+            NotificationKind::Follow => "seni takip etmeye başladı",
+            NotificationKind::Invitation => "seni bir yazıda birlikte çalışmaya davet etti",
+            NotificationKind::PostApproved => "yazını onayladı",
+            NotificationKind::ChangesRequested => "yazında değişiklik istedi",
+            NotificationKind::PostRejected => "yazını reddetti",
+            NotificationKind::NewPost => "yeni bir yazı yayımladı",
+        };
+        return format!("{} {}", who, what);
+    }
+    notification.message.clone()
+}
+
+fn layout(strings: &Strings, body_html: &str, unsubscribe_url: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><body style=\"font-family: sans-serif; max-width: 600px; margin: auto;\">{}\
+         <hr><p style=\"color: #888; font-size: 12px;\">{}<br><a href=\"{}\">{}</a></p></body></html>",
+        body_html,
+        clean_text(strings.why),
+        clean_text(unsubscribe_url),
+        clean_text(strings.unsubscribe),
+    )
+}
+
+pub fn digest_email(language: &str, digest: &Digest) -> RenderedEmail {
+    let strings = strings(language);
+    let mut html = format!("<p>{} {},</p>", strings.greeting, clean_text(&digest.name));
+    let mut text = format!("{} {},\n\n", strings.greeting, digest.name);
+
+    if !digest.posts.is_empty() {
+        html.push_str(&format!("<h2>{}</h2>", strings.posts_heading));
+        text.push_str(&format!("{}\n\n", strings.posts_heading));
+        for post in &digest.posts {
+            html.push_str(&format!(
+                "<h3><a href=\"{}\">{}</a></h3><p style=\"color: #555;\">{} · {} {}</p><p>{}</p>",
+                clean_text(&post.url),
+                clean_text(&post.title),
+                clean_text(&post.author),
+                post.read_time,
+                strings.minutes,
+                clean_text(&post.excerpt),
+            ));
+            text.push_str(&format!("{}\n{} · {} {}\n{}\n{}\n\n", post.title, post.author, post.read_time, strings.minutes, post.excerpt, post.url));
+        }
+    }
+
+    if !digest.notifications.is_empty() {
+        html.push_str(&format!("<h2>{}</h2><ul>", strings.notifications_heading));
+        text.push_str(&format!("{}\n\n", strings.notifications_heading));
+        for notification in &digest.notifications {
+            let line = notification_line(language, notification);
+            html.push_str(&format!("<li>{}</li>", clean_text(&line)));
+            text.push_str(&format!("- {}\n", line));
+        }
+        html.push_str("</ul>");
+        let more = digest.unread_count.saturating_sub(digest.notifications.len() as u64);
+        if more > 0 {
+            let line = strings.more_notifications.replace("{count}", &more.to_string());
+            html.push_str(&format!("<p>{}</p>", line));
+            text.push_str(&format!("{}\n", line));
+        }
+        text.push('\n');
+    }
+
+    text.push_str(&format!("--\n{}\n{}: {}\n", strings.why, strings.unsubscribe, digest.unsubscribe_url));
+    RenderedEmail {
+        subject: strings.digest_subject.to_string(),
+        html: layout(strings, &html, &digest.unsubscribe_url),
+        text,
+    }
+}
@@ -0,0 +1,56 @@
+use mongodb::{Database, bson::{doc, oid::ObjectId, Document}, options::{FindOneOptions, UpdateOptions}};
+use chrono::Utc;
+
+use crate::types::ReadingHistoryEntry;
+use super::post_stats::{record_post_event, PostEvent};
+
+// A post counts as read once this much of it was scrolled through
+pub const FINISHED_PROGRESS: u8 = 90;
+
+// Records that the user opened the post, or with `progress` how far they got.
+// Returns false when the user paused their history and nothing was recorded.
+pub async fn record_reading(db: &Database, user_id: ObjectId, post_id: ObjectId, progress: Option<u8>) -> mongodb::error::Result<bool> {
+    let options = FindOneOptions::builder().projection(doc! {"history_paused": 1}).build();
+    match db.collection::<Document>("users").find_one(doc! {"_id": user_id}, options).await? {
+        Some(user) if !user.get_bool("history_paused").unwrap_or(false) => {}
+        _ => return Ok(false),
+    }
+
+    let now = Utc::now().timestamp();
+    let mut set = doc! {"last_read_at": now};
+    let mut set_on_insert = doc! {"first_read_at": now};
+    let mut update = doc! {};
+    match progress {
+        Some(progress) => {
+            let progress = progress.min(100);
+            set.insert("progress", progress as i32);
+            set_on_insert.insert("open_count", 1);
+            if progress >= FINISHED_PROGRESS {
+                set.insert("finished", true);
+            } else {
+                set_on_insert.insert("finished", false);
+            }
+        }
+        None => {
+            set_on_insert.insert("progress", 0);
+            set_on_insert.insert("finished", false);
+            update.insert("$inc", doc! {"open_count": 1});
+        }
+    }
+    update.insert("$set", set);
+    update.insert("$setOnInsert", set_on_insert);
+
+    let options = UpdateOptions::builder().upsert(true).build();
+    let result = db.collection::<ReadingHistoryEntry>("reading_history")
+        .update_one(doc! {"user_id": user_id.to_hex(), "post_id": post_id}, update, options)
+        .await?;
+    if result.upserted_id.is_some() {
+        record_post_event(db, post_id, PostEvent::NewReader, 1).await?;
+    }
+    // The history only keeps the last read of a post, the days someone read anything are kept for the active user counts
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    db.collection::<Document>("reading_days")
+        .update_one(doc! {"user_id": user_id.to_hex(), "date": today}, doc! {"$setOnInsert": {"created_at": now}}, UpdateOptions::builder().upsert(true).build())
+        .await?;
+    Ok(true)
+}
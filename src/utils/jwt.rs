@@ -1,11 +1,11 @@
 use actix_web::{HttpRequest, http::Error, Responder, HttpResponse};
-use jsonwebtoken::{EncodingKey, Header, decode, Validation};
+use jsonwebtoken::{EncodingKey, DecodingKey, Header, decode, Validation};
 use chrono::{Duration, Utc};
 use serde::{Serialize, Deserialize};
 use std::env;
 use dotenv::dotenv;
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct JWTClaims {
     sub: String,
     iat: usize,
@@ -32,3 +32,16 @@ pub fn sign_jwt(user_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
 
     Ok(token)
 }
+
+// Returns the user id (`sub`) of a token signed by `sign_jwt`
+pub fn verify_jwt(token: &str) -> Result<String, jsonwebtoken::errors::Error> {
+    dotenv().ok();
+
+    let secret_key = env::var("JSON_SECRET")
+        .expect("JSON_SECRET environment variable not set");
+
+    let decoding_key = DecodingKey::from_secret(secret_key.as_bytes());
+    let data = decode::<JWTClaims>(token, &decoding_key, &Validation::default())?;
+
+    Ok(data.claims.sub)
+}
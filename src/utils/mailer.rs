@@ -0,0 +1,182 @@
+use std::{env, time::Duration as StdDuration};
+
+use chrono::{Duration, Utc};
+use dotenv::dotenv;
+use lettre::{
+    message::{header::{Header, HeaderName, HeaderValue}, Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use mongodb::{Database, bson::doc, options::{FindOneAndUpdateOptions, ReturnDocument}};
+use ring::hmac;
+
+use crate::types::OutgoingEmail;
+use super::email_templates::RenderedEmail;
+
+// Lets mail clients show their own unsubscribe button
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ListUnsubscribe(s.trim_matches(|c| c == '<' || c == '>').to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
+}
+
+enum Transport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>), // one .eml file per email, for local testing
+}
+
+// Sends emails through MAIL_TRANSPORT: "smtp" (SMTP_HOST, SMTP_PORT, SMTP_USERNAME, SMTP_PASSWORD)
+// or "file" (the default, into MAIL_DIR, default "./mail")
+pub struct Mailer {
+    transport: Transport,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn from_env() -> Mailer {
+        dotenv().ok();
+        let from = env::var("MAIL_FROM").unwrap_or_else(|_| "Edebiyati <no-reply@localhost>".to_string());
+        let from = from.parse().expect("MAIL_FROM is not a valid address");
+
+        let transport = match env::var("MAIL_TRANSPORT").as_deref() {
+            Ok("smtp") => {
+                let host = env::var("SMTP_HOST").expect("SMTP_HOST environment variable not set");
+                let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host).expect("invalid SMTP_HOST");
+                if let Some(port) = env::var("SMTP_PORT").ok().and_then(|value| value.parse().ok()) {
+                    builder = builder.port(port);
+                }
+                if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+                    builder = builder.credentials(Credentials::new(username, password));
+                }
+                Transport::Smtp(builder.build())
+            }
+            _ => {
+                let dir = env::var("MAIL_DIR").unwrap_or_else(|_| "./mail".to_string());
+                std::fs::create_dir_all(&dir).expect("failed to create MAIL_DIR");
+                Transport::File(AsyncFileTransport::new(dir))
+            }
+        };
+        Mailer { transport, from }
+    }
+
+    pub async fn send(&self, email: &OutgoingEmail) -> Result<(), String> {
+        let to: Mailbox = email.to.parse().map_err(|e| format!("invalid recipient: {}", e))?;
+        let mut builder = Message::builder().from(self.from.clone()).to(to).subject(&email.subject);
+        if let Some(url) = &email.unsubscribe_url {
+            builder = builder.header(ListUnsubscribe(url.clone()));
+        }
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(email.text.clone(), email.html.clone()))
+            .map_err(|e| e.to_string())?;
+
+        match &self.transport {
+            Transport::Smtp(transport) => transport.send(message).await.map(|_| ()).map_err(|e| e.to_string()),
+            Transport::File(transport) => transport.send(message).await.map(|_| ()).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+// Where links in emails point to, SITE_URL or https://SITE_HOST
+pub fn site_url() -> String {
+    env::var("SITE_URL")
+        .or_else(|_| env::var("SITE_HOST").map(|host| format!("https://{}", host)))
+        .unwrap_or_else(|_| "http://localhost:8080".to_string())
+}
+
+fn key() -> hmac::Key {
+    dotenv().ok();
+    let secret = env::var("JSON_SECRET").expect("JSON_SECRET environment variable not set");
+    hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())
+}
+
+// Unsubscribe links work without signing in, the token proves the link came from us
+pub fn unsubscribe_token(user_id: &str) -> String {
+    let signature = hmac::sign(&key(), format!("unsubscribe:{}", user_id).as_bytes());
+    signature.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn verify_unsubscribe_token(user_id: &str, token: &str) -> bool {
+    let signature: Option<Vec<u8>> = (0..token.len())
+        .step_by(2)
+        .map(|i| token.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect();
+    match signature {
+        Some(signature) => hmac::verify(&key(), format!("unsubscribe:{}", user_id).as_bytes(), &signature).is_ok(),
+        None => false,
+    }
+}
+
+pub fn unsubscribe_url(user_id: &str) -> String {
+    let api_url = env::var("API_URL").unwrap_or_else(|_| site_url());
+    format!("{}/email/unsubscribe?user={}&token={}", api_url.trim_end_matches('/'), user_id, unsubscribe_token(user_id))
+}
+
+// Puts an email in the outbox, the mail worker sends it
+pub async fn queue_email(db: &Database, to: &str, email: RenderedEmail, unsubscribe_url: Option<String>) -> mongodb::error::Result<()> {
+    let email = OutgoingEmail::new(to.to_string(), email.subject, email.html, email.text, unsubscribe_url);
+    db.collection::<OutgoingEmail>("outbox").insert_one(email, None).await?;
+    Ok(())
+}
+
+// Sends queued emails every MAIL_OUTBOX_INTERVAL seconds (default 30).
+// A failed email is tried again after 1, 2, 4... minutes, MAIL_MAX_ATTEMPTS times (default 5).
+pub fn spawn_mail_worker(db: Database, mailer: Mailer) {
+    let seconds = env::var("MAIL_OUTBOX_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(StdDuration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if let Err(e) = send_due_emails(&db, &mailer).await {
+                log::error!("mail worker error: {}", e);
+            }
+        }
+    });
+}
+
+async fn send_due_emails(db: &Database, mailer: &Mailer) -> mongodb::error::Result<()> {
+    let collection = db.collection::<OutgoingEmail>("outbox");
+    let max_attempts = env::var("MAIL_MAX_ATTEMPTS").ok().and_then(|value| value.parse().ok()).unwrap_or(5u32);
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+    loop {
+        // Claiming pushes the next attempt ahead, an instance that dies while sending leaves it for a later retry
+        let now = Utc::now();
+        let filter = doc! {"status": "Pending", "next_attempt_at": {"$lte": now.timestamp()}};
+        let claim = doc! {
+            "$set": {"next_attempt_at": (now + Duration::minutes(10)).timestamp()},
+            "$inc": {"attempts": 1},
+        };
+        let email = match collection.find_one_and_update(filter, claim, options.clone()).await? {
+            Some(email) => email,
+            None => return Ok(()),
+        };
+
+        let update = match mailer.send(&email).await {
+            Ok(()) => doc! {"$set": {"status": "Sent", "sent_at": Utc::now().timestamp(), "last_error": null}},
+            Err(e) if email.attempts >= max_attempts => {
+                log::warn!("giving up on email {} to {}: {}", email.id, email.to, e);
+                doc! {"$set": {"status": "Failed", "last_error": e}}
+            }
+            Err(e) => {
+                let retry_at = Utc::now() + Duration::minutes(1 << (email.attempts - 1).min(10));
+                doc! {"$set": {"next_attempt_at": retry_at.timestamp(), "last_error": e}}
+            }
+        };
+        collection.update_one(doc! {"_id": email.id, "status": "Pending"}, update, None).await?;
+    }
+}
@@ -0,0 +1,223 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{TimeZone, Utc};
+use futures::TryStreamExt;
+use mongodb::{Database, IndexModel, bson::{self, doc, oid::ObjectId, Document}, options::{FindOptions, IndexOptions, UpdateOptions}};
+
+use crate::types::{Bookmark, Comment, LegacyComment, FAVORITES};
+
+// Brings the database up to date with the current types. Every step is safe to run again.
+pub async fn run_migrations(db: &Database) -> mongodb::error::Result<()> {
+    ensure_indexes(db).await?;
+    migrate_embedded_comments(db).await?;
+    backfill_comment_paths(db).await?;
+    migrate_favorites(db).await?;
+    // `User.view_list` was never written, reading history replaces it
+    db.collection::<Document>("users").update_many(doc! {"view_list": {"$exists": true}}, doc! {"$unset": {"view_list": ""}}, None).await?;
+    Ok(())
+}
+
+async fn ensure_indexes(db: &Database) -> mongodb::error::Result<()> {
+    let comments = db.collection::<Document>("comments");
+    comments.create_index(IndexModel::builder().keys(doc! {"post_id": 1, "parent_id": 1, "created_at": 1}).build(), None).await?;
+
+    // Follower counts and author listings on profiles
+    let users = db.collection::<Document>("users");
+    users.create_index(IndexModel::builder().keys(doc! {"following": 1}).build(), None).await?;
+    let posts = db.collection::<Document>("posts");
+    posts.create_index(IndexModel::builder().keys(doc! {"author": 1, "status": 1, "published_at": -1}).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc! {"contributors.user_id": 1, "status": 1}).build(), None).await?;
+    // The review queue
+    posts.create_index(IndexModel::builder().keys(doc! {"status": 1, "updated_at": 1}).build(), None).await?;
+
+    let revisions = db.collection::<Document>("revisions");
+    let unique = IndexOptions::builder().unique(true).build();
+    revisions.create_index(IndexModel::builder().keys(doc! {"post_id": 1, "number": 1}).options(unique).build(), None).await?;
+
+    let invitations = db.collection::<Document>("post_invitations");
+    invitations.create_index(IndexModel::builder().keys(doc! {"invitee": 1, "status": 1, "created_at": -1}).build(), None).await?;
+    invitations.create_index(IndexModel::builder().keys(doc! {"post_id": 1, "invitee": 1, "status": 1}).build(), None).await?;
+
+    let series = db.collection::<Document>("series");
+    series.create_index(IndexModel::builder().keys(doc! {"author": 1, "updated_at": -1}).build(), None).await?;
+
+    let notifications = db.collection::<Document>("notifications");
+    notifications.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "read": 1, "updated_at": -1}).build(), None).await?;
+
+    let reports = db.collection::<Document>("reports");
+    reports.create_index(IndexModel::builder().keys(doc! {"status": 1, "created_at": 1}).build(), None).await?;
+    reports.create_index(IndexModel::builder().keys(doc! {"target_id": 1, "status": 1}).build(), None).await?;
+
+    let bookmarks = db.collection::<Document>("bookmarks");
+    let unique = IndexOptions::builder().unique(true).build();
+    bookmarks.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "name": 1}).options(unique).build(), None).await?;
+    bookmarks.create_index(IndexModel::builder().keys(doc! {"share_token": 1}).build(), None).await?;
+
+    let reading_history = db.collection::<Document>("reading_history");
+    let unique = IndexOptions::builder().unique(true).build();
+    reading_history.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "post_id": 1}).options(unique).build(), None).await?;
+    reading_history.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "last_read_at": -1}).build(), None).await?;
+
+    // Per day metrics on the admin dashboard
+    users.create_index(IndexModel::builder().keys(doc! {"created_at": 1}).build(), None).await?;
+    comments.create_index(IndexModel::builder().keys(doc! {"created_at": 1}).build(), None).await?;
+
+    let reading_days = db.collection::<Document>("reading_days");
+    let unique = IndexOptions::builder().unique(true).build();
+    reading_days.create_index(IndexModel::builder().keys(doc! {"date": 1, "user_id": 1}).options(unique).build(), None).await?;
+
+    // Per post and day counters for authors
+    let unique = IndexOptions::builder().unique(true).build();
+    let post_stats = db.collection::<Document>("post_stats");
+    post_stats.create_index(IndexModel::builder().keys(doc! {"post_id": 1, "date": 1}).options(unique.clone()).build(), None).await?;
+    let post_referrers = db.collection::<Document>("post_referrers");
+    post_referrers.create_index(IndexModel::builder().keys(doc! {"post_id": 1, "date": 1, "domain": 1}).options(unique).build(), None).await?;
+    reading_history.create_index(IndexModel::builder().keys(doc! {"post_id": 1}).build(), None).await?;
+
+    let role_changes = db.collection::<Document>("role_changes");
+    role_changes.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "created_at": -1}).build(), None).await?;
+
+    let outbox = db.collection::<Document>("outbox");
+    outbox.create_index(IndexModel::builder().keys(doc! {"status": 1, "next_attempt_at": 1}).build(), None).await?;
+
+    // Solved proof-of-work challenges are dropped when they expire
+    let expire = IndexOptions::builder().expire_after(Duration::from_secs(0)).build();
+    let used_challenges = db.collection::<Document>("used_challenges");
+    used_challenges.create_index(IndexModel::builder().keys(doc! {"expires_at": 1}).options(expire.clone()).build(), None).await?;
+
+    // So are rate limit buckets once they are full again
+    let rate_limits = db.collection::<Document>("rate_limits");
+    rate_limits.create_index(IndexModel::builder().keys(doc! {"expires_at": 1}).options(expire).build(), None).await?;
+    Ok(())
+}
+
+fn flatten_comments(post_id: ObjectId, parent: Option<&Comment>, created_at: i64, legacy: Vec<LegacyComment>, out: &mut Vec<Comment>) {
+    for legacy_comment in legacy {
+        let mut comment = Comment::new(post_id, parent, Some(legacy_comment.author_id), legacy_comment.content);
+        // Embedded comments had no date, the post's date and the insertion order of the ids keep them sorted
+        comment.created_at = Utc.timestamp_opt(created_at, 0).single().unwrap_or_else(Utc::now);
+        comment.like_count = legacy_comment.likes.len() as u32;
+        comment.likes = legacy_comment.likes;
+        comment.reply_count = legacy_comment.replies.len() as u32;
+        comment.legacy_id = Some(legacy_comment.id);
+
+        let replies = legacy_comment.replies;
+        flatten_comments(post_id, Some(&comment), created_at, replies, out);
+        out.push(comment);
+    }
+}
+
+// A document without an id can't be migrated, and nothing else should run on a database like that
+fn id_of(document: &Document) -> mongodb::error::Result<ObjectId> {
+    document.get_object_id("_id").map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into())
+}
+
+// Moves `Post.comments` into the comments collection
+async fn migrate_embedded_comments(db: &Database) -> mongodb::error::Result<()> {
+    let posts = db.collection::<Document>("posts");
+    let comments = db.collection::<Comment>("comments");
+
+    let options = FindOptions::builder().projection(doc! {"comments": 1, "created_at": 1}).build();
+    let mut cursor = posts.find(doc! {"comments": {"$exists": true}}, options).await?;
+
+    while let Some(post) = cursor.try_next().await? {
+        let post_id = id_of(&post)?;
+        let created_at = post.get_i64("created_at").unwrap_or_else(|_| Utc::now().timestamp());
+        // Comments that can't be read stay on the post to be fixed by hand
+        let legacy: Vec<LegacyComment> = match post.get("comments").map(|value| bson::from_bson(value.clone())) {
+            Some(Ok(legacy)) => legacy,
+            Some(Err(e)) => {
+                log::error!("skipped the comments of post {}, they can't be read: {}", post_id, e);
+                continue;
+            }
+            None => vec![],
+        };
+
+        let mut flat = vec![];
+        flatten_comments(post_id, None, created_at, legacy, &mut flat);
+
+        // A previous run may have stopped after inserting, start this post over
+        comments.delete_many(doc! {"post_id": post_id, "legacy_id": {"$exists": true}}, None).await?;
+        if !flat.is_empty() {
+            comments.insert_many(&flat, None).await?;
+        }
+        posts.update_one(
+            doc! {"_id": post_id},
+            doc! {"$unset": {"comments": ""}, "$set": {"comment_count": flat.len() as u32}},
+            None,
+        ).await?;
+        log::info!("migrated {} comments of post {}", flat.len(), post_id);
+    }
+
+    Ok(())
+}
+
+// Comments stored before threads kept their path only know their parent
+async fn backfill_comment_paths(db: &Database) -> mongodb::error::Result<()> {
+    let comments = db.collection::<Comment>("comments");
+
+    // A parent's id is always older than its replies', so parents are done first
+    let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+    let mut cursor = comments.find(doc! {"depth": {"$exists": false}}, options).await?;
+    let mut paths: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+
+    while let Some(comment) = cursor.try_next().await? {
+        let ancestors = match comment.parent_id {
+            None => vec![],
+            Some(parent_id) => match paths.get(&parent_id) {
+                Some(path) => path.clone(),
+                None => match comments.find_one(doc! {"_id": parent_id}, None).await? {
+                    Some(parent) => parent.path(),
+                    None => vec![parent_id],
+                },
+            },
+        };
+        comments.update_one(
+            doc! {"_id": comment.id},
+            doc! {"$set": {"ancestors": &ancestors, "depth": ancestors.len() as u32}},
+            None,
+        ).await?;
+
+        let mut path = ancestors;
+        path.push(comment.id);
+        paths.insert(comment.id, path);
+    }
+
+    Ok(())
+}
+
+// Moves `User.favorites` into each user's Favorites bookmark collection
+async fn migrate_favorites(db: &Database) -> mongodb::error::Result<()> {
+    let users = db.collection::<Document>("users");
+    let bookmarks = db.collection::<Bookmark>("bookmarks");
+
+    let options = FindOptions::builder().projection(doc! {"favorites": 1}).build();
+    let mut cursor = users.find(doc! {"favorites": {"$exists": true}}, options).await?;
+
+    while let Some(user) = cursor.try_next().await? {
+        let user_id = id_of(&user)?;
+        let favorites: Vec<String> = match user.get("favorites").map(|value| bson::from_bson(value.clone())) {
+            Some(Ok(favorites)) => favorites,
+            Some(Err(e)) => {
+                log::error!("skipped the favorites of user {}, they can't be read: {}", user_id, e);
+                continue;
+            }
+            None => vec![],
+        };
+
+        if !favorites.is_empty() {
+            // Joins a Favorites collection left by a previous run instead of making a second one
+            let new = Bookmark::new(user_id.to_hex(), FAVORITES.to_string(), 0);
+            let update = doc! {
+                "$addToSet": {"posts": {"$each": &favorites}},
+                "$setOnInsert": {"position": 0, "share_token": null, "created_at": new.created_at.timestamp(), "updated_at": new.updated_at.timestamp()},
+            };
+            let options = UpdateOptions::builder().upsert(true).build();
+            bookmarks.update_one(doc! {"user_id": &new.user_id, "name": FAVORITES}, update, options).await?;
+        }
+        users.update_one(doc! {"_id": user_id}, doc! {"$unset": {"favorites": ""}}, None).await?;
+        log::info!("migrated {} favorites of user {}", favorites.len(), user_id);
+    }
+
+    Ok(())
+}
@@ -1,7 +1,47 @@
 mod jwt;
 mod s3;
-mod calculate_reading_time;
+mod reading_metrics;
+mod auth;
+mod diff;
+mod publish;
+mod scheduler;
+mod render;
+mod migrations;
+mod proof_of_work;
+mod content_filter;
+mod rate_limit;
+mod notify;
+mod realtime;
+mod email_templates;
+mod mailer;
+mod digest;
+mod history;
+mod review;
+mod roles;
+mod capabilities;
+mod post_stats;
+mod db;
 
 pub use jwt::sign_jwt;
 pub use s3::upload_image_to_s3;
-pub use calculate_reading_time::calculate_reading_time;
\ No newline at end of file
+pub use reading_metrics::{excerpt, reading_metrics};
+pub use auth::{AuthUser, OptionalAuthUser};
+pub use diff::line_diff;
+pub use publish::on_post_published;
+pub use scheduler::{spawn_publish_scheduler, schedule_or_publish};
+
+pub use render::prepare_content;
+pub use migrations::run_migrations;
+pub use proof_of_work::{issue_challenge, verify_challenge};
+pub use content_filter::{client_ip_hash, train_spam_model, ContentFilterPipeline, Submission, Verdict};
+pub use rate_limit::{RateLimit, RateLimiter};
+pub use notify::{notify, notify_new_comment};
+pub use realtime::Broker;
+pub use mailer::{spawn_mail_worker, verify_unsubscribe_token, Mailer};
+pub use digest::spawn_digest_scheduler;
+pub use history::{record_reading, FINISHED_PROGRESS};
+pub use review::{needs_review, submit_for_review};
+pub use post_stats::{record_post_event, record_view, PostEvent};
+pub use capabilities::{effective_capabilities, has_capability, load_capabilities};
+pub use db::is_duplicate;
+pub use roles::{ban_user, bootstrap_admin, change_permission, promote_admin, unban_user};
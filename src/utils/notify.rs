@@ -0,0 +1,90 @@
+use std::{env, str::FromStr};
+
+use chrono::{Duration, Utc};
+use mongodb::{Database, bson::{self, doc, oid::ObjectId, Document}, options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument}};
+
+use crate::types::{Comment, CommentStatus, Notification, NotificationKind, NotificationPreferences, Post};
+use super::realtime::Broker;
+
+// Events within NOTIFICATION_COALESCE_MINUTES (default 60) of the last one join its notification while it is unread
+fn coalesce_window() -> Duration {
+    let minutes = env::var("NOTIFICATION_COALESCE_MINUTES").ok().and_then(|value| value.parse().ok()).unwrap_or(60);
+    Duration::minutes(minutes)
+}
+
+async fn preferences(db: &Database, user_id: ObjectId) -> mongodb::error::Result<Option<NotificationPreferences>> {
+    let options = FindOneOptions::builder().projection(doc! {"notification_preferences": 1}).build();
+    let user = db.collection::<Document>("users").find_one(doc! {"_id": user_id}, options).await?;
+    Ok(user.map(|user| match user.get("notification_preferences") {
+        Some(preferences) => bson::from_bson(preferences.clone()).unwrap_or_default(),
+        None => NotificationPreferences::default(),
+    }))
+}
+
+// Tells `recipient` that `actor` did something, unless it's their own doing or they don't want to know.
+// Open notification streams of the recipient get the notification right away.
+pub async fn notify(
+    db: &Database,
+    broker: &Broker,
+    recipient: &str,
+    kind: NotificationKind,
+    actor: &str,
+    post_id: Option<ObjectId>,
+    comment_id: Option<ObjectId>,
+) -> mongodb::error::Result<()> {
+    if recipient == actor {
+        return Ok(());
+    }
+    // Anonymous and deleted authors have nobody to notify
+    let recipient_id = match ObjectId::from_str(recipient) {
+        Ok(id) => id,
+        Err(_) => return Ok(()),
+    };
+    match preferences(db, recipient_id).await? {
+        Some(preferences) if preferences.wants(kind) => {}
+        _ => return Ok(()),
+    }
+
+    let now = Utc::now().timestamp();
+    let filter = doc! {
+        "user_id": recipient,
+        "kind": bson::to_bson(&kind).unwrap(),
+        "post_id": post_id,
+        "comment_id": comment_id,
+        "read": false,
+        "updated_at": {"$gte": now - coalesce_window().num_seconds()},
+    };
+    // Joins the recent unread notification or starts a new one, in one step
+    let actors = doc! {"$setUnion": [{"$ifNull": ["$actors", []]}, [actor]]};
+    let update = vec![doc! {"$set": {
+        "actors": {"$concatArrays": [{"$setDifference": [{"$ifNull": ["$actors", []]}, [actor]]}, [actor]]},
+        "actor_count": {"$size": actors},
+        "created_at": {"$ifNull": ["$created_at", now]},
+        "updated_at": now,
+    }}];
+    let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+    let notification = db.collection::<Notification>("notifications").find_one_and_update(filter, update, options).await?;
+    if let Some(notification) = notification {
+        broker.publish(&format!("user:{}", recipient), "notification", &notification);
+    }
+    Ok(())
+}
+
+// A new visible comment tells the post's author, a reply tells the author of the comment replied to.
+// Comments waiting for approval only tell the post's author, who has to approve them.
+pub async fn notify_new_comment(db: &Database, broker: &Broker, post: &Post, comment: &Comment) -> mongodb::error::Result<()> {
+    match (&comment.status, comment.parent_id) {
+        (CommentStatus::Pending, _) | (CommentStatus::Approved, None) => {
+            notify(db, broker, &post.author, NotificationKind::Comment, &comment.author_id, Some(post.id), None).await
+        }
+        (CommentStatus::Approved, Some(parent_id)) => {
+            let options = FindOneOptions::builder().projection(doc! {"author_id": 1}).build();
+            let parent = db.collection::<Document>("comments").find_one(doc! {"_id": parent_id}, options).await?;
+            match parent.as_ref().and_then(|parent| parent.get_str("author_id").ok()) {
+                Some(parent_author) => notify(db, broker, parent_author, NotificationKind::Reply, &comment.author_id, Some(post.id), Some(parent_id)).await,
+                None => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,65 @@
+use chrono::Utc;
+use mongodb::{Database, bson::{doc, oid::ObjectId, Document}, options::UpdateOptions};
+use reqwest::Url;
+
+#[derive(Debug, Clone, Copy)]
+pub enum PostEvent {
+    View,
+    Like,
+    Dislike,
+    Comment,
+    NewReader,
+}
+
+impl PostEvent {
+    fn field(&self) -> &'static str {
+        match self {
+            PostEvent::View => "views",
+            PostEvent::Like => "likes",
+            PostEvent::Dislike => "dislikes",
+            PostEvent::Comment => "comments",
+            PostEvent::NewReader => "new_readers",
+        }
+    }
+}
+
+fn today() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+// Adds `change` to today's counter of `event` in the post's daily bucket
+pub async fn record_post_event(db: &Database, post_id: ObjectId, event: PostEvent, change: i64) -> mongodb::error::Result<()> {
+    let options = UpdateOptions::builder().upsert(true).build();
+    db.collection::<Document>("post_stats")
+        .update_one(doc! {"post_id": post_id, "date": today()}, doc! {"$inc": {event.field(): change}}, options)
+        .await
+        .map(|_| ())
+}
+
+// "www.google.com" for "https://www.google.com/search?q=..."
+fn referrer_domain(referrer: Option<&str>) -> String {
+    referrer
+        .and_then(|referrer| Url::parse(referrer).ok())
+        .and_then(|url| url.host_str().map(|host| host.trim_start_matches("www.").to_lowercase()))
+        .unwrap_or_else(|| "direct".to_string())
+}
+
+// Counts a view of a public post, views of its own authors and contributors are not counted.
+// Returns false when nothing was counted.
+pub async fn record_view(db: &Database, post_id: ObjectId, viewer: Option<String>, referrer: Option<&str>) -> mongodb::error::Result<bool> {
+    let mut filter = doc! {"_id": post_id, "status": "Public"};
+    if let Some(viewer) = &viewer {
+        filter.insert("author", doc! {"$ne": viewer});
+        filter.insert("contributors.user_id", doc! {"$ne": viewer});
+    }
+    let counted = db.collection::<Document>("posts").update_one(filter, doc! {"$inc": {"views": 1}}, None).await?;
+    if counted.matched_count == 0 {
+        return Ok(false);
+    }
+    record_post_event(db, post_id, PostEvent::View, 1).await?;
+
+    let options = UpdateOptions::builder().upsert(true).build();
+    let filter = doc! {"post_id": post_id, "date": today(), "domain": referrer_domain(referrer)};
+    db.collection::<Document>("post_referrers").update_one(filter, doc! {"$inc": {"views": 1}}, options).await?;
+    Ok(true)
+}
@@ -0,0 +1,96 @@
+use std::env;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use dotenv::dotenv;
+use mongodb::{Database, bson::{self, doc}, error::{ErrorKind, WriteFailure}};
+use ring::{digest, hmac, rand::{SecureRandom, SystemRandom}};
+use serde::Serialize;
+
+// Anonymous comments have to come with the nonce of a solved challenge:
+// sha256(challenge + nonce) must start with `difficulty` zero bits.
+#[derive(Debug, Serialize)]
+pub struct Challenge {
+    pub challenge: String, // "<expires_at>.<difficulty>.<random>.<signature>"
+    pub difficulty: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+}
+
+// Zero bits required, COMMENT_POW_DIFFICULTY (default 18, about a second in a browser)
+fn difficulty() -> u32 {
+    env::var("COMMENT_POW_DIFFICULTY")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(18u32)
+        .min(64)
+}
+
+fn key() -> hmac::Key {
+    dotenv().ok();
+    let secret = env::var("JSON_SECRET").expect("JSON_SECRET environment variable not set");
+    hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut count = 0;
+    for byte in bytes {
+        count += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    count
+}
+
+// The challenge is signed, so nothing has to be stored until it is used
+pub fn issue_challenge() -> Challenge {
+    let mut random = [0u8; 16];
+    SystemRandom::new().fill(&mut random).expect("failed to generate random bytes");
+
+    let difficulty = difficulty();
+    let expires_at = Utc::now() + Duration::minutes(10);
+    let payload = format!("{}.{}.{}", expires_at.timestamp(), difficulty, hex(&random));
+    let signature = hmac::sign(&key(), payload.as_bytes());
+
+    Challenge {
+        challenge: format!("{}.{}", payload, hex(signature.as_ref())),
+        difficulty,
+        expires_at,
+    }
+}
+
+// Checks the signature, expiry and work of a solved challenge, then marks it used
+pub async fn verify_challenge(db: &Database, challenge: &str, nonce: &str) -> Result<(), &'static str> {
+    let (payload, signature) = challenge.rsplit_once('.').ok_or("Invalid challenge")?;
+    let expected = hex(hmac::sign(&key(), payload.as_bytes()).as_ref());
+    if ring::constant_time::verify_slices_are_equal(expected.as_bytes(), signature.as_bytes()).is_err() {
+        return Err("Invalid challenge");
+    }
+
+    let mut parts = payload.split('.');
+    let expires_at = parts.next().and_then(|value| value.parse::<i64>().ok()).ok_or("Invalid challenge")?;
+    let difficulty = parts.next().and_then(|value| value.parse::<u32>().ok()).ok_or("Invalid challenge")?;
+    let expires_at = Utc.timestamp_opt(expires_at, 0).single().ok_or("Invalid challenge")?;
+    if expires_at < Utc::now() {
+        return Err("Challenge expired");
+    }
+
+    let hash = digest::digest(&digest::SHA256, format!("{}{}", challenge, nonce).as_bytes());
+    if leading_zero_bits(hash.as_ref()) < difficulty {
+        return Err("Challenge not solved");
+    }
+
+    // A challenge is good for one comment, the TTL index removes it once it has expired anyway
+    let used = doc! {"_id": challenge, "expires_at": bson::DateTime::from_millis(expires_at.timestamp_millis())};
+    match db.collection("used_challenges").insert_one(used, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => match *e.kind {
+            ErrorKind::Write(WriteFailure::WriteError(ref error)) if error.code == 11000 => Err("Challenge already used"),
+            _ => Err("Failed to verify challenge"),
+        },
+    }
+}
@@ -0,0 +1,45 @@
+use actix_web::web::Data;
+use futures::TryStreamExt;
+use mongodb::{Database, bson::{doc, oid::ObjectId, Document}, options::FindOptions};
+
+use crate::types::{NotificationKind, Post};
+use super::notify::notify;
+use super::realtime::Broker;
+
+// Runs everything that has to happen once when a post becomes public,
+// whether it was published directly or by the scheduler
+pub async fn on_post_published(db: &Database, broker: &Data<Broker>, post: &Post) -> mongodb::error::Result<()> {
+    db.collection::<Document>("common")
+        .update_one(doc! {}, doc! {"$inc": {"post_count": 1}}, None)
+        .await?;
+
+    // Followers of the author and of the co-authors hear about the post once.
+    // An author can have many followers, they are told in the background.
+    let mut authors = vec![post.author.clone()];
+    authors.extend(post.contributors.iter().filter(|contributor| contributor.role.is_author()).map(|contributor| contributor.user_id.clone()));
+    let (db, broker, post_id) = (db.clone(), broker.clone(), post.id);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = notify_followers(&db, &broker, post_id, &authors).await {
+            log::error!("failed to notify followers of post {}: {}", post_id, e);
+        }
+    });
+    Ok(())
+}
+
+async fn notify_followers(db: &Database, broker: &Broker, post_id: ObjectId, authors: &[String]) -> mongodb::error::Result<()> {
+
+    let options = FindOptions::builder().projection(doc! {"following": 1}).build();
+    let mut followers = db.collection::<Document>("users").find(doc! {"following": {"$in": authors}}, options).await?;
+    while let Some(follower) = followers.try_next().await? {
+        let follower_id = match follower.get_object_id("_id") {
+            Ok(id) => id.to_hex(),
+            Err(_) => continue,
+        };
+        let following = follower.get_array("following").map(|following| following.iter().filter_map(|id| id.as_str()).collect::<Vec<_>>()).unwrap_or_default();
+        // Credited to the first author they follow
+        if let Some(actor) = authors.iter().find(|author| following.contains(&author.as_str())) {
+            notify(db, broker, &follower_id, NotificationKind::NewPost, actor, Some(post_id), None).await?;
+        }
+    }
+    Ok(())
+}
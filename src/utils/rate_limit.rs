@@ -0,0 +1,228 @@
+use std::{collections::HashMap, env, future::{ready, Ready}, rc::Rc, sync::Mutex};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web::Data,
+    Error, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use dotenv::dotenv;
+use futures::future::LocalBoxFuture;
+use mongodb::{Database, bson::{self, doc, Document}, options::{FindOneAndUpdateOptions, ReturnDocument}};
+use serde_json::json;
+
+use super::auth::AuthUser;
+
+// The client's address. Proxy headers are only believed with TRUST_PROXY_HEADERS=true,
+// otherwise anyone could pick a new address for every request.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trust_proxy = env::var("TRUST_PROXY_HEADERS").map(|value| value == "true").unwrap_or(false);
+    if trust_proxy {
+        req.connection_info().realip_remote_addr().map(str::to_string)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+}
+
+// `capacity` requests at once, refilled at `per_second`
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub capacity: f64,
+    pub per_second: f64,
+}
+
+impl Quota {
+    // RATE_LIMIT_<RULE>="<requests>/<seconds>", e.g. RATE_LIMIT_LOGIN="5/60"
+    fn for_rule(rule: &str) -> Quota {
+        let (requests, seconds) = match rule {
+            "login" => (5.0, 60.0),
+            "signup" => (3.0, 3600.0),
+            "comment" => (10.0, 60.0),
+            "upload" => (20.0, 3600.0),
+            _ => (60.0, 60.0),
+        };
+        let configured = env::var(format!("RATE_LIMIT_{}", rule.to_uppercase())).ok().and_then(|value| {
+            let (requests, seconds) = value.split_once('/')?;
+            Some((requests.trim().parse::<f64>().ok()?, seconds.trim().parse::<f64>().ok()?))
+        });
+        let (requests, seconds) = configured.filter(|(requests, seconds)| *requests >= 1.0 && *seconds > 0.0).unwrap_or((requests, seconds));
+        Quota { capacity: requests, per_second: requests / seconds }
+    }
+
+    // Seconds until the bucket has a token again
+    fn retry_after(&self, tokens: f64) -> u64 {
+        ((1.0 - tokens) / self.per_second).ceil().max(1.0) as u64
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: f64, // unix seconds
+    full_at: f64, // when the bucket has refilled under its own rule's quota
+}
+
+// Where the buckets live. Memory is enough for a single instance,
+// with more than one they have to share the buckets in MongoDB.
+enum RateLimitBackend {
+    Memory(Mutex<HashMap<String, Bucket>>),
+    Mongo(Database),
+}
+
+pub struct RateLimiter {
+    backend: RateLimitBackend,
+}
+
+fn now() -> f64 {
+    Utc::now().timestamp_millis() as f64 / 1000.0
+}
+
+impl RateLimiter {
+    // RATE_LIMIT_BACKEND=memory (default) or mongo
+    pub fn from_env(db: &Database) -> RateLimiter {
+        dotenv().ok();
+        let backend = match env::var("RATE_LIMIT_BACKEND").as_deref() {
+            Ok("mongo") => RateLimitBackend::Mongo(db.clone()),
+            _ => RateLimitBackend::Memory(Mutex::new(HashMap::new())),
+        };
+        RateLimiter { backend }
+    }
+
+    // Takes a token from the bucket of `key`, or says in how many seconds to try again
+    pub async fn take(&self, key: &str, quota: Quota) -> Result<(), u64> {
+        match &self.backend {
+            RateLimitBackend::Memory(buckets) => {
+                let now = now();
+                let mut buckets = buckets.lock().unwrap();
+                // Full buckets carry no information, drop them now and then so the map doesn't grow forever
+                if buckets.len() > 100_000 {
+                    buckets.retain(|_, bucket| bucket.full_at > now);
+                }
+                let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: quota.capacity, updated_at: now, full_at: now });
+                bucket.tokens = (bucket.tokens + (now - bucket.updated_at) * quota.per_second).min(quota.capacity);
+                bucket.updated_at = now;
+                let result = if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    Ok(())
+                } else {
+                    Err(quota.retry_after(bucket.tokens))
+                };
+                bucket.full_at = now + (quota.capacity - bucket.tokens) / quota.per_second;
+                result
+            }
+            RateLimitBackend::Mongo(db) => match take_from_mongo(db, key, quota).await {
+                Ok(result) => result,
+                Err(e) => {
+                    // Better to let a request through than to take the site down with the database
+                    log::error!("rate limiter failed, letting the request through: {}", e);
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+// Refill and take in a single pipeline update, so instances racing for the same bucket can't both get the last token
+async fn take_from_mongo(db: &Database, key: &str, quota: Quota) -> mongodb::error::Result<Result<(), u64>> {
+    let now = now();
+    let full_at = bson::DateTime::from_millis(((now + quota.capacity / quota.per_second) * 1000.0) as i64);
+    let update = vec![
+        doc! {"$set": {
+            "tokens": {"$min": [quota.capacity, {"$add": [
+                {"$ifNull": ["$tokens", quota.capacity]},
+                {"$multiply": [{"$subtract": [now, {"$ifNull": ["$updated_at", now]}]}, quota.per_second]},
+            ]}]},
+            "updated_at": now,
+            "expires_at": full_at, // a full bucket is the same as no bucket, the TTL index removes it
+        }},
+        doc! {"$set": {"allowed": {"$gte": ["$tokens", 1.0]}}},
+        doc! {"$set": {"tokens": {"$cond": ["$allowed", {"$subtract": ["$tokens", 1.0]}, "$tokens"]}}},
+    ];
+    let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+    let bucket = db.collection::<Document>("rate_limits").find_one_and_update(doc! {"_id": key}, update, options).await?;
+
+    Ok(match bucket {
+        Some(bucket) if !bucket.get_bool("allowed").unwrap_or(true) => Err(quota.retry_after(bucket.get_f64("tokens").unwrap_or(0.0))),
+        _ => Ok(()),
+    })
+}
+
+// Limits the requests of a resource: `web::resource("/user/login").wrap(RateLimit::new("login"))`.
+// Signed in users have their own bucket, everyone else shares the bucket of their address.
+pub struct RateLimit {
+    rule: &'static str,
+}
+
+impl RateLimit {
+    pub fn new(rule: &'static str) -> RateLimit {
+        RateLimit { rule }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), rule: self.rule }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    rule: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let rule = self.rule;
+
+        Box::pin(async move {
+            // Without a limiter in the app data there is nothing to enforce
+            let limiter = match req.app_data::<Data<RateLimiter>>() {
+                Some(limiter) => limiter.clone(),
+                None => return service.call(req).await.map(ServiceResponse::map_into_left_body),
+            };
+
+            let user = req
+                .headers()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .and_then(|token| AuthUser::from_token(token.trim()));
+            let key = match (user, client_ip(req.request())) {
+                (Some(user), _) => format!("{}:user:{}", rule, user.id_str()),
+                (None, Some(ip)) => format!("{}:ip:{}", rule, ip),
+                (None, None) => format!("{}:ip:unknown", rule),
+            };
+
+            match limiter.take(&key, Quota::for_rule(rule)).await {
+                Ok(()) => service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(retry_after) => {
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header(("Retry-After", retry_after.to_string()))
+                        .json(json!({"error": "Too many requests, try again later", "retry_after": retry_after}));
+                    Ok(req.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
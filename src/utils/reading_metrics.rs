@@ -0,0 +1,137 @@
+use std::env;
+
+use html2text::from_read_with_decorator;
+use html2text::render::text_renderer::TrivialDecorator;
+use scraper::{Html, Selector};
+
+use crate::types::ReadingMetrics;
+
+// Plain text of the html, without link targets or markup decorations
+pub fn html_to_text(html: &str) -> String {
+    from_read_with_decorator(html.as_bytes(), 80, TrivialDecorator::new())
+}
+
+// The first `words` words of the text, with an ellipsis when it was cut
+pub fn excerpt(html: &str, words: usize) -> String {
+    let text = html_to_text(html);
+    let mut parts = text.split_whitespace();
+    let mut excerpt = parts.by_ref().take(words).collect::<Vec<&str>>().join(" ");
+    if parts.next().is_some() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+// Words per minute for a language, READING_WPM_TR, READING_WPM_EN, ... override the defaults.
+// Turkish words are long (agglutination) so fewer of them are read in a minute.
+fn words_per_minute(language: &str) -> u32 {
+    let default = match language {
+        "tr" => 180,
+        "en" => 230,
+        _ => 200,
+    };
+    env::var(format!("READING_WPM_{}", language.to_uppercase()))
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|wpm| *wpm > 0)
+        .unwrap_or(default)
+}
+
+// Seconds spent looking at the images: 12 for the first one, one less for each next, 3 from the tenth on
+fn image_seconds(image_count: u32) -> u32 {
+    (0..image_count).map(|i| 12u32.saturating_sub(i).max(3)).sum()
+}
+
+fn is_vowel(c: char, language: &str) -> bool {
+    "aeıioöuüâîûAEIİOÖUÜÂÎÛ".contains(c) || (language != "tr" && (c == 'y' || c == 'Y'))
+}
+
+// Turkish is written as it is spoken, every vowel is one syllable.
+// For other languages a run of vowels is counted as one syllable, which is close enough for English.
+fn syllables(word: &str, language: &str) -> u32 {
+    if language == "tr" {
+        return word.chars().filter(|c| is_vowel(*c, language)).count().max(1) as u32;
+    }
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c, language);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    count.max(1)
+}
+
+// Ateşman's formula for Turkish, Flesch reading ease otherwise. Both are 0 (hard) to 100 (easy).
+fn readability(text: &str, language: &str) -> Option<f64> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return None;
+    }
+    let sentences = text
+        .split(['.', '!', '?', '…'])
+        .filter(|sentence| sentence.chars().any(char::is_alphanumeric))
+        .count()
+        .max(1) as f64;
+    let word_count = words.len() as f64;
+    let syllable_count: u32 = words.iter().map(|word| syllables(word, language)).sum();
+
+    let syllables_per_word = syllable_count as f64 / word_count;
+    let words_per_sentence = word_count / sentences;
+    let score = if language == "tr" {
+        198.825 - 40.175 * syllables_per_word - 2.610 * words_per_sentence
+    } else {
+        206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word
+    };
+    Some((score.clamp(0.0, 100.0) * 10.0).round() / 10.0)
+}
+
+// Sanitized html only, there every "<pre" is a real tag
+fn strip_code_blocks(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<pre") {
+        output.push_str(&rest[..start]);
+        rest = match rest[start..].find("</pre>") {
+            Some(end) => &rest[start + end + "</pre>".len()..],
+            None => "",
+        };
+    }
+    output.push_str(rest);
+    output
+}
+
+pub fn reading_metrics(html: &str, language: &str) -> ReadingMetrics {
+    let document = Html::parse_fragment(html);
+    let image_count = document.select(&Selector::parse("img").unwrap()).count() as u32;
+    let code_blocks: Vec<String> = document
+        .select(&Selector::parse("pre").unwrap())
+        .map(|pre| pre.text().collect())
+        .collect();
+
+    let text = html_to_text(html);
+    let word_count = text.split_whitespace().count() as u32;
+    let character_count = text.chars().filter(|c| !c.is_whitespace()).count() as u32;
+    let code_words = code_blocks.iter().map(|code| code.split_whitespace().count() as u32).sum::<u32>().min(word_count);
+    let prose_words = word_count - code_words;
+
+    // Code is read at half the speed of prose
+    let wpm = words_per_minute(language);
+    let seconds = (prose_words * 60 + code_words * 120) / wpm + image_seconds(image_count);
+
+    // Readability is about the prose, leave the code out of it
+    let prose_text = if code_blocks.is_empty() { text } else { html_to_text(&strip_code_blocks(html)) };
+
+    ReadingMetrics {
+        language: language.to_string(),
+        word_count,
+        character_count,
+        image_count,
+        code_block_count: code_blocks.len() as u32,
+        seconds,
+        minutes: seconds.div_ceil(60),
+        readability: readability(&prose_text, language),
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::VecDeque, env, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::Duration};
+
+use actix_web::{rt::time::{interval_at, Instant, Interval}, web::Bytes};
+use chrono::Utc;
+use futures::{future::{select, Either}, Stream};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+pub struct RealtimeEvent {
+    pub id: u64,
+    pub channel: String, // "post:<id>" or "user:<id>"
+    pub event: String,
+    pub data: Value,
+}
+
+impl RealtimeEvent {
+    fn to_sse(&self) -> Bytes {
+        Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", self.id, self.event, self.data))
+    }
+}
+
+// In-process pub/sub: handlers publish to channels, every open stream gets the events of its channel.
+// The last REALTIME_BUFFER (default 1000) events are kept so a client that reconnects with
+// Last-Event-ID gets what it missed.
+pub struct Broker {
+    sender: broadcast::Sender<Arc<RealtimeEvent>>,
+    next_id: AtomicU64,
+    history: Mutex<VecDeque<Arc<RealtimeEvent>>>,
+    history_size: usize,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Broker::new()
+    }
+}
+
+impl Broker {
+    pub fn new() -> Broker {
+        let history_size = env::var("REALTIME_BUFFER").ok().and_then(|value| value.parse().ok()).unwrap_or(1000);
+        let (sender, _) = broadcast::channel(1024);
+        Broker {
+            sender,
+            // Ids keep growing across restarts, an id from before a restart is simply older than the buffer
+            next_id: AtomicU64::new(Utc::now().timestamp_millis() as u64),
+            history: Mutex::new(VecDeque::with_capacity(history_size)),
+            history_size,
+        }
+    }
+
+    pub fn publish(&self, channel: &str, event: &str, data: impl Serialize) {
+        let event = Arc::new(RealtimeEvent {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            channel: channel.to_string(),
+            event: event.to_string(),
+            data: serde_json::to_value(data).unwrap_or(Value::Null),
+        });
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() == self.history_size {
+            history.pop_front();
+        }
+        history.push_back(event.clone());
+        // Sending fails when nobody is listening, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    // A server-sent events stream of `channel`, starting after `last_event_id` when the client is reconnecting
+    pub fn subscribe(&self, channel: String, last_event_id: Option<u64>) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        // Subscribe before reading the history, so nothing published in between is lost
+        let receiver = self.sender.subscribe();
+        let history = self.history.lock().unwrap();
+
+        let mut backlog: VecDeque<Bytes> = VecDeque::new();
+        let retry = env::var("REALTIME_RETRY_MS").ok().and_then(|value| value.parse().ok()).unwrap_or(3000u64);
+        backlog.push_back(Bytes::from(format!("retry: {}\n\n", retry)));
+        let mut last_sent = None;
+        if let Some(last_event_id) = last_event_id {
+            // Events older than the buffer are gone, the client has to fetch again
+            if history.front().map(|oldest| oldest.id > last_event_id + 1).unwrap_or(false) {
+                backlog.push_back(Bytes::from("event: resync\ndata: {}\n\n"));
+            }
+            for event in history.iter().filter(|event| event.id > last_event_id && event.channel == channel) {
+                backlog.push_back(event.to_sse());
+            }
+            last_sent = history.back().map(|event| event.id);
+        }
+
+        let heartbeat_seconds = env::var("REALTIME_HEARTBEAT_SECS").ok().and_then(|value| value.parse().ok()).unwrap_or(15);
+        let period = Duration::from_secs(heartbeat_seconds);
+        let subscription = Subscription {
+            channel,
+            backlog,
+            last_sent,
+            receiver,
+            heartbeat: interval_at(Instant::now() + period, period),
+        };
+        futures::stream::unfold(subscription, |mut subscription| async move {
+            let chunk = subscription.next().await?;
+            Some((Ok(chunk), subscription))
+        })
+    }
+}
+
+struct Subscription {
+    channel: String,
+    backlog: VecDeque<Bytes>,
+    last_sent: Option<u64>, // events up to here were replayed from the history
+    receiver: broadcast::Receiver<Arc<RealtimeEvent>>,
+    heartbeat: Interval,
+}
+
+impl Subscription {
+    async fn next(&mut self) -> Option<Bytes> {
+        if let Some(chunk) = self.backlog.pop_front() {
+            return Some(chunk);
+        }
+        loop {
+            let received = match select(Box::pin(self.receiver.recv()), Box::pin(self.heartbeat.tick())).await {
+                Either::Left((received, _)) => received,
+                // A comment line keeps proxies from closing an idle connection
+                Either::Right(_) => return Some(Bytes::from(": heartbeat\n\n")),
+            };
+            match received {
+                Ok(event) if event.channel == self.channel && Some(event.id) > self.last_sent => return Some(event.to_sse()),
+                Ok(_) => continue,
+                // The stream fell behind and events were dropped, the client has to fetch again
+                Err(RecvError::Lagged(_)) => return Some(Bytes::from("event: resync\ndata: {}\n\n")),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
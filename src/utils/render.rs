@@ -0,0 +1,225 @@
+use std::env;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+use reqwest::Url;
+
+use mongodb::bson::{self, doc, Document};
+use scraper::Html;
+
+use crate::types::{Content, Post, ReadingMetrics, TocEntry};
+use super::{excerpt, reading_metrics};
+
+// Content as it is stored: html rendered/sanitized by the server and everything derived from it
+pub struct PreparedContent {
+    pub content: Content,
+    pub read_time: u32,
+    pub excerpt: String,
+    pub toc: Vec<TocEntry>,
+    pub metrics: ReadingMetrics,
+}
+
+impl PreparedContent {
+    pub fn apply_to(self, post: &mut Post) {
+        post.content = self.content;
+        post.read_time = self.read_time;
+        post.excerpt = self.excerpt;
+        post.toc = self.toc;
+        post.language = self.metrics.language.clone();
+        post.metrics = self.metrics;
+    }
+
+    // The fields to `$set` on a stored post
+    pub fn to_update(&self) -> Document {
+        doc! {
+            "content": bson::to_bson(&self.content).unwrap(),
+            "read_time": self.read_time,
+            "excerpt": &self.excerpt,
+            "toc": bson::to_bson(&self.toc).unwrap(),
+            "language": &self.metrics.language,
+            "metrics": bson::to_bson(&self.metrics).unwrap(),
+        }
+    }
+}
+
+// CommonMark with the GitHub style extensions writers use
+pub fn render_markdown(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let parser = Parser::new_ext(markdown, options);
+    let mut output = String::new();
+    html::push_html(&mut output, parser);
+    output
+}
+
+// Strips everything outside the allowlist and marks external links as nofollow
+pub fn sanitize_html(html: &str) -> String {
+    let clean = Builder::default()
+        .link_rel(None)
+        .add_tag_attributes("div", &["id"]) // footnote definitions
+        .clean(html)
+        .to_string();
+
+    add_external_link_rel(&clean)
+}
+
+// Markdown is the source of truth when the client sends it, the html is only used for html-only posts
+pub fn prepare_content(content: Content, language: &str) -> PreparedContent {
+    let html = if content.markdown.trim().is_empty() {
+        sanitize_html(&content.html)
+    } else {
+        sanitize_html(&render_markdown(&content.markdown))
+    };
+    let (html, toc) = add_heading_anchors(&html);
+    let metrics = reading_metrics(&html, language);
+    let excerpt_words = env::var("EXCERPT_WORDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(40);
+
+    PreparedContent {
+        excerpt: excerpt(&html, excerpt_words),
+        content: Content { html, markdown: content.markdown },
+        read_time: metrics.minutes,
+        toc,
+        metrics,
+    }
+}
+
+// Anchor slug of a heading, Turkish letters are folded so links stay readable ("Giriş" => "giris")
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    // 'İ' lowercases to 'i' plus a combining dot, fold it before that happens
+    for c in text.chars().flat_map(|c| if c == 'İ' { 'i'.to_lowercase() } else { c.to_lowercase() }) {
+        let c = match c {
+            'ç' => 'c',
+            'ğ' => 'g',
+            'ı' => 'i',
+            'ö' => 'o',
+            'ş' => 's',
+            'ü' => 'u',
+            'â' => 'a',
+            'î' => 'i',
+            'û' => 'u',
+            c => c,
+        };
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-').to_string();
+    if slug.is_empty() { "section".to_string() } else { slug }
+}
+
+// Gives every heading of sanitized html an id and collects them as the table of contents.
+// The same heading text always gets the same anchor, repeated ones are numbered ("intro", "intro-2").
+fn add_heading_anchors(html: &str) -> (String, Vec<TocEntry>) {
+    let mut output = String::with_capacity(html.len());
+    let mut toc: Vec<TocEntry> = vec![];
+    let mut rest = html;
+
+    while let Some(start) = find_heading(rest) {
+        let level = rest.as_bytes()[start + 2] - b'0';
+        let tag_end = match find_tag_end(rest, start) {
+            Some(end) => end,
+            None => break,
+        };
+        let close_tag = format!("</h{}>", level);
+        let inner_end = match rest[tag_end..].find(&close_tag) {
+            Some(i) => tag_end + i,
+            None => break,
+        };
+
+        let title = Html::parse_fragment(&rest[tag_end + 1..inner_end])
+            .root_element()
+            .text()
+            .collect::<String>()
+            .trim()
+            .to_string();
+        let base = slugify(&title);
+        let mut anchor = base.clone();
+        let mut n = 1;
+        while toc.iter().any(|entry| entry.anchor == anchor) {
+            n += 1;
+            anchor = format!("{}-{}", base, n);
+        }
+
+        output.push_str(&rest[..tag_end]);
+        output.push_str(&format!(" id=\"{}\"", anchor));
+        toc.push(TocEntry { level, title, anchor });
+        rest = &rest[tag_end..];
+    }
+
+    output.push_str(rest);
+    (output, toc)
+}
+
+fn find_heading(html: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    html.match_indices("<h").map(|(i, _)| i).find(|&i| {
+        matches!(bytes.get(i + 2), Some(b'1'..=b'6')) && matches!(bytes.get(i + 3), Some(b'>') | Some(b' '))
+    })
+}
+
+fn is_external(href: &str) -> bool {
+    let href = href.replace("&amp;", "&");
+    let url = if href.starts_with("//") {
+        Url::parse(&format!("https:{}", href))
+    } else {
+        Url::parse(&href)
+    };
+    match url {
+        // Relative links don't parse on their own and always stay on the site
+        Err(_) => false,
+        Ok(url) => match (url.host_str(), env::var("SITE_HOST")) {
+            (Some(host), Ok(site)) => !host.eq_ignore_ascii_case(&site),
+            (Some(_), Err(_)) => true,
+            (None, _) => false,
+        },
+    }
+}
+
+// Works on sanitized output only: every `<a ` there is a real tag and attribute values are double quoted
+fn add_external_link_rel(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("<a ") {
+        let end = match find_tag_end(rest, start) {
+            Some(end) => end,
+            None => break,
+        };
+
+        let tag = &rest[start..end];
+        output.push_str(&rest[..end]);
+        let href = tag
+            .find("href=\"")
+            .map(|i| &tag[i + 6..])
+            .and_then(|value| value.split('"').next());
+        if href.map(is_external).unwrap_or(false) {
+            output.push_str(" rel=\"nofollow noopener\"");
+        }
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// Index of the '>' closing the tag at `start`, a '>' inside a quoted value doesn't close it
+fn find_tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut in_quotes = false;
+    for (i, c) in html[start..].char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '>' if !in_quotes => return Some(start + i),
+            _ => {}
+        }
+    }
+    None
+}
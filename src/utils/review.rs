@@ -0,0 +1,32 @@
+use std::env;
+
+use chrono::{TimeZone, Utc};
+
+use crate::types::{Permission, Post, PostStatus, ReviewEvent, User};
+
+fn flag(name: &str, default: bool) -> bool {
+    env::var(name).map(|value| value != "false" && value != "0").unwrap_or(default)
+}
+
+// With REVIEW_REQUIRED (default false) posts of guests go through an editor before they are published,
+// and so do posts of authors unless AUTHOR_BYPASS_REVIEW (default true) lets them through
+pub fn needs_review(user: &User) -> bool {
+    if !flag("REVIEW_REQUIRED", false) {
+        return false;
+    }
+    match user.permission {
+        Permission::Editor | Permission::Admin => false,
+        Permission::Author => !flag("AUTHOR_BYPASS_REVIEW", true),
+        Permission::Guest | Permission::Banned => true,
+    }
+}
+
+// Puts `post` in the review queue instead of publishing it. A future `publish_at` is kept
+// in `scheduled_at`, and the post is scheduled for it when it is approved.
+pub fn submit_for_review(post: &mut Post, publish_at: Option<i64>, by: String) {
+    let now = Utc::now();
+    post.scheduled_at = publish_at.and_then(|ts| Utc.timestamp_opt(ts, 0).single()).filter(|at| *at > now);
+    post.published_at = None;
+    post.review_history.push(ReviewEvent::new(post.status.clone(), PostStatus::InReview, by, None));
+    post.status = PostStatus::InReview;
+}
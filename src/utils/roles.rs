@@ -0,0 +1,98 @@
+use std::env;
+
+use chrono::{DateTime, Utc};
+use mongodb::{Database, bson::{self, doc, oid::ObjectId}};
+
+use crate::types::{BanInfo, Permission, RoleChange, User};
+
+// Moves `user` to the `to` permission and records the change. Nothing happens and None is returned
+// when the permission was changed by someone else since `user` was fetched.
+pub async fn change_permission(
+    db: &Database,
+    user: &User,
+    to: Permission,
+    changed_by: &str,
+    reason: Option<String>,
+    ban: Option<BanInfo>,
+) -> mongodb::error::Result<Option<RoleChange>> {
+    let now = Utc::now();
+    let filter = doc! {"_id": user.id, "permission": bson::to_bson(&user.permission)?};
+    let update = doc! {"$set": {"permission": bson::to_bson(&to)?, "ban": bson::to_bson(&ban)?, "updated_at": now.timestamp()}};
+    let result = db.collection::<User>("users").update_one(filter, update, None).await?;
+    if result.matched_count == 0 {
+        return Ok(None);
+    }
+
+    let change = RoleChange {
+        id: ObjectId::new(),
+        user_id: user.id.to_hex(),
+        from: user.permission.clone(),
+        to,
+        changed_by: changed_by.to_string(),
+        reason,
+        created_at: now,
+    };
+    db.collection::<RoleChange>("role_changes").insert_one(&change, None).await?;
+    Ok(Some(change))
+}
+
+pub async fn ban_user(db: &Database, user: &User, banned_by: &str, reason: String, expires_at: Option<DateTime<Utc>>) -> mongodb::error::Result<Option<RoleChange>> {
+    let ban = BanInfo {
+        reason: reason.clone(),
+        banned_by: banned_by.to_string(),
+        banned_at: Utc::now(),
+        expires_at,
+        previous: user.permission.clone(),
+    };
+    change_permission(db, user, Permission::Banned, banned_by, Some(reason), Some(ban)).await
+}
+
+// Gives a banned user their permission from before the ban back
+pub async fn unban_user(db: &Database, user: &User, changed_by: &str, reason: Option<String>) -> mongodb::error::Result<Option<RoleChange>> {
+    let previous = user.ban.as_ref().map(|ban| ban.previous.clone()).unwrap_or(Permission::Guest);
+    change_permission(db, user, previous, changed_by, reason, None).await
+}
+
+// Temporary bans are lifted the first time the user shows up after they expire
+pub async fn lift_expired_ban(db: &Database, mut user: User) -> mongodb::error::Result<User> {
+    if user.permission != Permission::Banned || !user.ban.as_ref().map(BanInfo::expired).unwrap_or(false) {
+        return Ok(user);
+    }
+    if let Some(change) = unban_user(db, &user, "system", Some("Ban expired".to_string())).await? {
+        user.permission = change.to;
+        user.ban = None;
+    }
+    Ok(user)
+}
+
+// Makes the user with `email` an admin, Err when there is no such user or they already are one
+pub async fn promote_admin(db: &Database, email: &str, changed_by: &str) -> mongodb::error::Result<Result<RoleChange, &'static str>> {
+    let user = match db.collection::<User>("users").find_one(doc! {"email": email}, None).await? {
+        Some(user) => user,
+        None => return Ok(Err("No user with this email")),
+    };
+    if user.permission == Permission::Admin {
+        return Ok(Err("User is already an admin"));
+    }
+    match change_permission(db, &user, Permission::Admin, changed_by, Some("Promoted to admin".to_string()), None).await? {
+        Some(change) => Ok(Ok(change)),
+        None => Ok(Err("User's permission changed meanwhile, try again")),
+    }
+}
+
+// Promotes BOOTSTRAP_ADMIN_EMAIL to admin as long as the site has no admin, so the first admin
+// doesn't need database access
+pub async fn bootstrap_admin(db: &Database) -> mongodb::error::Result<()> {
+    let email = match env::var("BOOTSTRAP_ADMIN_EMAIL") {
+        Ok(email) if !email.is_empty() => email,
+        _ => return Ok(()),
+    };
+    if db.collection::<User>("users").count_documents(doc! {"permission": "Admin"}, None).await? > 0 {
+        return Ok(());
+    }
+    match promote_admin(db, &email, "bootstrap").await? {
+        Ok(_) => println!("{} is now an admin", email),
+        Err(reason) => println!("could not bootstrap admin {}: {}", email, reason),
+    }
+    Ok(())
+}
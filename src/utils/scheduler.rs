@@ -0,0 +1,64 @@
+use std::{env, time::Duration};
+
+use actix_web::web::Data;
+use chrono::{TimeZone, Utc};
+use mongodb::{Database, bson::doc, options::{FindOneAndUpdateOptions, ReturnDocument}};
+
+use crate::types::{Post, PostStatus};
+use super::publish::on_post_published;
+use super::realtime::Broker;
+
+// Publishes scheduled posts whose time has come.
+// Runs every PUBLISH_SCHEDULER_INTERVAL seconds (default 30).
+pub fn spawn_publish_scheduler(db: Database, broker: Data<Broker>) {
+    let seconds = env::var("PUBLISH_SCHEDULER_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(seconds));
+        loop {
+            interval.tick().await;
+            if let Err(e) = publish_due_posts(&db, &broker).await {
+                println!("publish scheduler error: {}", e);
+            }
+        }
+    });
+}
+
+async fn publish_due_posts(db: &Database, broker: &Data<Broker>) -> mongodb::error::Result<()> {
+    let collection = db.collection::<Post>("posts");
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    // Claim posts one at a time so that two running instances never publish the same post twice
+    loop {
+        let now = Utc::now().timestamp();
+        let filter = doc! {"status": "Scheduled", "scheduled_at": {"$lte": now}};
+        let update = doc! {"$set": {"status": "Public", "published_at": now}};
+
+        match collection.find_one_and_update(filter, update, options.clone()).await? {
+            Some(post) => on_post_published(db, broker, &post).await?,
+            None => return Ok(()),
+        }
+    }
+}
+
+// Marks `post` as scheduled if `publish_at` is in the future, otherwise as public
+pub fn schedule_or_publish(post: &mut Post, publish_at: Option<i64>) {
+    let now = Utc::now();
+    match publish_at.and_then(|ts| Utc.timestamp_opt(ts, 0).single()) {
+        Some(at) if at > now => {
+            post.status = PostStatus::Scheduled;
+            post.scheduled_at = Some(at);
+            post.published_at = None;
+        }
+        _ => {
+            post.status = PostStatus::Public;
+            post.scheduled_at = None;
+            post.published_at = Some(now);
+        }
+    }
+}